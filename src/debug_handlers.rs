@@ -1 +1 @@
-pub use crate::handlers::debug_handlers::{TranslationHelper, DebugStream};
+pub use crate::handlers::debug_handlers::{TranslationHelper, DebugStream, RecordingOutput};
@@ -0,0 +1,159 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::{KeyCode, KeyCodeInfo};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::{USBKeyOut, MODIFIER_KEYS};
+use core::convert::TryInto;
+use no_std_compat::prelude::v1::*;
+
+/// Firmware side key repeat for hosts/setups without one.
+///
+/// While one of keys is held, it's released and pressed again
+/// after delay_ms, and then every interval_ms - as accumulated
+/// from TimeOut events, so those need to be send regularly.
+/// At most one repeat happens per TimeOut.
+///
+/// Only the most recently pressed key repeats,
+/// pressing any other key stops the repeat.
+///
+/// Needs to be added before the USBKeyboard, which sends the
+/// key down again.
+pub struct AutoRepeat {
+    keys: Vec<u32>,
+    delay_ms: u16,
+    interval_ms: u16,
+    held: Option<u32>,
+    held_ms: u16,
+    next_repeat_ms: u16,
+}
+impl AutoRepeat {
+    pub fn new(keys: &[u32], delay_ms: u16, interval_ms: u16) -> AutoRepeat {
+        AutoRepeat {
+            keys: keys.to_vec(),
+            delay_ms,
+            interval_ms,
+            held: None,
+            held_ms: 0,
+            next_repeat_ms: 0,
+        }
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for AutoRepeat {
+    fn describe(&self) -> String {
+        format!(
+            "AutoRepeat({} keys, delay_ms: {}, interval_ms: {})",
+            self.keys.len(),
+            self.delay_ms,
+            self.interval_ms
+        )
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        let mut repeat = false;
+        for (event, _status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.flag & 0x1 == 0 {
+                        //a fresh key press
+                        if self.keys.contains(&kc.keycode) {
+                            self.held = Some(kc.keycode);
+                            self.held_ms = 0;
+                            self.next_repeat_ms = self.delay_ms;
+                        } else {
+                            self.held = None;
+                        }
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if self.held == Some(kc.keycode) {
+                        self.held = None;
+                    }
+                }
+                Event::TimeOut(ms_since_last) => {
+                    if self.held.is_some() {
+                        self.held_ms = self.held_ms.saturating_add(*ms_since_last);
+                        if self.held_ms >= self.next_repeat_ms {
+                            repeat = true;
+                            while self.next_repeat_ms <= self.held_ms
+                                && self.next_repeat_ms < u16::MAX
+                            {
+                                self.next_repeat_ms =
+                                    self.next_repeat_ms.saturating_add(self.interval_ms.max(1));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if repeat {
+            if let Some(held) = self.held {
+                //release just the repeating key,
+                //the USBKeyboard presses it again
+                let mut report: Vec<KeyCode> = Vec::new();
+                for (event, _status) in iter_unhandled_mut(events) {
+                    if let Event::KeyPress(kc) = event {
+                        if kc.keycode != held && kc.keycode.is_usb_keycode() {
+                            let oc: Result<KeyCode, _> = kc.keycode.try_into();
+                            if let Ok(key) = oc {
+                                report.push(key);
+                            }
+                        }
+                    }
+                }
+                for (modifier, left, right) in MODIFIER_KEYS.iter() {
+                    if output.state().modifier(*modifier)
+                        && !report.contains(left)
+                        && !report.contains(right)
+                    {
+                        report.push(*left);
+                    }
+                }
+                output.send_keys(&report);
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{AutoRepeat, USBKeyboard};
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Keyboard;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_autorepeat() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(AutoRepeat::new(&[A.to_u32()], 500, 100)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.tc(200, &[&[A]]);
+        keyboard.tc(200, &[&[A]]);
+        //past the delay
+        keyboard.tc(200, &[&[], &[A]]);
+        keyboard.tc(50, &[&[A]]);
+        keyboard.tc(50, &[&[], &[A]]);
+        keyboard.tc(100, &[&[], &[A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.tc(1000, &[&[]]);
+
+        //other keys stay down
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.pc(A, &[&[LShift, A]]);
+        keyboard.tc(500, &[&[LShift], &[LShift, A]]);
+        keyboard.rc(A, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[]]);
+
+        //not a repeating key
+        keyboard.pc(B, &[&[B]]);
+        keyboard.tc(1000, &[&[B]]);
+        keyboard.rc(B, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+}
@@ -5,14 +5,55 @@ use crate::USBKeyOut;
 use core::convert::TryInto;
 use no_std_compat::prelude::v1::*;
 
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::Kb1,
+    KeyCode::Kb2,
+    KeyCode::Kb3,
+    KeyCode::Kb4,
+    KeyCode::Kb5,
+    KeyCode::Kb6,
+    KeyCode::Kb7,
+    KeyCode::Kb8,
+    KeyCode::Kb9,
+    KeyCode::Kb0,
+];
+/// the keys with a (US layout) shifted character
+const SPECIAL: [KeyCode; 12] = [
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::LBracket,
+    KeyCode::RBracket,
+    KeyCode::BSlash,
+    KeyCode::NonUsHash,
+    KeyCode::SColon,
+    KeyCode::Quote,
+    KeyCode::Grave,
+    KeyCode::Comma,
+    KeyCode::Dot,
+    KeyCode::Slash,
+];
+
 /// Shift keys if they're pressend beyond threshold_ms
 /// supposedly for RSI sufferers - this implementation has
 /// not been used in daily usage yet.
+///
+/// Use with_exceptions to never autoshift some keys.
+///
+/// The hold time is summed up from the ms_since_last of the events
+/// after the press, saturating at u16::MAX (about 65 seconds).
+///
+/// Use with_hold_from_timeouts to only count the TimeOut events
+/// while the key is down instead - more accurate if your matrix
+/// sends them regularly, since it's independent of the timing of
+/// the surrounding key strokes.
 pub struct AutoShift {
     shift_letters: bool,
     shift_numbers: bool,
     shift_special: bool,
     threshold_ms: u16,
+    exceptions: Vec<u32>,
+    hold_from_timeouts: bool,
+    timeout_held: Vec<(u8, u16)>, // running_number of the press, TimeOut ms since
 }
 
 impl AutoShift {
@@ -22,36 +63,74 @@ impl AutoShift {
             shift_numbers: true,
             shift_special: true,
             threshold_ms,
+            exceptions: Vec::new(),
+            hold_from_timeouts: false,
+            timeout_held: Vec::new(),
+        }
+    }
+    pub fn with_exceptions(threshold_ms: u16, exceptions: Vec<u32>) -> AutoShift {
+        AutoShift {
+            exceptions,
+            ..AutoShift::new(threshold_ms)
+        }
+    }
+    pub fn with_hold_from_timeouts(self) -> AutoShift {
+        AutoShift {
+            hold_from_timeouts: true,
+            ..self
         }
     }
     fn should_autoshift(&self, keycode: u32) -> bool {
+        if self.exceptions.contains(&keycode) {
+            return false;
+        }
         (self.shift_letters && keycode >= KeyCode::A.to_u32() && keycode <= KeyCode::Z.to_u32())
-            | (self.shift_numbers
-                && keycode >= KeyCode::Kb1.to_u32()
-                && keycode <= KeyCode::Kb0.to_u32())
-            | (self.shift_special
-                && keycode >= KeyCode::Minus.to_u32()
-                && keycode <= KeyCode::Slash.to_u32())
+            | (self.shift_numbers && DIGITS.iter().any(|k| k.to_u32() == keycode))
+            | (self.shift_special && SPECIAL.iter().any(|k| k.to_u32() == keycode))
     }
 }
 impl<T: USBKeyOut> ProcessKeys<T> for AutoShift {
+    fn describe(&self) -> String {
+        format!("AutoShift(threshold_ms: {})", self.threshold_ms)
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
-        let mut presses = Vec::new();
+        let mut presses: Vec<(u32, u16, u8)> = Vec::new(); // keycode, ms held so far, running_number
         let mut handled = Vec::new();
         for (event, status) in iter_unhandled_mut(events) {
+            let ms_since_last = match event {
+                Event::KeyPress(kc) | Event::KeyRelease(kc) => kc.ms_since_last,
+                Event::TimeOut(ms) => *ms,
+            };
+            for (_keycode, held_ms, _running_number) in presses.iter_mut() {
+                *held_ms = held_ms.saturating_add(ms_since_last);
+            }
             match event {
                 Event::KeyPress(kc) => {
                     if self.should_autoshift(kc.keycode) {
                         *status = EventStatus::Ignored;
-                        presses.push((kc.keycode, kc.ms_since_last));
+                        presses.push((kc.keycode, 0, kc.running_number));
+                        if self.hold_from_timeouts
+                            && !self.timeout_held.iter().any(|(rn, _)| *rn == kc.running_number)
+                        {
+                            self.timeout_held.push((kc.running_number, 0));
+                        }
                     }
                 }
                 Event::KeyRelease(kc) => {
                     if self.should_autoshift(kc.keycode) {
-                        for (other_keycode, timestamp) in presses.iter() {
+                        for (other_keycode, held_ms, running_number) in presses.iter() {
                             if *other_keycode == kc.keycode {
-                                let delta = kc.ms_since_last - timestamp;
-                                if delta >= self.threshold_ms {
+                                let held_ms = if self.hold_from_timeouts {
+                                    self.timeout_held
+                                        .iter()
+                                        .find(|(rn, _)| rn == running_number)
+                                        .map(|(_, ms)| *ms)
+                                        .unwrap_or(0)
+                                } else {
+                                    *held_ms
+                                };
+                                self.timeout_held.retain(|(rn, _)| rn != running_number);
+                                if held_ms >= self.threshold_ms {
                                     output.send_keys(&[
                                         KeyCode::LShift,
                                         (kc.keycode as u8).try_into().unwrap(),
@@ -65,7 +144,14 @@ impl<T: USBKeyOut> ProcessKeys<T> for AutoShift {
                         *status = EventStatus::Handled;
                     }
                 }
-                _ => {}
+                Event::TimeOut(ms) => {
+                    //only those whose press we have seen (this pass) are down
+                    for (running_number, held_ms) in self.timeout_held.iter_mut() {
+                        if presses.iter().any(|(_, _, rn)| rn == running_number) {
+                            *held_ms = held_ms.saturating_add(*ms);
+                        }
+                    }
+                }
             }
         }
         if !handled.is_empty() {
@@ -77,6 +163,17 @@ impl<T: USBKeyOut> ProcessKeys<T> for AutoShift {
                 }
             }
         }
+        //forget presses that left the stream without us seeing the release,
+        //a running_number reused after wrapping must start from zero
+        if !self.timeout_held.is_empty() {
+            self.timeout_held.retain(|(rn, _)| {
+                events.iter().any(|(event, status)| match (event, status) {
+                    (_, EventStatus::Handled) => false,
+                    (Event::KeyPress(kc), _) => kc.running_number == *rn,
+                    _ => false,
+                })
+            });
+        }
     HandlerResult::NoOp
     }
 }
@@ -123,6 +220,105 @@ mod tests {
         keyboard.output.clear();
     }
     #[test]
+    fn test_autoshift_pathological_timings() {
+        let threshold = 200;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(AutoShift::new(threshold)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //release 'earlier' than the press
+        keyboard.add_keypress(KeyCode::X, 500);
+        keyboard.add_keyrelease(KeyCode::X, 10);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::X], &[]]);
+        keyboard.output.clear();
+        //held beyond u16::MAX
+        keyboard.add_keypress(KeyCode::X, 0);
+        keyboard.add_timeout(60000);
+        keyboard.add_keyrelease(KeyCode::X, 60000);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::X, KeyCode::LShift], &[]]);
+        keyboard.output.clear();
+        //held over other events
+        keyboard.add_keypress(KeyCode::X, 0);
+        keyboard.add_keypress(KeyCode::Enter, 150);
+        keyboard.add_keyrelease(KeyCode::Enter, 10);
+        keyboard.add_keyrelease(KeyCode::X, 100);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::X, KeyCode::LShift], &[KeyCode::Enter]]);
+        assert!(keyboard.events.is_empty());
+    }
+    #[test]
+    fn test_autoshift_hold_from_timeouts() {
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let threshold = 100;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(AutoShift::new(threshold).with_hold_from_timeouts()));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //the TimeOuts add up past the threshold, the release timing does not matter
+        keyboard.pc(X, &[&[]]);
+        keyboard.tc(40, &[&[]]);
+        keyboard.tc(40, &[&[]]);
+        keyboard.tc(40, &[&[]]);
+        keyboard.rct(X, 0, &[&[X, LShift], &[]]);
+        //a long gap in ms_since_last alone does not shift
+        keyboard.pc(X, &[&[]]);
+        keyboard.tc(40, &[&[]]);
+        keyboard.rct(X, 500, &[&[X], &[]]);
+        //TimeOuts before the press don't count
+        keyboard.add_timeout(200);
+        keyboard.add_keypress(X, 0);
+        keyboard.add_timeout(50);
+        keyboard.add_keyrelease(X, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[X], &[]]);
+        assert!(keyboard.events.is_empty());
+    }
+    #[test]
+    fn test_autoshift_hold_from_timeouts_lost_release() {
+        use crate::handlers::HandlerResult;
+        use crate::iter_unhandled_mut;
+        use crate::premade::FnHandler;
+        use crate::test_helpers::Checks;
+        use spin::RwLock;
+        use alloc::sync::Arc;
+        use KeyCode::*;
+        let swallow = Arc::new(RwLock::new(false));
+        let swallow2 = swallow.clone();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(FnHandler::new(
+            move |events: &mut Vec<(Event, EventStatus)>, _output: &mut dyn USBKeyOut| {
+                for (e, status) in iter_unhandled_mut(events) {
+                    if let Event::KeyRelease(_) = e {
+                        if *swallow2.read() {
+                            *status = EventStatus::Handled;
+                        }
+                    }
+                }
+                HandlerResult::NoOp
+            },
+        )));
+        keyboard.add_handler(Box::new(AutoShift::new(100).with_hold_from_timeouts()));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //the release never reaches the AutoShift, and the press leaves the stream
+        let running_number = keyboard.running_number;
+        keyboard.pc(X, &[&[]]);
+        keyboard.tc(80, &[&[]]);
+        *swallow.write() = true;
+        keyboard.add_keyrelease(X, 0);
+        keyboard.handle_keys().unwrap();
+        *swallow.write() = false;
+        keyboard.events.clear();
+        keyboard.output.clear();
+        keyboard.tc(0, &[&[]]);
+        //once the running_number wraps around, the old hold time is not inherited
+        keyboard.running_number = running_number;
+        keyboard.pc(X, &[&[]]);
+        keyboard.tc(40, &[&[]]);
+        keyboard.rc(X, &[&[X], &[]]);
+        assert!(keyboard.events.is_empty());
+    }
+    #[test]
     fn test_autoshift_no_letters() {
         let threshold = 200;
         let mut l = AutoShift::new(threshold);
@@ -155,4 +351,51 @@ mod tests {
         check_output(&keyboard, &[&[KeyCode::X]]);
         keyboard.output.clear()
     }
+    #[test]
+    fn test_autoshift_exceptions() {
+        let threshold = 200;
+        let l = AutoShift::with_exceptions(threshold, vec![KeyCode::A.to_u32()]);
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(KeyCode::A, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::A]]);
+        keyboard.output.clear();
+        keyboard.add_keyrelease(KeyCode::A, threshold + 1);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[]]);
+        keyboard.output.clear();
+
+        keyboard.add_keypress(KeyCode::B, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[]]);
+        keyboard.output.clear();
+        keyboard.add_keyrelease(KeyCode::B, threshold + 1);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::B, KeyCode::LShift], &[]]);
+        assert!(keyboard.events.is_empty());
+    }
+    #[test]
+    fn test_should_autoshift() {
+        use crate::handlers::autoshift::DIGITS;
+        let l = AutoShift::new(200);
+        for key in DIGITS.iter() {
+            assert!(l.should_autoshift(key.to_u32()));
+        }
+        let cases = [
+            (KeyCode::Kb0, true),
+            (KeyCode::Kb9, true),
+            (KeyCode::A, true),
+            (KeyCode::Minus, true),
+            (KeyCode::Slash, true),
+            (KeyCode::Enter, false),
+            (KeyCode::Tab, false),
+            (KeyCode::Space, false),
+            (KeyCode::CapsLock, false),
+        ];
+        for (key, should) in cases.iter() {
+            assert!(l.should_autoshift(key.to_u32()) == *should);
+        }
+    }
 }
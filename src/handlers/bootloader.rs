@@ -0,0 +1,187 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::describe_keycode;
+use crate::key_stream::{Event, EventStatus};
+use crate::USBKeyOut;
+use no_std_compat::prelude::v1::*;
+
+/// Enter the bootloader (output.bootloader()) - but only
+/// if all keys of a chord (e.g. both Fn keys plus B) are down at the same time,
+/// so it's not triggered by accident.
+///
+/// A key counts as down from it's press until it's release, across passes,
+/// so the chord keys may be pressed in separate scans and be
+/// consumed by later handlers. The chord fires once all of them are down
+/// at the end of a pass. Triggers once per chord - release any of the keys
+/// to arm it again.
+///
+/// The key events themselves are left alone, add this before
+/// the handlers that consume them (e.g. Layers).
+pub struct BootloaderChord {
+    keys: Vec<u32>,
+    down: Vec<(u32, u8)>, // chord keycode, running_number of it's press
+    fired: bool,
+}
+
+impl BootloaderChord {
+    pub fn new(keys: &[u32]) -> BootloaderChord {
+        if keys.len() < 2 {
+            panic!("BootloaderChord needs at least two keys");
+        }
+        BootloaderChord {
+            keys: keys.to_vec(),
+            down: Vec::new(),
+            fired: false,
+        }
+    }
+    fn all_down(&self) -> bool {
+        self.keys
+            .iter()
+            .all(|keycode| self.down.iter().any(|(k, _rn)| k == keycode))
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for BootloaderChord {
+    fn describe(&self) -> String {
+        let keys: Vec<String> = self.keys.iter().map(|k| describe_keycode(*k)).collect();
+        format!("BootloaderChord({})", keys.join("+"))
+    }
+    fn reset(&mut self, _output: &mut T) {
+        self.down.clear();
+        self.fired = false;
+    }
+    fn on_disabled(&mut self, _output: &mut T) {
+        //we won't see the releases
+        self.down.clear();
+        self.fired = false;
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        for (event, _status) in events.iter() {
+            match event {
+                Event::KeyPress(kc) => {
+                    if !self.keys.contains(&kc.original_keycode) {
+                        continue;
+                    }
+                    //presses stay in the stream until released - only count them once
+                    if self
+                        .down
+                        .iter()
+                        .any(|(_k, rn)| *rn == kc.running_number)
+                    {
+                        continue;
+                    }
+                    self.down.retain(|(k, _rn)| *k != kc.original_keycode);
+                    self.down.push((kc.original_keycode, kc.running_number));
+                }
+                Event::KeyRelease(kc) => {
+                    if self.keys.contains(&kc.original_keycode) {
+                        self.down.retain(|(k, _rn)| *k != kc.original_keycode);
+                        self.fired = false;
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+        //all of them still down at the end of the pass
+        if !self.fired && self.all_down() {
+            self.fired = true;
+            output.bootloader();
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{BootloaderChord, USBKeyboard};
+    use crate::key_codes::{KeyCode, UserKey};
+    use crate::test_helpers::KeyOutCatcher;
+    use crate::Keyboard;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_bootloader_chord() {
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(BootloaderChord::new(&[
+            UserKey::UK0.to_u32(),
+            UserKey::UK1.to_u32(),
+            KeyCode::B.to_u32(),
+        ])));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(UserKey::UK0, 0);
+        keyboard.add_keypress(UserKey::UK1, 10);
+        keyboard.handle_keys().ok();
+        assert!(!keyboard.output.bootloader_called);
+        keyboard.add_keypress(KeyCode::B, 10);
+        keyboard.handle_keys().ok();
+        assert!(keyboard.output.bootloader_called);
+    }
+
+    #[test]
+    fn test_bootloader_chord_partial() {
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(BootloaderChord::new(&[
+            UserKey::UK0.to_u32(),
+            UserKey::UK1.to_u32(),
+            KeyCode::B.to_u32(),
+        ])));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(UserKey::UK0, 0);
+        keyboard.add_keypress(KeyCode::B, 10);
+        keyboard.handle_keys().ok();
+        assert!(!keyboard.output.bootloader_called);
+        //UK1 is only pressed after B has been released
+        keyboard.add_keyrelease(KeyCode::B, 10);
+        keyboard.handle_keys().ok();
+        keyboard.add_keypress(UserKey::UK1, 10);
+        keyboard.handle_keys().ok();
+        assert!(!keyboard.output.bootloader_called);
+        //and all three within one batch, but B released in between
+        keyboard.add_keypress(KeyCode::B, 10);
+        keyboard.add_keyrelease(KeyCode::B, 10);
+        keyboard.handle_keys().ok();
+        assert!(!keyboard.output.bootloader_called);
+    }
+
+    #[test]
+    fn test_bootloader_chord_consumed_keys() {
+        use crate::handlers::RewriteLayer;
+        use crate::premade;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(BootloaderChord::new(&[
+            UserKey::UK0.to_u32(),
+            UserKey::UK1.to_u32(),
+            KeyCode::B.to_u32(),
+        ])));
+        let layer_id = keyboard.future_handler_id(3);
+        keyboard.add_handler(premade::momentary_layer(UserKey::UK0, layer_id));
+        keyboard.add_handler(premade::momentary_layer(UserKey::UK1, layer_id));
+        assert!(keyboard.add_handler(Box::new(RewriteLayer::new(&[]))) == layer_id);
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //one key per scan, the Fn keys are handled (and drained) right away
+        keyboard.add_keypress(UserKey::UK0, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keypress(UserKey::UK1, 10);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.events.is_empty());
+        assert!(!keyboard.output.bootloader_called);
+        keyboard.add_keypress(KeyCode::B, 10);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.bootloader_called);
+        //only once per chord
+        keyboard.output.bootloader_called = false;
+        keyboard.add_timeout(10);
+        keyboard.handle_keys().unwrap();
+        assert!(!keyboard.output.bootloader_called);
+        //releasing a Fn key re-arms it
+        keyboard.add_keyrelease(UserKey::UK1, 10);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keypress(UserKey::UK1, 10);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.bootloader_called);
+    }
+}
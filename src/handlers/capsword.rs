@@ -0,0 +1,182 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::{KeyCode, KeyCodeInfo};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::USBKeyOut;
+use core::convert::TryFrom;
+use no_std_compat::prelude::v1::*;
+
+/// Caps Word - shift letters until the word ends.
+///
+/// Starts disabled - enable it with a trigger, for example
+/// premade::toggle_handler(trigger, id) or a OneShot.
+///
+/// While enabled, A-Z are send with LShift,
+/// digits, Minus and BSpace are passed on unshifted
+/// (the shift ends when one of them is pressed while the letter is still held).
+/// Any other (usb, non modifier) key press ends the word,
+/// as does not pressing a word key for timeout_ms,
+/// and the handler disables itself (see AutoOff on Layer).
+pub struct CapsWord {
+    timeout_ms: u16,
+    idle_ms: u16,
+    shifted_letter: Option<u32>, // original_keycode of the held letter that gets LShift
+}
+impl CapsWord {
+    pub fn new(timeout_ms: u16) -> CapsWord {
+        CapsWord {
+            timeout_ms,
+            idle_ms: 0,
+            shifted_letter: None,
+        }
+    }
+    fn is_letter(keycode: u32) -> bool {
+        KeyCode::A.to_u32() <= keycode && keycode <= KeyCode::Z.to_u32()
+    }
+    fn is_modifier(keycode: u32) -> bool {
+        match KeyCode::try_from(keycode) {
+            Ok(keycode) => keycode.is_modifier(),
+            Err(_) => false,
+        }
+    }
+    fn continues_word(keycode: u32) -> bool {
+        CapsWord::is_letter(keycode)
+            || (KeyCode::Kb1.to_u32() <= keycode && keycode <= KeyCode::Kb0.to_u32())
+            || keycode == KeyCode::Minus.to_u32()
+            || keycode == KeyCode::BSpace.to_u32()
+            || CapsWord::is_modifier(keycode)
+            || !keycode.is_usb_keycode()
+    }
+}
+impl<T: USBKeyOut> ProcessKeys<T> for CapsWord {
+    fn describe(&self) -> String {
+        format!("CapsWord(timeout_ms: {})", self.timeout_ms)
+    }
+    fn on_disabled(&mut self, _output: &mut T) {
+        self.idle_ms = 0;
+        self.shifted_letter = None;
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        let mut result = HandlerResult::NoOp;
+        let mut fresh_presses: Vec<u32> = Vec::new();
+        for (event, _status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.flag & 0x1 == 0 {
+                        //a fresh key press
+                        fresh_presses.push(kc.original_keycode);
+                        if CapsWord::continues_word(kc.keycode) {
+                            self.idle_ms = 0;
+                        } else {
+                            result = HandlerResult::Disable;
+                        }
+                        if CapsWord::is_letter(kc.keycode) {
+                            self.shifted_letter = Some(kc.original_keycode);
+                        } else if kc.keycode.is_usb_keycode() && !CapsWord::is_modifier(kc.keycode)
+                        {
+                            //digits etc. are not shifted - so the letter no longer is either
+                            self.shifted_letter = None;
+                        }
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    //a press in this very pass is still send
+                    if self.shifted_letter == Some(kc.original_keycode)
+                        && !fresh_presses.contains(&kc.original_keycode)
+                    {
+                        self.shifted_letter = None;
+                    }
+                }
+                Event::TimeOut(ms_since_last) => {
+                    self.idle_ms = self.idle_ms.saturating_add(*ms_since_last);
+                    if self.idle_ms >= self.timeout_ms {
+                        result = HandlerResult::Disable;
+                    }
+                }
+            }
+        }
+        if self.shifted_letter.is_some() {
+            if let HandlerResult::NoOp = result {
+                output.register_key(KeyCode::LShift);
+            }
+        }
+        result
+    }
+    fn default_enabled(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{CapsWord, USBKeyboard};
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::{Keyboard, USBKeyOut};
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_capsword() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let id = keyboard.add_handler(Box::new(CapsWord::new(1000)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        assert!(!keyboard.output.state().is_handler_enabled(id));
+        keyboard.output.state().enable_handler(id);
+        for key in [H, E, L, L, O].iter() {
+            keyboard.pc(*key, &[&[LShift, *key]]);
+            keyboard.rc(*key, &[&[]]);
+        }
+        //digits and minus are not shifted
+        keyboard.pc(Minus, &[&[Minus]]);
+        keyboard.rc(Minus, &[&[]]);
+        keyboard.pc(Kb2, &[&[Kb2]]);
+        keyboard.rc(Kb2, &[&[]]);
+        //not even while a letter is held
+        keyboard.pc(A, &[&[LShift, A]]);
+        keyboard.pc(Kb2, &[&[A, Kb2]]);
+        keyboard.pc(Minus, &[&[A, Kb2, Minus]]);
+        keyboard.rc(Kb2, &[&[A, Minus]]);
+        keyboard.rc(Minus, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(id));
+        //space ends the word
+        keyboard.pc(Space, &[&[Space]]);
+        assert!(!keyboard.output.state().is_handler_enabled(id));
+        keyboard.rc(Space, &[&[]]);
+        for key in [W, O, R, L, D].iter() {
+            keyboard.pc(*key, &[&[*key]]);
+            keyboard.rc(*key, &[&[]]);
+        }
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_capsword_timeout() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let id = keyboard.add_handler(Box::new(CapsWord::new(1000)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().enable_handler(id);
+        keyboard.pc(A, &[&[LShift, A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.tc(600, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(id));
+        keyboard.pc(B, &[&[LShift, B]]);
+        keyboard.rc(B, &[&[]]);
+        keyboard.tc(600, &[&[]]);
+        keyboard.tc(600, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(id));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+
+        //enabled again, the timeout starts anew
+        keyboard.output.state().enable_handler(id);
+        keyboard.tc(600, &[&[]]);
+        keyboard.pc(A, &[&[LShift, A]]);
+        keyboard.rc(A, &[&[]]);
+    }
+}
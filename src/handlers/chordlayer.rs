@@ -0,0 +1,238 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::describe_keycode;
+use crate::key_stream::{Event, EventStatus};
+use crate::{HandlerID, USBKeyOut};
+use no_std_compat::prelude::v1::*;
+
+/// Enable a layer while at least min_keys of keys are held together,
+/// e.g. any two of three thumb keys.
+///
+/// The chord has to be pressed within timeout_ms of it's first key.
+/// Until then the presses are held back (Ignored) - if another key is pressed,
+/// a chord key is released, or the timeout passes, they're passed on
+/// to the following handlers untouched (just like a Combo).
+///
+/// Once enabled, the presses and releases of the chord keys are consumed,
+/// and the layer is disabled again when fewer than min_keys are held.
+///
+/// Add it before the layer it enables.
+pub struct ChordLayer {
+    keys: Vec<u32>,
+    min_keys: u8,
+    layer: HandlerID,
+    timeout_ms: u16,
+    active: bool,
+    pending: Option<u8>,     // running_number of the first held back press
+    pending_timeout_ms: u16, // timeouts seen since then
+    passed_on: Vec<u8>,      // running_numbers of presses we gave up on
+    down: Vec<u32>,          // consumed keys whose release we still need to swallow
+}
+
+impl ChordLayer {
+    pub fn new(keys: &[u32], min_keys: u8, layer: HandlerID, timeout_ms: u16) -> ChordLayer {
+        if min_keys < 2 || min_keys as usize > keys.len() {
+            panic!("ChordLayer needs 2 <= min_keys <= keys.len()");
+        }
+        ChordLayer {
+            keys: keys.to_vec(),
+            min_keys,
+            layer,
+            timeout_ms,
+            active: false,
+            pending: None,
+            pending_timeout_ms: 0,
+            passed_on: Vec::new(),
+            down: Vec::new(),
+        }
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for ChordLayer {
+    fn describe(&self) -> String {
+        let keys: Vec<String> = self.keys.iter().map(|k| describe_keycode(*k)).collect();
+        format!(
+            "ChordLayer({} of {}, layer: {}, timeout_ms: {})",
+            self.min_keys,
+            keys.join("/"),
+            self.layer,
+            self.timeout_ms
+        )
+    }
+    fn on_disabled(&mut self, output: &mut T) {
+        if self.active {
+            output.state().disable_handler(self.layer);
+            self.active = false;
+        }
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        //forget about the presses we gave up on once they're gone
+        self.passed_on.retain(|running_number| {
+            events.iter().any(|(e, _status)| match e {
+                Event::KeyPress(kc) => kc.running_number == *running_number,
+                _ => false,
+            })
+        });
+        let mut found: Vec<(usize, u32)> = Vec::new(); //index, keycode
+        let mut elapsed: u16 = 0;
+        let mut broken = false;
+        for (ii, (event, status)) in events.iter_mut().enumerate() {
+            if !status.is_unhandled() {
+                continue;
+            }
+            match event {
+                Event::KeyPress(kc) => {
+                    if self.active {
+                        if self.keys.contains(&kc.keycode) && !self.down.contains(&kc.keycode) {
+                            *status = EventStatus::Handled;
+                            self.down.push(kc.keycode);
+                        }
+                        continue;
+                    }
+                    if !found.is_empty() {
+                        elapsed = elapsed.saturating_add(kc.ms_since_last);
+                    }
+                    if self.keys.contains(&kc.keycode)
+                        && !self.passed_on.contains(&kc.running_number)
+                        && !broken
+                    {
+                        if found.is_empty() && self.pending != Some(kc.running_number) {
+                            self.pending = Some(kc.running_number);
+                            self.pending_timeout_ms = 0;
+                        }
+                        if found.iter().any(|(_ii, keycode)| *keycode == kc.keycode) {
+                            broken = true;
+                        } else {
+                            found.push((ii, kc.keycode));
+                        }
+                    } else if !found.is_empty() {
+                        broken = true;
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if let Some(pos) = self.down.iter().position(|k| *k == kc.keycode) {
+                        *status = EventStatus::Handled;
+                        self.down.remove(pos);
+                        if self.active && self.down.len() < self.min_keys as usize {
+                            self.active = false;
+                            output.state().disable_handler(self.layer);
+                        }
+                    } else if !found.is_empty() {
+                        elapsed = elapsed.saturating_add(kc.ms_since_last);
+                        if found.iter().any(|(_ii, keycode)| *keycode == kc.keycode) {
+                            broken = true;
+                        }
+                    }
+                }
+                Event::TimeOut(ms_since_last) => {
+                    if !found.is_empty() {
+                        self.pending_timeout_ms =
+                            self.pending_timeout_ms.saturating_add(*ms_since_last);
+                    }
+                }
+            }
+        }
+        if found.is_empty() {
+            self.pending = None;
+        } else if broken || elapsed.saturating_add(self.pending_timeout_ms) > self.timeout_ms {
+            //give up, let the presses through
+            self.pending = None;
+            for (ii, _keycode) in found.iter() {
+                if let Event::KeyPress(kc) = &events[*ii].0 {
+                    self.passed_on.push(kc.running_number);
+                }
+            }
+        } else if found.len() >= self.min_keys as usize {
+            self.pending = None;
+            for (ii, keycode) in found.iter() {
+                events[*ii].1 = EventStatus::Handled;
+                self.down.push(*keycode);
+            }
+            self.active = true;
+            output.state().enable_handler(self.layer);
+        } else {
+            //hold them back for now
+            for (ii, _keycode) in found.iter() {
+                events[*ii].1 = EventStatus::Ignored;
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{ChordLayer, RewriteLayer, USBKeyboard};
+    use crate::key_codes::KeyCode;
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::{Keyboard, USBKeyOut};
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    const MAP: &[(u32, u32)] = &[(KeyCode::A.to_u32(), KeyCode::X.to_u32())];
+
+    #[test]
+    fn test_chordlayer_activate() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.future_handler_id(2);
+        keyboard.add_handler(Box::new(ChordLayer::new(
+            &[KeyCode::Space.to_u32(), KeyCode::Enter.to_u32(), KeyCode::BSpace.to_u32()],
+            2,
+            layer_id,
+            50,
+        )));
+        assert!(keyboard.add_handler(Box::new(RewriteLayer::new(MAP))) == layer_id);
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(Space, &[&[]]);
+        keyboard.pct(BSpace, 10, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(layer_id));
+        keyboard.pc(A, &[&[X]]);
+        keyboard.rc(A, &[&[]]);
+        //the third key is swallowed as well
+        keyboard.pc(Enter, &[&[]]);
+        keyboard.rc(Enter, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(layer_id));
+
+        //below the threshold - layer off, remaining release swallowed
+        keyboard.rc(Space, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.rc(BSpace, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_chordlayer_single_presses() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.future_handler_id(2);
+        keyboard.add_handler(Box::new(ChordLayer::new(
+            &[KeyCode::Space.to_u32(), KeyCode::Enter.to_u32(), KeyCode::BSpace.to_u32()],
+            2,
+            layer_id,
+            50,
+        )));
+        assert!(keyboard.add_handler(Box::new(RewriteLayer::new(MAP))) == layer_id);
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //tap
+        keyboard.pc(Space, &[&[]]);
+        keyboard.rct(Space, 10, &[&[Space]]);
+        keyboard.tc(10, &[&[]]);
+        //timeout
+        keyboard.pc(Enter, &[&[]]);
+        keyboard.tc(60, &[&[Enter]]);
+        keyboard.rc(Enter, &[&[]]);
+        //another key
+        keyboard.pc(Space, &[&[]]);
+        keyboard.pct(A, 10, &[&[Space, A]]);
+        keyboard.rc(Space, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+        assert!(keyboard.events.is_empty());
+    }
+}
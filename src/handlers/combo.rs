@@ -0,0 +1,229 @@
+use crate::handlers::{Action, HandlerResult, ProcessKeys};
+use crate::key_codes::describe_keycode;
+use crate::key_stream::{Event, EventStatus};
+use crate::USBKeyOut;
+use no_std_compat::prelude::v1::*;
+
+/// A combo: press all of keys together (within timeout_ms of the first one)
+/// to trigger an action, e.g. J+K for Escape.
+///
+/// While the combo is incomplete the presses are held back (Ignored).
+/// If another key is pressed, a combo key is released, or the timeout
+/// passes, they're passed on to the following handlers (e.g. the USBKeyboard)
+/// in their original order.
+///
+/// Once triggered, the presses and releases of the combo keys are consumed.
+///
+/// Combos may share keys - a combo that gives up passes it's presses on
+/// to the next Combo in the handler list.
+pub struct Combo<M> {
+    keys: Vec<u32>,
+    action: M,
+    timeout_ms: u16,
+    pending: Option<u8>,      // running_number of the first held back press
+    pending_timeout_ms: u16,  // timeouts seen since then
+    passed_on: Vec<u8>,       // running_numbers of presses we gave up on
+    fired: Vec<u32>,          // keys whose release we still need to swallow
+}
+
+impl<M: Action> Combo<M> {
+    pub fn new(keys: &[u32], action: M, timeout_ms: u16) -> Combo<M> {
+        Combo {
+            keys: keys.to_vec(),
+            action,
+            timeout_ms,
+            pending: None,
+            pending_timeout_ms: 0,
+            passed_on: Vec::new(),
+            fired: Vec::new(),
+        }
+    }
+}
+
+impl<T: USBKeyOut, M: Action> ProcessKeys<T> for Combo<M> {
+    fn describe(&self) -> String {
+        let keys: Vec<String> = self.keys.iter().map(|k| describe_keycode(*k)).collect();
+        format!("Combo({}, timeout_ms: {})", keys.join("+"), self.timeout_ms)
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        //forget about the presses we gave up on once they're gone
+        self.passed_on.retain(|running_number| {
+            events.iter().any(|(e, _status)| match e {
+                Event::KeyPress(kc) => kc.running_number == *running_number,
+                _ => false,
+            })
+        });
+
+        let mut found: Vec<(usize, u8)> = Vec::new(); //index, running_number
+        let mut found_keys: Vec<u32> = Vec::new();
+        let mut elapsed: u16 = 0;
+        let mut broken = false;
+        for (ii, (event, status)) in events.iter_mut().enumerate() {
+            if !status.is_unhandled() {
+                continue;
+            }
+            match event {
+                Event::KeyPress(kc) => {
+                    if !found.is_empty() {
+                        elapsed = elapsed.saturating_add(kc.ms_since_last);
+                    }
+                    if self.keys.contains(&kc.keycode)
+                        && !self.passed_on.contains(&kc.running_number)
+                        && !broken
+                    {
+                        if found.is_empty() && self.pending != Some(kc.running_number) {
+                            self.pending = Some(kc.running_number);
+                            self.pending_timeout_ms = 0;
+                        }
+                        if found_keys.contains(&kc.keycode) {
+                            broken = true;
+                        } else {
+                            found.push((ii, kc.running_number));
+                            found_keys.push(kc.keycode);
+                        }
+                    } else if !found.is_empty() {
+                        broken = true;
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if let Some(pos) = self.fired.iter().position(|k| *k == kc.keycode) {
+                        *status = EventStatus::Handled;
+                        self.fired.remove(pos);
+                    } else if !found.is_empty() {
+                        elapsed = elapsed.saturating_add(kc.ms_since_last);
+                        if found_keys.contains(&kc.keycode) {
+                            broken = true;
+                        }
+                    }
+                }
+                Event::TimeOut(ms_since_last) => {
+                    if !found.is_empty() {
+                        self.pending_timeout_ms = self.pending_timeout_ms.saturating_add(*ms_since_last);
+                    }
+                }
+            }
+        }
+        if found.is_empty() {
+            self.pending = None;
+        } else if broken || elapsed.saturating_add(self.pending_timeout_ms) > self.timeout_ms {
+            //give up, let the presses through
+            self.pending = None;
+            for (_ii, running_number) in found.iter() {
+                self.passed_on.push(*running_number);
+            }
+        } else if found.len() == self.keys.len() {
+            self.pending = None;
+            for (ii, _running_number) in found.iter() {
+                events[*ii].1 = EventStatus::Handled;
+            }
+            self.fired.extend(found_keys);
+            self.action.on_trigger(output);
+        } else {
+            //hold them back for now
+            for (ii, _running_number) in found.iter() {
+                events[*ii].1 = EventStatus::Ignored;
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{Combo, USBKeyboard};
+    use crate::key_codes::KeyCode;
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Keyboard;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_combo() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(Combo::new(
+            &[J.to_u32(), K.to_u32()],
+            KeyCode::Escape,
+            50,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(J, &[&[]]);
+        keyboard.pct(K, 10, &[&[Escape]]);
+        keyboard.rc(J, &[&[]]);
+        keyboard.rc(K, &[&[]]);
+        assert!(keyboard.events.is_empty());
+
+        //order does not matter
+        keyboard.pc(K, &[&[]]);
+        keyboard.pct(J, 10, &[&[Escape]]);
+        keyboard.rc(K, &[&[]]);
+        keyboard.rc(J, &[&[]]);
+
+        //other keys are untouched
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+    }
+
+    #[test]
+    fn test_combo_partial() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(Combo::new(
+            &[J.to_u32(), K.to_u32()],
+            KeyCode::Escape,
+            50,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //timed out
+        keyboard.pc(J, &[&[]]);
+        keyboard.tc(60, &[&[J]]);
+        //K might start a combo on it's own
+        keyboard.pct(K, 10, &[&[J]]);
+        keyboard.tc(60, &[&[J, K]]);
+        keyboard.rc(J, &[&[K]]);
+        keyboard.rc(K, &[&[]]);
+
+        //released before completion
+        keyboard.pc(J, &[&[]]);
+        keyboard.rct(J, 10, &[&[J]]);
+        keyboard.tc(10, &[&[]]);
+
+        //another key
+        keyboard.pc(J, &[&[]]);
+        keyboard.pct(A, 10, &[&[J, A]]);
+        keyboard.rc(J, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_combo_overlapping() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(Combo::new(
+            &[J.to_u32(), K.to_u32()],
+            KeyCode::Escape,
+            50,
+        )));
+        keyboard.add_handler(Box::new(Combo::new(
+            &[K.to_u32(), L.to_u32()],
+            KeyCode::Tab,
+            50,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(K, &[&[]]);
+        keyboard.pct(L, 10, &[&[Tab]]);
+        keyboard.rc(K, &[&[]]);
+        keyboard.rc(L, &[&[]]);
+
+        keyboard.pc(J, &[&[]]);
+        keyboard.pct(K, 10, &[&[Escape]]);
+        keyboard.rc(J, &[&[]]);
+        keyboard.rc(K, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+}
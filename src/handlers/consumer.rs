@@ -0,0 +1,141 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::{describe_keycode, KeyCode};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::USBKeyOut;
+use no_std_compat::prelude::v1::*;
+
+/// KeyCode::Media* to their USB HID consumer page (0x0C) usage ids
+pub const CONSUMER_USAGES: &[(KeyCode, u16)] = &[
+    (KeyCode::MediaPlayPause, 0xCD),
+    (KeyCode::MediaStopCd, 0xB7),
+    (KeyCode::MediaPrevioussong, 0xB6),
+    (KeyCode::MediaNextsong, 0xB5),
+    (KeyCode::MediaEjectCd, 0xB8),
+    (KeyCode::MediaVolumeUp, 0xE9),
+    (KeyCode::MediaVolumeDown, 0xEA),
+    (KeyCode::MediaMUte, 0xE2),
+    (KeyCode::MediaWww, 0x196),        // AL Internet Browser
+    (KeyCode::MediaBack, 0x224),       // AC Back
+    (KeyCode::MediaForward, 0x225),    // AC Forward
+    (KeyCode::MediaStop, 0x226),       // AC Stop
+    (KeyCode::MediaFind, 0x221),       // AC Search
+    (KeyCode::MediaScrollUp, 0x233),   // AC Scroll Up
+    (KeyCode::MediaScrollDown, 0x234), // AC Scroll Down
+    (KeyCode::MediaEdit, 0x185),       // AL Text Editor
+    (KeyCode::MediaSleep, 0x32),
+    (KeyCode::MediaCoffee, 0x19E),     // AL Terminal Lock/Screensaver
+    (KeyCode::MediaRefresh, 0x227),    // AC Refresh
+    (KeyCode::MediaCalc, 0x192),       // AL Calculator
+];
+
+/// the consumer usage id for a KeyCode::Media* keycode
+pub fn consumer_usage(keycode: u32) -> Option<u16> {
+    CONSUMER_USAGES
+        .iter()
+        .find(|(k, _usage)| k.to_u32() == keycode)
+        .map(|(_k, usage)| *usage)
+}
+
+/// Send the KeyCode::Media* keys as consumer control reports
+/// (USBKeyOut::send_consumer) - USBKeyboard only handles
+/// the keycodes up to RGui and would drop them.
+///
+/// The usage is send on press, and 0 once the last
+/// held media key is released.
+///
+/// Add it before the USBKeyboard.
+#[derive(Default)]
+pub struct ConsumerControl {
+    held: Vec<u32>,
+}
+impl ConsumerControl {
+    pub fn new() -> ConsumerControl {
+        ConsumerControl { held: Vec::new() }
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for ConsumerControl {
+    fn describe(&self) -> String {
+        let held: Vec<String> = self.held.iter().map(|k| describe_keycode(*k)).collect();
+        format!("ConsumerControl(held: {})", held.join(", "))
+    }
+    fn on_disabled(&mut self, output: &mut T) {
+        if !self.held.is_empty() {
+            self.held.clear();
+            output.send_consumer(0);
+        }
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if let Some(usage) = consumer_usage(kc.keycode) {
+                        *status = EventStatus::Handled;
+                        if !self.held.contains(&kc.keycode) {
+                            self.held.push(kc.keycode);
+                        }
+                        output.send_consumer(usage);
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if consumer_usage(kc.keycode).is_some() {
+                        *status = EventStatus::Handled;
+                        self.held.retain(|k| *k != kc.keycode);
+                        //a report holds a single usage - fall back to the
+                        //one still held, if any
+                        match self.held.last().and_then(|k| consumer_usage(*k)) {
+                            Some(usage) => output.send_consumer(usage),
+                            None => output.send_consumer(0),
+                        }
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{consumer_usage, ConsumerControl, USBKeyboard};
+    use crate::key_codes::KeyCode;
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Keyboard;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_consumer_usage() {
+        assert!(consumer_usage(KeyCode::MediaVolumeUp.to_u32()) == Some(0xE9));
+        assert!(consumer_usage(KeyCode::MediaPlayPause.to_u32()) == Some(0xCD));
+        assert!(consumer_usage(KeyCode::VolumeUp.to_u32()).is_none());
+        assert!(consumer_usage(KeyCode::A.to_u32()).is_none());
+    }
+
+    #[test]
+    fn test_consumer_control() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(ConsumerControl::new()));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(MediaVolumeUp, 0);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.consumer_reports == vec![0xE9]);
+        assert!(keyboard.events.is_empty());
+        keyboard.add_keyrelease(MediaVolumeUp, 10);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.consumer_reports == vec![0xE9, 0]);
+        assert!(keyboard.events.is_empty());
+        keyboard.output.clear();
+
+        //regular keys are passed on
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(keyboard.output.consumer_reports.is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_stream::{Event, EventStatus};
+use crate::USBKeyOut;
+use no_std_compat::prelude::v1::*;
+
+/// Drop switch bounces - a release immediately followed
+/// by a press of the same key.
+///
+/// A release is held back (Ignored, together with all events after it)
+/// until either the same key is pressed again within debounce_ms
+/// (then both are dropped, and the key stays down), or debounce_ms
+/// have passed (as accumulated from the following events and TimeOuts),
+/// and it is passed on.
+///
+/// So releases are delayed by debounce_ms, and TimeOut events
+/// need to be send regularly.
+///
+/// Add it as the very first handler.
+pub struct Debounce {
+    debounce_ms: u16,
+    waiting: Option<(u8, u16)>, // running_number of the held back release, TimeOut ms since
+}
+impl Debounce {
+    pub fn new(debounce_ms: u16) -> Debounce {
+        Debounce {
+            debounce_ms,
+            waiting: None,
+        }
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for Debounce {
+    fn describe(&self) -> String {
+        format!("Debounce(debounce_ms: {})", self.debounce_ms)
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        _output: &mut T,
+    ) -> HandlerResult {
+        let mut waiting = None;
+        let mut ii = 0;
+        while ii < events.len() {
+            let (keycode, running_number) = match &events[ii] {
+                (Event::KeyRelease(kc), EventStatus::Unhandled) => (kc.keycode, kc.running_number),
+                _ => {
+                    ii += 1;
+                    continue;
+                }
+            };
+            let waited_ms = match self.waiting {
+                Some((waiting_for, ms)) if waiting_for == running_number => ms,
+                _ => 0,
+            };
+            let mut elapsed = waited_ms;
+            let mut timeouts: u16 = 0;
+            let mut bounce = None;
+            for (jj, (event, _status)) in events.iter().enumerate().skip(ii + 1) {
+                match event {
+                    Event::KeyPress(kc) | Event::KeyRelease(kc) => {
+                        elapsed = elapsed.saturating_add(kc.ms_since_last);
+                        if elapsed > self.debounce_ms {
+                            break;
+                        }
+                        if let Event::KeyPress(_) = event {
+                            if kc.keycode == keycode {
+                                bounce = Some(jj);
+                                break;
+                            }
+                        }
+                    }
+                    Event::TimeOut(ms) => {
+                        timeouts = timeouts.saturating_add(*ms);
+                        elapsed = elapsed.saturating_add(*ms);
+                    }
+                }
+            }
+            if let Some(jj) = bounce {
+                events[ii].1 = EventStatus::Handled;
+                events[jj].1 = EventStatus::Handled;
+            } else if elapsed <= self.debounce_ms {
+                //hold it (and everything after it) back for now
+                waiting = Some((running_number, waited_ms.saturating_add(timeouts)));
+                for (event, status) in events.iter_mut().skip(ii) {
+                    match event {
+                        Event::KeyPress(_) | Event::KeyRelease(_) => {
+                            *status = EventStatus::Ignored
+                        }
+                        Event::TimeOut(_) => {}
+                    }
+                }
+                break;
+            }
+            ii += 1;
+        }
+        self.waiting = waiting;
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{Debounce, USBKeyboard};
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Keyboard;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_debounce_bounce() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(Debounce::new(5)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rct(A, 2, &[&[A]]);
+        keyboard.pct(A, 1, &[&[A]]);
+        //a single press survives
+        assert!(keyboard.events.len() == 1);
+        keyboard.tc(10, &[&[A]]);
+        //a real release
+        keyboard.rc(A, &[&[A]]);
+        keyboard.tc(3, &[&[A]]);
+        keyboard.tc(3, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_debounce_same_pass() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(Debounce::new(5)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(A, 0);
+        keyboard.add_keyrelease(A, 2);
+        keyboard.add_keypress(A, 1);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.events.len() == 1);
+        keyboard.output.clear();
+        keyboard.rc(A, &[&[A]]);
+        //other keys wait for the release - and release it once they're
+        //debounce_ms later
+        keyboard.pct(B, 10, &[&[B]]);
+        keyboard.rc(B, &[&[B]]);
+        keyboard.tc(10, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_debounce_slow_repress() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(Debounce::new(5)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rct(A, 2, &[&[A]]);
+        keyboard.tc(6, &[&[]]);
+        keyboard.pct(A, 1, &[&[A]]);
+        keyboard.rct(A, 10, &[&[A]]);
+        keyboard.tc(10, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+}
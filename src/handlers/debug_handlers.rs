@@ -1,7 +1,8 @@
 use crate::handlers::{ProcessKeys, HandlerResult};
 use crate::key_codes::KeyCode;
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
-use crate::USBKeyOut;
+use crate::{KeyboardState, USBKeyOut};
+use alloc::collections::VecDeque;
 ///handlers that probably are only useful while building a keyboard
 ///
 ///
@@ -111,18 +112,143 @@ impl<T: USBKeyOut, F: FnMut(String)> ProcessKeys<T> for DebugStream<F> {
                     EventStatus::Ignored => {
                         (self.write_callback)("EventStatus::Ignored),".to_string())
                     }
+                    EventStatus::Observed => {
+                        (self.write_callback)("EventStatus::Observed),".to_string())
+                    }
                 }
             }
         }
         HandlerResult::NoOp
     }
 }
+
+/// Record the reports actually send to the host (as opposed to
+/// DebugStream, which shows the events), for replaying them host side.
+///
+/// Handlers never see the final reports, so this is not a handler
+/// but a wrapper around your USBKeyOut - Keyboard::new(RecordingOutput::new(output, 64)).
+/// Everything is forwarded to the inner output, while each send_keys/
+/// send_registered/send_empty is appended to a ring buffer
+/// of the last capacity reports.
+///
+/// Keys send via send_keys_later are not recorded.
+pub struct RecordingOutput<T> {
+    pub inner: T,
+    capacity: usize,
+    registered: Vec<u8>,
+    reports: VecDeque<Vec<u8>>,
+}
+impl<T: USBKeyOut> RecordingOutput<T> {
+    pub fn new(inner: T, capacity: usize) -> RecordingOutput<T> {
+        RecordingOutput {
+            inner,
+            capacity,
+            registered: Vec::new(),
+            reports: VecDeque::with_capacity(capacity),
+        }
+    }
+    /// retrieve (and forget) the recorded reports, oldest first
+    pub fn drain_reports(&mut self) -> Vec<Vec<u8>> {
+        self.reports.drain(..).collect()
+    }
+    fn record(&mut self, report: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.reports.len() == self.capacity {
+            self.reports.pop_front();
+        }
+        self.reports.push_back(report);
+    }
+}
+impl<T: USBKeyOut> USBKeyOut for RecordingOutput<T> {
+    fn send_keys(&mut self, keys: &[KeyCode]) {
+        self.record(keys.iter().map(|&x| x.to_u8()).collect());
+        self.inner.send_keys(keys);
+    }
+    fn register_key(&mut self, key: KeyCode) {
+        if !self.registered.iter().any(|x| *x == key.to_u8()) {
+            self.registered.push(key.to_u8());
+        }
+        self.inner.register_key(key);
+    }
+    fn send_registered(&mut self) {
+        let report = core::mem::take(&mut self.registered);
+        self.record(report);
+        self.inner.send_registered();
+    }
+    fn send_empty(&mut self) {
+        self.record(Vec::new());
+        self.inner.send_empty();
+    }
+    fn state(&mut self) -> &mut KeyboardState {
+        self.inner.state()
+    }
+    fn ro_state(&self) -> &KeyboardState {
+        self.inner.ro_state()
+    }
+    fn debug(&mut self, s: &str) {
+        self.inner.debug(s);
+    }
+    fn bootloader(&mut self) {
+        self.inner.bootloader();
+    }
+    fn send_keys_later(&mut self, keys: &[KeyCode], ms: u16) {
+        self.inner.send_keys_later(keys, ms);
+    }
+    fn do_send_later(&mut self) {
+        self.inner.do_send_later();
+    }
+    fn send_mouse(&mut self, buttons: u8, dx: i8, dy: i8, wheel: i8) {
+        self.inner.send_mouse(buttons, dx, dy, wheel);
+    }
+    fn send_consumer(&mut self, usage: u16) {
+        self.inner.send_consumer(usage);
+    }
+}
+
 #[cfg(test)]
 //#[macro_use]
 //extern crate std;
 mod tests {
-    use crate::handlers::debug_handlers::transform_u32_to_keycodes;
+    use crate::handlers::debug_handlers::{transform_u32_to_keycodes, RecordingOutput};
+    use crate::handlers::USBKeyboard;
     use crate::key_codes::KeyCode;
+    use crate::test_helpers::KeyOutCatcher;
+    use crate::Keyboard;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_recording_output() {
+        use KeyCode::*;
+        let mut keyboard = Keyboard::new(RecordingOutput::new(KeyOutCatcher::new(), 16));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        for key in &[A, B] {
+            keyboard.add_keypress(*key, 0);
+            keyboard.handle_keys().unwrap();
+            keyboard.add_keyrelease(*key, 0);
+            keyboard.handle_keys().unwrap();
+        }
+        let recorded = keyboard.output.drain_reports();
+        assert!(recorded == keyboard.output.inner.reports);
+        assert!(recorded.len() == 4);
+        assert!(recorded[0] == vec![A.to_u8()]);
+        assert!(recorded[2] == vec![B.to_u8()]);
+        assert!(keyboard.output.drain_reports().is_empty());
+    }
+
+    #[test]
+    fn test_recording_output_ring_buffer() {
+        use crate::USBKeyOut;
+        use KeyCode::*;
+        let mut output = RecordingOutput::new(KeyOutCatcher::new(), 2);
+        output.send_keys(&[A]);
+        output.send_keys(&[B]);
+        output.send_empty();
+        assert!(output.drain_reports() == vec![vec![B.to_u8()], vec![]]);
+        assert!(output.inner.reports.len() == 3);
+    }
     #[test]
     fn test_transform_u32_to_keycodes() {
         assert!(transform_u32_to_keycodes(0) == [KeyCode::Kb0; 8]);
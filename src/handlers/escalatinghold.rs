@@ -0,0 +1,223 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::{describe_keycode, AcceptsKeycode};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::{HandlerID, Modifier, USBKeyOut};
+use no_std_compat::prelude::v1::*;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EscalatingHoldState {
+    Base,
+    FirstPressed, // could still be a tap
+    ModifierHeld,
+    Tapped, // released quickly - waiting for a second press
+    LayerHeld,
+}
+
+/// A key that escalates:
+/// hold it for a modifier, but tap it and hold it again
+/// (within gap_ms) for a layer.
+///
+/// The modifier is set once the key has been held for hold_ms,
+/// or as soon as another key is pressed while it's down.
+/// A press-release shorter than hold_ms does nothing by itself,
+/// but if the trigger is pressed again within gap_ms the layer
+/// is enabled for the duration of that second hold.
+pub struct EscalatingHold {
+    trigger: u32,
+    modifier: Modifier,
+    layer_id: HandlerID,
+    gap_ms: u16,
+    hold_ms: u16,
+    state: EscalatingHoldState,
+    ms_in_state: u16,
+}
+impl EscalatingHold {
+    pub fn new(
+        trigger: impl AcceptsKeycode,
+        modifier: Modifier,
+        layer_id: HandlerID,
+        gap_ms: u16,
+        hold_ms: u16,
+    ) -> EscalatingHold {
+        EscalatingHold {
+            trigger: trigger.to_u32(),
+            modifier,
+            layer_id,
+            gap_ms,
+            hold_ms,
+            state: EscalatingHoldState::Base,
+            ms_in_state: 0,
+        }
+    }
+    fn set_state(&mut self, state: EscalatingHoldState) {
+        self.state = state;
+        self.ms_in_state = 0;
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for EscalatingHold {
+    fn describe(&self) -> String {
+        format!(
+            "EscalatingHold(trigger: {}, layer: {}, gap_ms: {}, hold_ms: {})",
+            describe_keycode(self.trigger),
+            self.layer_id,
+            self.gap_ms,
+            self.hold_ms
+        )
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        if self.state == EscalatingHoldState::Tapped
+                            && self.ms_in_state.saturating_add(kc.ms_since_last) <= self.gap_ms
+                        {
+                            self.set_state(EscalatingHoldState::LayerHeld);
+                            output.state().enable_handler(self.layer_id);
+                        } else {
+                            self.set_state(EscalatingHoldState::FirstPressed);
+                        }
+                    } else if kc.flag & 0x1 == 0 {
+                        //a fresh key press, not one we've seen before
+                        match self.state {
+                            EscalatingHoldState::FirstPressed => {
+                                self.set_state(EscalatingHoldState::ModifierHeld);
+                                output.state().set_modifier(self.modifier, true);
+                            }
+                            EscalatingHoldState::Tapped => {
+                                self.set_state(EscalatingHoldState::Base);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        match self.state {
+                            EscalatingHoldState::FirstPressed => {
+                                if self.ms_in_state.saturating_add(kc.ms_since_last)
+                                    < self.hold_ms
+                                {
+                                    self.set_state(EscalatingHoldState::Tapped);
+                                } else {
+                                    self.set_state(EscalatingHoldState::Base);
+                                }
+                            }
+                            EscalatingHoldState::ModifierHeld => {
+                                self.set_state(EscalatingHoldState::Base);
+                                output.state().set_modifier(self.modifier, false);
+                            }
+                            EscalatingHoldState::LayerHeld => {
+                                self.set_state(EscalatingHoldState::Base);
+                                output.state().disable_handler(self.layer_id);
+                            }
+                            EscalatingHoldState::Base | EscalatingHoldState::Tapped => {}
+                        }
+                    }
+                }
+                Event::TimeOut(ms_since_last) => {
+                    self.ms_in_state = self.ms_in_state.saturating_add(*ms_since_last);
+                    match self.state {
+                        EscalatingHoldState::FirstPressed if self.ms_in_state >= self.hold_ms => {
+                            self.set_state(EscalatingHoldState::ModifierHeld);
+                            output.state().set_modifier(self.modifier, true);
+                        }
+                        EscalatingHoldState::Tapped if self.ms_in_state > self.gap_ms => {
+                            self.set_state(EscalatingHoldState::Base);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{AutoOff, EscalatingHold, Layer, LayerAction, USBKeyboard};
+    use crate::key_codes::{KeyCode, UserKey};
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Modifier::*;
+    use crate::{Keyboard, USBKeyOut};
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_escalating_hold_modifier() {
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.future_handler_id(2);
+        keyboard.add_handler(Box::new(EscalatingHold::new(
+            UserKey::UK0,
+            Ctrl,
+            layer_id,
+            200,
+            150,
+        )));
+        keyboard.add_handler(Box::new(Layer::new(
+            vec![(KeyCode::A, LayerAction::RewriteTo(KeyCode::X.into()))],
+            AutoOff::No,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.tc(150, &[&[KeyCode::LCtrl]]);
+        assert!(keyboard.output.state().modifier(Ctrl));
+        keyboard.pc(KeyCode::A, &[&[KeyCode::A, KeyCode::LCtrl]]);
+        keyboard.rc(KeyCode::A, &[&[KeyCode::LCtrl]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+        assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+
+        //another key while held - modifier right away
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.pct(KeyCode::A, 10, &[&[KeyCode::A, KeyCode::LCtrl]]);
+        keyboard.rc(KeyCode::A, &[&[KeyCode::LCtrl]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+    }
+
+    #[test]
+    fn test_escalating_hold_layer() {
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.future_handler_id(2);
+        keyboard.add_handler(Box::new(EscalatingHold::new(
+            UserKey::UK0,
+            Ctrl,
+            layer_id,
+            200,
+            150,
+        )));
+        keyboard.add_handler(Box::new(Layer::new(
+            vec![(KeyCode::A, LayerAction::RewriteTo(KeyCode::X.into()))],
+            AutoOff::No,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(layer_id));
+        assert!(!keyboard.output.state().modifier(Ctrl));
+        keyboard.pc(KeyCode::A, &[&[KeyCode::X]]);
+        keyboard.rc(KeyCode::A, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+
+        //too slow - just another first press
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        keyboard.tc(250, &[&[]]);
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+        keyboard.tc(150, &[&[KeyCode::LCtrl]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_stream::{Event, EventStatus};
+use crate::USBKeyOut;
+use no_std_compat::prelude::v1::*;
+
+/// A bundle of handlers that is added (and enabled/disabled) as one,
+/// with a single HandlerID - e.g. a set of related macros or layers.
+///
+/// The members run in order, as if they had been added one after another,
+/// and share the group's enabled bit - their own default_enabled is ignored.
+/// The group starts enabled if all its members would.
+///
+/// If any member returns HandlerResult::Disable, the whole group is disabled.
+pub struct HandlerGroup<'a, T> {
+    handlers: Vec<Box<dyn ProcessKeys<T> + Send + 'a>>,
+}
+
+impl<'a, T: USBKeyOut> HandlerGroup<'a, T> {
+    pub fn new(handlers: Vec<Box<dyn ProcessKeys<T> + Send + 'a>>) -> HandlerGroup<'a, T> {
+        HandlerGroup { handlers }
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for HandlerGroup<'_, T> {
+    fn describe(&self) -> String {
+        let members: Vec<String> = self.handlers.iter().map(|h| h.describe()).collect();
+        format!("HandlerGroup({})", members.join(", "))
+    }
+    fn default_enabled(&self) -> bool {
+        self.handlers.iter().all(|h| h.default_enabled())
+    }
+    fn runs_in_passthrough(&self) -> bool {
+        self.handlers.iter().any(|h| h.runs_in_passthrough())
+    }
+    fn on_added(&mut self, output: &mut T) {
+        for h in self.handlers.iter_mut() {
+            h.on_added(output);
+        }
+    }
+    fn on_enabled(&mut self, output: &mut T) {
+        for h in self.handlers.iter_mut() {
+            h.on_enabled(output);
+        }
+    }
+    fn on_disabled(&mut self, output: &mut T) {
+        for h in self.handlers.iter_mut() {
+            h.on_disabled(output);
+        }
+    }
+    fn reset(&mut self, output: &mut T) {
+        for h in self.handlers.iter_mut() {
+            h.reset(output);
+        }
+    }
+    fn process_keys_while_disabled(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) {
+        for h in self.handlers.iter_mut() {
+            h.process_keys_while_disabled(events, output);
+        }
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        let mut result = HandlerResult::NoOp;
+        for h in self.handlers.iter_mut() {
+            if output.ro_state().passthrough() && !h.runs_in_passthrough() {
+                continue;
+            }
+            if let HandlerResult::Disable = h.process_keys(events, output) {
+                result = HandlerResult::Disable;
+            }
+            if output.state()._aborted() {
+                break; // the Keyboard clears the events
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{HandlerGroup, ProcessKeys, RewriteLayer, USBKeyboard};
+    use crate::key_codes::KeyCode;
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::{Keyboard, USBKeyOut};
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    const MAP_A: &[(u32, u32)] = &[(KeyCode::A.to_u32(), KeyCode::X.to_u32())];
+    const MAP_B: &[(u32, u32)] = &[(KeyCode::B.to_u32(), KeyCode::Y.to_u32())];
+
+    #[test]
+    fn test_handler_group() {
+        use crate::key_codes::KeyCode::*;
+        let group: HandlerGroup<KeyOutCatcher> = HandlerGroup::new(vec![
+            Box::new(RewriteLayer::new(MAP_A)),
+            Box::new(RewriteLayer::new(MAP_B)),
+        ]);
+        assert!(!group.default_enabled());
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let id = keyboard.add_handler(Box::new(group));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.pc(B, &[&[B]]);
+        keyboard.rc(B, &[&[]]);
+        keyboard.output.state().enable_handler(id);
+        keyboard.pc(A, &[&[X]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.pc(B, &[&[Y]]);
+        //the members still finish what they started
+        keyboard.output.state().disable_handler(id);
+        keyboard.rc(B, &[&[]]);
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.pc(B, &[&[B]]);
+        keyboard.rc(B, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::handlers::{Action, HandlerResult, ProcessKeys};
+use crate::key_codes::{describe_keycode, AcceptsKeycode};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::USBKeyOut;
+use no_std_compat::prelude::v1::*;
+
+/// Do one thing on a tap, and another one once the key
+/// has been held for hold_ms.
+///
+/// Unlike LongTap, the hold action happens as soon as the
+/// threshold is reached (detected via TimeOut events) - the key is still down.
+/// The release then does nothing.
+///
+/// A release before hold_ms triggers the tap action.
+/// Unlike ModTap, both are arbitrary Actions and other keys
+/// are not affected.
+pub struct HoldEmit<M1, M2> {
+    trigger: u32,
+    tap_action: M1,
+    hold_action: M2,
+    hold_ms: u16,
+    pressed: bool,
+    fired: bool,
+    held_ms: u16,
+}
+
+impl<M1: Action, M2: Action> HoldEmit<M1, M2> {
+    pub fn new(
+        trigger: impl AcceptsKeycode,
+        tap_action: M1,
+        hold_action: M2,
+        hold_ms: u16,
+    ) -> HoldEmit<M1, M2> {
+        HoldEmit {
+            trigger: trigger.to_u32(),
+            tap_action,
+            hold_action,
+            hold_ms,
+            pressed: false,
+            fired: false,
+            held_ms: 0,
+        }
+    }
+}
+
+impl<T: USBKeyOut, M1: Action, M2: Action> ProcessKeys<T> for HoldEmit<M1, M2> {
+    fn describe(&self) -> String {
+        format!(
+            "HoldEmit(trigger: {}, hold_ms: {})",
+            describe_keycode(self.trigger),
+            self.hold_ms
+        )
+    }
+    fn on_disabled(&mut self, _output: &mut T) {
+        self.pressed = false;
+        self.fired = false;
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        self.pressed = true;
+                        self.fired = false;
+                        self.held_ms = 0;
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        if self.pressed && !self.fired {
+                            if self.held_ms.saturating_add(kc.ms_since_last) >= self.hold_ms {
+                                self.hold_action.on_trigger(output);
+                            } else {
+                                self.tap_action.on_trigger(output);
+                            }
+                        }
+                        self.pressed = false;
+                        self.fired = false;
+                    }
+                }
+                Event::TimeOut(ms_since_last) => {
+                    if self.pressed && !self.fired {
+                        self.held_ms = self.held_ms.saturating_add(*ms_since_last);
+                        if self.held_ms >= self.hold_ms {
+                            self.fired = true;
+                            self.hold_action.on_trigger(output);
+                        }
+                    }
+                }
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{HoldEmit, USBKeyboard};
+    use crate::key_codes::{KeyCode, UserKey};
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Keyboard;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_holdemit_tap() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(HoldEmit::new(UserKey::UK0, A, B, 300)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.tc(100, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[A]]);
+        //other keys are passed through
+        keyboard.pc(KeyCode::X, &[&[X]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+    }
+
+    #[test]
+    fn test_holdemit_hold() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(HoldEmit::new(UserKey::UK0, A, B, 300)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.tc(200, &[&[]]);
+        //fires while the key is still down
+        keyboard.tc(100, &[&[B]]);
+        keyboard.tc(100, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+
+        //a slow release without timeouts is a hold as well
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rct(UserKey::UK0, 300, &[&[B]]);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::{describe_keycode, KeyCode};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::{Modifier, USBKeyOut};
+use no_std_compat::prelude::v1::*;
+
+#[repr(u8)]
+#[derive(Clone, Copy)]
+enum HomeRowState {
+    Pressed,    //could be either a tap or a hold
+    Held,       //modifier is set
+    PressedTap, //was a tap, waiting for the release
+}
+
+struct HomeRowKey {
+    index: usize, // into HomeRowMods.keys
+    running_number: u8,
+    state: HomeRowState,
+    held_ms: u16,
+}
+
+/// ModTap for a whole row of keys at once - e.g. the home row
+/// with Ctrl/Alt/Gui/Shift on A/S/D/F.
+///
+/// Configured with (trigger, tap_keycode, hold_modifier) per key,
+/// and one tapping_term_ms for all of them.
+///
+/// Each key is a tap if released before tapping_term_ms, and sets it's
+/// modifier if held past it (or another key is pressed after that).
+/// Another key pressed before the tapping term - including another
+/// one of the home row keys - sends the tap right away, so fast
+/// rolls over the home row come out as plain letters.
+///
+/// TimeOuts need to be send regularly.
+pub struct HomeRowMods {
+    keys: &'static [(u32, KeyCode, Modifier)],
+    tapping_term_ms: u16,
+    down: Vec<HomeRowKey>,
+}
+impl HomeRowMods {
+    pub fn new(keys: &'static [(u32, KeyCode, Modifier)], tapping_term_ms: u16) -> HomeRowMods {
+        HomeRowMods {
+            keys,
+            tapping_term_ms,
+            down: Vec::new(),
+        }
+    }
+    /// advance the time of the undecided keys
+    fn elapse(&mut self, ms: u16, output: &mut dyn USBKeyOut) {
+        for key in self.down.iter_mut() {
+            if let HomeRowState::Pressed = key.state {
+                key.held_ms = key.held_ms.saturating_add(ms);
+                if key.held_ms >= self.tapping_term_ms {
+                    key.state = HomeRowState::Held;
+                    output.state().set_modifier(self.keys[key.index].2, true);
+                }
+            }
+        }
+    }
+    /// another key was pressed - undecided keys are taps now
+    /// (held ones were decided by elapse)
+    fn roll_over(&mut self, output: &mut dyn USBKeyOut) {
+        for key in self.down.iter_mut() {
+            if let HomeRowState::Pressed = key.state {
+                key.state = HomeRowState::PressedTap;
+                output.register_key(self.keys[key.index].1);
+            }
+        }
+    }
+    fn release_modifier(&self, index: usize, output: &mut dyn USBKeyOut) {
+        let modifier = self.keys[index].2;
+        //another held key might share the modifier
+        let still_held = self.down.iter().any(|key| match key.state {
+            HomeRowState::Held => key.index != index && self.keys[key.index].2 == modifier,
+            HomeRowState::Pressed | HomeRowState::PressedTap => false,
+        });
+        if !still_held {
+            output.state().set_modifier(modifier, false);
+        }
+    }
+}
+impl<T: USBKeyOut> ProcessKeys<T> for HomeRowMods {
+    fn describe(&self) -> String {
+        let triggers: Vec<String> = self
+            .keys
+            .iter()
+            .map(|(trigger, _, _)| describe_keycode(*trigger))
+            .collect();
+        format!(
+            "HomeRowMods(triggers: [{}], tapping_term_ms: {})",
+            triggers.join(", "),
+            self.tapping_term_ms
+        )
+    }
+    fn on_disabled(&mut self, output: &mut T) {
+        for key in self.down.iter() {
+            if let HomeRowState::Held = key.state {
+                output.state().set_modifier(self.keys[key.index].2, false);
+            }
+        }
+        self.down.clear();
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    let index = self.keys.iter().position(|(t, _, _)| *t == kc.keycode);
+                    if let Some(index) = index {
+                        *status = EventStatus::Handled;
+                        if self.down.iter().any(|key| key.running_number == kc.running_number) {
+                            continue;
+                        }
+                        self.elapse(kc.ms_since_last, output);
+                        self.roll_over(output);
+                        self.down.retain(|key| key.index != index);
+                        self.down.push(HomeRowKey {
+                            index,
+                            running_number: kc.running_number,
+                            state: HomeRowState::Pressed,
+                            held_ms: 0,
+                        });
+                    } else if kc.flag & 0x1 == 0 {
+                        self.elapse(kc.ms_since_last, output);
+                        self.roll_over(output);
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    self.elapse(kc.ms_since_last, output);
+                    let pos = self
+                        .down
+                        .iter()
+                        .position(|key| self.keys[key.index].0 == kc.keycode);
+                    if let Some(pos) = pos {
+                        *status = EventStatus::Handled;
+                        match self.down[pos].state {
+                            HomeRowState::Pressed => {
+                                output.register_key(self.keys[self.down[pos].index].1);
+                            }
+                            HomeRowState::Held => {
+                                self.release_modifier(self.down[pos].index, output);
+                            }
+                            HomeRowState::PressedTap => {}
+                        }
+                        self.down.remove(pos);
+                    }
+                }
+                Event::TimeOut(ms_since_last) => {
+                    self.elapse(*ms_since_last, output);
+                }
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{HomeRowMods, USBKeyboard};
+    use crate::key_codes::KeyCode;
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Modifier;
+    use crate::Modifier::*;
+    use crate::{Keyboard, USBKeyOut};
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    const HOME_ROW: &[(u32, KeyCode, Modifier)] = &[
+        (KeyCode::F as u32, KeyCode::F, Ctrl),
+        (KeyCode::J as u32, KeyCode::J, Shift),
+    ];
+
+    #[test]
+    fn test_homerow_tap() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(HomeRowMods::new(HOME_ROW, 200)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(F, &[&[]]);
+        keyboard.rc(F, &[&[F]]);
+        keyboard.tc(10, &[&[]]);
+        keyboard.pc(J, &[&[]]);
+        keyboard.rc(J, &[&[J]]);
+        keyboard.tc(10, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+        assert!(!keyboard.output.state().modifier(Shift));
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_homerow_hold() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(HomeRowMods::new(HOME_ROW, 200)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //held via timeouts
+        keyboard.pc(F, &[&[]]);
+        keyboard.tc(100, &[&[]]);
+        keyboard.tc(100, &[&[LCtrl]]);
+        assert!(keyboard.output.state().modifier(Ctrl));
+        keyboard.pc(A, &[&[LCtrl, A]]);
+        keyboard.rc(A, &[&[LCtrl]]);
+        keyboard.rc(F, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+
+        //held via another key after the tapping term - even a home row one
+        keyboard.pc(F, &[&[]]);
+        keyboard.pct(J, 250, &[&[LCtrl]]);
+        keyboard.rc(J, &[&[LCtrl, J]]);
+        keyboard.rc(F, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+        assert!(!keyboard.output.state().modifier(Shift));
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_homerow_roll() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(HomeRowMods::new(HOME_ROW, 200)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(F, &[&[]]);
+        keyboard.pct(J, 20, &[&[F]]);
+        keyboard.rct(F, 20, &[&[]]);
+        keyboard.rct(J, 20, &[&[J]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+        assert!(!keyboard.output.state().modifier(Shift));
+
+        //nested roll, inner key released first
+        keyboard.pc(F, &[&[]]);
+        keyboard.pct(J, 20, &[&[F]]);
+        keyboard.rct(J, 20, &[&[J]]);
+        keyboard.rct(F, 20, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+        assert!(!keyboard.output.state().modifier(Shift));
+        assert!(keyboard.events.is_empty());
+    }
+}
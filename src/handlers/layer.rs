@@ -1,19 +1,27 @@
 use crate::handlers::{ProcessKeys, HandlerResult};
-use crate::key_codes::{AcceptsKeycode, KeyCode};
-use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::key_codes::{describe_keycode, AcceptsKeycode, KeyCode};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus, Key};
 use crate::Modifier::*;
-use crate::USBKeyOut;
-use crate::handlers::oneshot::ONESHOT_TRIGGERS;
+use crate::{Modifier, USBKeyOut, MODIFIER_KEYS};
 
 use no_std_compat::prelude::v1::*;
 pub enum LayerAction<'a> {
     RewriteTo(u32),
     RewriteToShifted(u32, u32),
+    /// rewrite, and have the USBKeyboard send it without Shift
+    /// (while it's held), even if shift is active
+    RewriteUnshifted(u32),
+    /// rewrite, and set the modifiers while the key is held,
+    /// e.g. Ctrl+Left. Modifiers that were already active are left alone.
+    RewriteToWithMods(u32, &'static [Modifier]),
     //todo: rewrite shift
     SendString(&'a str),
     SendStringShifted(&'a str, &'a str),
-    //    Callback(fn(&mut T) -> (), fn(&mut T) -> ()),
-    Action(Box<dyn crate::handlers::Action>)
+    /// run arbitrary code (on_trigger) upon key release
+    Action(Box<dyn crate::handlers::Action + 'a>),
+    /// leave the key alone for the handlers below,
+    /// even if a later mapping (of this layer) would match it
+    Transparent,
 }
 
 #[repr(u8)]
@@ -24,8 +32,8 @@ pub enum AutoOff {
     AfterAll
 }
 
-/// A layer either rewrites a key to another one
-/// or outputs a string upon key release.
+/// A layer either rewrites a key to another one,
+/// outputs a string or runs an Action upon key release.
 ///
 /// It does this for multiple mappings at once,
 /// and it can consider the shift state, which
@@ -43,34 +51,147 @@ pub enum AutoOff {
 /// after any key release (AutoOff::AfterAll), after a non-modifier-non-oneshot
 /// key release (AutoOff::AfterNonModifier), or after a successfull 
 /// match AutoOff::AfterMatch
+///
+/// Releases are rewritten like their presses were, even if the layer
+/// has been disabled (or shift changed) in the meantime.
 pub struct Layer<'a> {
     rewrites: Vec<(u32, LayerAction<'a>)>,
-    auto_off: AutoOff
+    auto_off: AutoOff,
+    rewritten: Vec<(u32, u32)>, // original_keycode, keycode of rewritten presses
+    forced_modifiers: Vec<(u32, Vec<Modifier>)>, // original_keycode, modifiers set by RewriteToWithMods
 }
-impl Layer<'_> {
-    pub fn new<F: AcceptsKeycode>(rewrites: Vec<(F, LayerAction)>, 
-    auto_off: AutoOff) -> Layer<'_> {
+impl<'a> Layer<'a> {
+    pub fn new<F: AcceptsKeycode>(rewrites: Vec<(F, LayerAction<'a>)>, 
+    auto_off: AutoOff) -> Layer<'a> {
         Layer {
             rewrites: rewrites
                 .into_iter()
                 .map(|(trigger, action)| (trigger.to_u32(), action))
                 .collect(),
-            auto_off
+            auto_off,
+            rewritten: Vec::new(),
+            forced_modifiers: Vec::new(),
+        }
+    }
+
+    /// unset the modifiers a RewriteToWithMods press set
+    fn release_forced_modifiers(&mut self, original_keycode: u32, output: &mut dyn USBKeyOut) {
+        if let Some(pos) = self
+            .forced_modifiers
+            .iter()
+            .position(|(original, _modifiers)| *original == original_keycode)
+        {
+            for modifier in self.forced_modifiers.remove(pos).1 {
+                output.state().set_modifier(modifier, false);
+            }
+        }
+    }
+}
+
+/// rewrite a release like it's press was rewritten
+/// (as recorded in rewritten), returns whether it was
+pub(crate) fn rewrite_release_as_recorded(rewritten: &mut Vec<(u32, u32)>, kc: &mut Key) -> bool {
+    if (kc.flag & 2) == 0 {
+        if let Some(pos) = rewritten
+            .iter()
+            .position(|(original, _to)| *original == kc.original_keycode)
+        {
+            kc.keycode = rewritten.remove(pos).1;
+            kc.flag |= 2;
+            return true;
+        }
+    }
+    false
+}
+
+/// a press nobody rewrote of a key we still have a recorded rewrite for -
+/// the release of the earlier press never reached us
+/// (e.g. it was handled before us), so forget it.
+/// Returns whether there was one.
+pub(crate) fn forget_stale_rewrite(rewritten: &mut Vec<(u32, u32)>, kc: &Key) -> bool {
+    if (kc.flag & 2) == 0 {
+        if let Some(pos) = rewritten
+            .iter()
+            .position(|(original, _to)| *original == kc.original_keycode)
+        {
+            rewritten.remove(pos);
+            return true;
+        }
+    }
+    false
+}
+
+/// rewrite the releases of keys rewritten by a (now disabled) layer
+pub(crate) fn rewrite_releases_as_recorded(
+    rewritten: &mut Vec<(u32, u32)>,
+    events: &mut Vec<(Event, EventStatus)>,
+) {
+    if rewritten.is_empty() {
+        return;
+    }
+    for (event, _status) in iter_unhandled_mut(events) {
+        match event {
+            Event::KeyRelease(kc) => {
+                rewrite_release_as_recorded(rewritten, kc);
+            }
+            Event::KeyPress(kc) => {
+                forget_stale_rewrite(rewritten, kc);
+            }
+            Event::TimeOut(_) => {}
         }
     }
 }
 impl<T: USBKeyOut> ProcessKeys<T> for Layer<'_> {
+    fn describe(&self) -> String {
+        let mappings: Vec<String> = self
+            .rewrites
+            .iter()
+            .map(|(from, to)| {
+                let to = match to {
+                    LayerAction::RewriteTo(to) => describe_keycode(*to),
+                    LayerAction::RewriteUnshifted(to) => {
+                        format!("{} (unshifted)", describe_keycode(*to))
+                    }
+                    LayerAction::RewriteToWithMods(to, modifiers) => {
+                        let mut keys: Vec<String> = MODIFIER_KEYS
+                            .iter()
+                            .filter(|(modifier, _left, _right)| modifiers.contains(modifier))
+                            .map(|(_modifier, left, _right)| describe_keycode(left.to_u32()))
+                            .collect();
+                        keys.push(describe_keycode(*to));
+                        keys.join("+")
+                    }
+                    LayerAction::RewriteToShifted(to, shifted) => {
+                        format!("{}/{}", describe_keycode(*to), describe_keycode(*shifted))
+                    }
+                    LayerAction::SendString(s) => format!("{:?}", s),
+                    LayerAction::SendStringShifted(s, shifted) => format!("{:?}/{:?}", s, shifted),
+                    LayerAction::Action(_) => "Action".to_string(),
+                    LayerAction::Transparent => "Transparent".to_string(),
+                };
+                format!("{} -> {}", describe_keycode(*from), to)
+            })
+            .collect();
+        format!("Layer({})", mappings.join(", "))
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
         let mut result = HandlerResult::NoOp;
         for (event, status) in iter_unhandled_mut(events) {
             //events.iter_mut() {
             match event {
                 Event::KeyRelease(kc) => {
-                    let mut rewrite_happend = false;
+                    //rewritten like the press - skip the mappings
+                    let recorded = rewrite_release_as_recorded(&mut self.rewritten, kc);
+                    if recorded {
+                        self.release_forced_modifiers(kc.original_keycode, output);
+                    }
+                    let mut rewrite_happend = recorded;
                     for (from, to) in self.rewrites.iter_mut() {
-                        if *from == kc.keycode {
+                        if *from == kc.keycode && !recorded {
                             match to {
-                                LayerAction::RewriteTo(to_keycode) => {
+                                LayerAction::RewriteTo(to_keycode)
+                                | LayerAction::RewriteUnshifted(to_keycode)
+                                | LayerAction::RewriteToWithMods(to_keycode, _) => {
                                     if (kc.flag & 2) == 0 {
                                         kc.keycode = *to_keycode;
                                         kc.flag |= 2;
@@ -110,7 +231,9 @@ impl<T: USBKeyOut> ProcessKeys<T> for Layer<'_> {
                                     action.on_trigger(output);
                                     *status = EventStatus::Handled;
                                     rewrite_happend = true;
+                                    break; //only one rewrite per layer
                                 }
+                                LayerAction::Transparent => break,
                             }
                         }
                     }
@@ -119,7 +242,7 @@ impl<T: USBKeyOut> ProcessKeys<T> for Layer<'_> {
                         AutoOff::AfterAll => true,
                         AutoOff::AfterMatch => rewrite_happend,
                         AutoOff::AfterNonModifier => {
-                            !ONESHOT_TRIGGERS.read().contains(&kc.keycode) && ! 
+                            !output.state().is_oneshot_trigger(kc.keycode) && ! 
                             ( KeyCode::LCtrl.to_u32() <= kc.keycode && kc.keycode <= KeyCode::RGui.to_u32())
                         }
                     };
@@ -130,6 +253,9 @@ impl<T: USBKeyOut> ProcessKeys<T> for Layer<'_> {
 
                 }
                 Event::KeyPress(kc) => {
+                    if forget_stale_rewrite(&mut self.rewritten, kc) {
+                        self.release_forced_modifiers(kc.original_keycode, output);
+                    }
                     for (from, to) in self.rewrites.iter() {
                         if *from == kc.keycode {
                             match to {
@@ -137,6 +263,31 @@ impl<T: USBKeyOut> ProcessKeys<T> for Layer<'_> {
                                     if (kc.flag & 2) == 0 {
                                         kc.keycode = *to_keycode;
                                         kc.flag |= 2;
+                                        self.rewritten.push((kc.original_keycode, kc.keycode));
+                                    }
+                                    break; //only one rewrite per layer
+                                }
+                                LayerAction::RewriteUnshifted(to_keycode) => {
+                                    if (kc.flag & 2) == 0 {
+                                        kc.keycode = *to_keycode;
+                                        kc.flag |= 2 | 8;
+                                        self.rewritten.push((kc.original_keycode, kc.keycode));
+                                    }
+                                    break; //only one rewrite per layer
+                                }
+                                LayerAction::RewriteToWithMods(to_keycode, modifiers) => {
+                                    if (kc.flag & 2) == 0 {
+                                        kc.keycode = *to_keycode;
+                                        kc.flag |= 2;
+                                        self.rewritten.push((kc.original_keycode, kc.keycode));
+                                        let mut forced = Vec::new();
+                                        for modifier in modifiers.iter() {
+                                            if !output.state().modifier(*modifier) {
+                                                output.state().set_modifier(*modifier, true);
+                                                forced.push(*modifier);
+                                            }
+                                        }
+                                        self.forced_modifiers.push((kc.original_keycode, forced));
                                     }
                                     break; //only one rewrite per layer
                                 }
@@ -148,6 +299,7 @@ impl<T: USBKeyOut> ProcessKeys<T> for Layer<'_> {
                                             kc.keycode = *to_keycode;
                                         }
                                         kc.flag |= 2;
+                                        self.rewritten.push((kc.original_keycode, kc.keycode));
                                     }
                                     break; //only one rewrite per layer
                                 }
@@ -157,6 +309,7 @@ impl<T: USBKeyOut> ProcessKeys<T> for Layer<'_> {
                                     *status = EventStatus::Handled;
                                     break;
                                 }
+                                LayerAction::Transparent => break,
                             }
                         }
                     }
@@ -166,6 +319,39 @@ impl<T: USBKeyOut> ProcessKeys<T> for Layer<'_> {
         }
         result
     }
+    fn process_keys_while_disabled(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) {
+        if self.forced_modifiers.is_empty() {
+            rewrite_releases_as_recorded(&mut self.rewritten, events);
+            return;
+        }
+        for (event, _status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyRelease(kc) => {
+                    if rewrite_release_as_recorded(&mut self.rewritten, kc) {
+                        self.release_forced_modifiers(kc.original_keycode, output);
+                    }
+                }
+                Event::KeyPress(kc) => {
+                    if forget_stale_rewrite(&mut self.rewritten, kc) {
+                        self.release_forced_modifiers(kc.original_keycode, output);
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+    }
+    fn reset(&mut self, output: &mut T) {
+        self.rewritten.clear();
+        for (_original, modifiers) in self.forced_modifiers.drain(..) {
+            for modifier in modifiers {
+                output.state().set_modifier(modifier, false);
+            }
+        }
+    }
     fn default_enabled(&self) -> bool {
         false
     }
@@ -187,6 +373,22 @@ mod tests {
     #[allow(unused_imports)]
     use no_std_compat::prelude::v1::*;
     #[test]
+    fn test_layer_describe() {
+        let l = Layer::new(
+            vec![
+                (KeyCode::A, LayerAction::RewriteTo(KeyCode::X.into())),
+                (KeyCode::B, LayerAction::SendString("hello")),
+            ],
+            AutoOff::No,
+        );
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        let descriptions = keyboard.describe_handlers();
+        assert!(descriptions[0] == "Layer(A -> X, B -> \"hello\")");
+        assert!(descriptions[1].ends_with("USBKeyboard"));
+    }
+    #[test]
     fn test_layer_rewrite() {
         let l = Layer::new(vec![(
             KeyCode::A,
@@ -241,10 +443,7 @@ mod tests {
         keyboard.handle_keys().unwrap();
         check_output(&keyboard, &[&[KeyCode::X], &[]]);
 
-        //TODO: what happens when you disable the layer in the middle?
-        // I suspect that we will keep repeating one of the keycodes.
-        // what would be the sensible thing to happen? How can we achive this?
-        // possibly by clearing the keyboard events whenever a layer toggle happens?
+        //disabling the layer in the middle: see test_layer_disable_in_the_middle*
     }
     #[test]
     fn test_layer_rewrite_shifted() {
@@ -327,6 +526,100 @@ mod tests {
         keyboard.output.clear();
     }
     #[test]
+    fn test_layer_disable_in_the_middle_release_rewritten() {
+        use crate::handlers::LayerAction::{RewriteTo, RewriteToShifted};
+        //the unicode keyboard acts on the release - so the release
+        //must still be rewritten after the layer is disabled
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        let l = Layer::new(
+            vec![
+                (KeyCode::A, RewriteTo(0xDF)),
+                (KeyCode::B, RewriteToShifted(KeyCode::M.into(), KeyCode::Z.into())),
+            ],
+            AutoOff::No,
+        );
+        let layer_id = keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(UnicodeKeyboard::new()));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().enable_handler(layer_id);
+        keyboard.add_keypress(KeyCode::A, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[]]);
+        keyboard.output.clear();
+
+        keyboard.output.state().disable_handler(layer_id);
+        keyboard.add_keyrelease(KeyCode::A, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::D], &[KeyCode::F], &[]]);
+        assert!(keyboard.events.is_empty());
+        keyboard.output.clear();
+
+        //the shift state at press time counts
+        keyboard.output.state().enable_handler(layer_id);
+        keyboard.output.state().set_modifier(Shift, true);
+        keyboard.add_keypress(KeyCode::B, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.output.state().set_modifier(Shift, false);
+        keyboard.add_keyrelease(KeyCode::B, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::LShift, KeyCode::Z], &[]]);
+        assert!(keyboard.events.is_empty());
+    }
+    #[test]
+    fn test_layer_lost_release_not_rewritten_later() {
+        use crate::handlers::HandlerResult;
+        use crate::handlers::LayerAction::RewriteTo;
+        use crate::premade::FnHandler;
+        use crate::test_helpers::Checks;
+        use crate::iter_unhandled_mut;
+        use alloc::sync::Arc;
+        use spin::RwLock;
+        //0: pass, 1: abort on the release of A, 2: swallow the release of A
+        let mode = Arc::new(RwLock::new(0));
+        let mode2 = mode.clone();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        keyboard.add_handler(Box::new(FnHandler::new(
+            move |events: &mut Vec<(Event, EventStatus)>, output: &mut dyn USBKeyOut| {
+                for (e, status) in iter_unhandled_mut(events) {
+                    if let Event::KeyRelease(kc) = e {
+                        if kc.keycode == KeyCode::A.to_u32() {
+                            match *mode2.read() {
+                                1 => output.state().abort_and_clear_events(),
+                                2 => *status = EventStatus::Handled,
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                HandlerResult::NoOp
+            },
+        )));
+        let layer_id = keyboard.add_handler(Box::new(Layer::new(
+            vec![(KeyCode::A, RewriteTo(0xDF))],
+            AutoOff::No,
+        )));
+        keyboard.add_handler(Box::new(UnicodeKeyboard::new()));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        for lost in [1, 2].iter() {
+            keyboard.output.state().enable_handler(layer_id);
+            keyboard.pc(KeyCode::A, &[&[]]);
+            *mode.write() = *lost;
+            keyboard.add_keyrelease(KeyCode::A, 0);
+            keyboard.handle_keys().unwrap();
+            keyboard.output.clear();
+            *mode.write() = 0;
+            keyboard.events.clear();
+
+            //the next release of A is not rewritten (and typed as unicode)
+            keyboard.output.state().disable_handler(layer_id);
+            keyboard.pc(KeyCode::A, &[&[KeyCode::A]]);
+            keyboard.rc(KeyCode::A, &[&[]]);
+            assert!(keyboard.events.is_empty());
+        }
+    }
+    #[test]
     fn test_rewrite_shifted() {
         use crate::handlers::LayerAction::RewriteToShifted;
         let mut keyboard = Keyboard::new(KeyOutCatcher::new());
@@ -598,6 +891,134 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_layer_mixed_actions() {
+        use crate::test_helpers::Checks;
+        use crate::key_codes::KeyCode::*;
+        use crate::handlers::RewriteLayer;
+        use crate::premade::ActionToggleHandler;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.future_handler_id(1);
+        let other_id = keyboard.future_handler_id(2);
+        let l = Layer::new(
+            vec![
+                (A, LayerAction::RewriteTo(X.into())),
+                (B, LayerAction::SendString("a")),
+                (C, LayerAction::Action(Box::new(ActionToggleHandler { id: other_id }))),
+            ],
+            AutoOff::No,
+        );
+        assert!(keyboard.add_handler(Box::new(l)) == layer_id);
+        const MAP: &[(u32, u32)] = &[(KeyCode::D.to_u32(), KeyCode::Y.to_u32())];
+        assert!(keyboard.add_handler(Box::new(RewriteLayer::new(MAP))) == other_id);
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        keyboard.output.state().enable_handler(layer_id);
+        assert!(!keyboard.output.state().is_handler_enabled(other_id));
 
+        keyboard.pc(A, &[&[X]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.pc(B, &[&[]]);
+        keyboard.rc(B, &[&[Kb6], &[Kb1], &[]]);
+        //the action runs on release, the press is swallowed
+        keyboard.pc(C, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(other_id));
+        keyboard.rc(C, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(other_id));
+        keyboard.pc(D, &[&[Y]]);
+        keyboard.rc(D, &[&[]]);
+        keyboard.pc(C, &[&[]]);
+        keyboard.rc(C, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(other_id));
+        assert!(keyboard.events.is_empty());
+    }
 
+    #[test]
+    fn test_layer_transparent() {
+        use crate::key_codes::KeyCode::*;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let top = keyboard.add_handler(Box::new(Layer::new(
+            vec![
+                (A, LayerAction::Transparent),
+                (A, LayerAction::RewriteTo(Y.into())), //shadowed
+                (B, LayerAction::RewriteTo(Z.into())),
+            ],
+            AutoOff::AfterMatch,
+        )));
+        let bottom = keyboard.add_handler(Box::new(Layer::new(
+            vec![(A, LayerAction::RewriteTo(X.into()))],
+            AutoOff::No,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().enable_handler(top);
+        keyboard.output.state().enable_handler(bottom);
+        keyboard.pc(A, &[&[X]]);
+        keyboard.rc(A, &[&[]]);
+        //not a match for AutoOff
+        assert!(keyboard.output.state().is_handler_enabled(top));
+        keyboard.pc(B, &[&[Z]]);
+        keyboard.rc(B, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(top));
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_layer_rewrite_unshifted() {
+        use crate::key_codes::KeyCode::*;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.add_handler(Box::new(Layer::new(
+            vec![(A, LayerAction::RewriteUnshifted(Kb1.into()))],
+            AutoOff::No,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().enable_handler(layer_id);
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.pc(A, &[&[Kb1]]);
+        keyboard.rc(A, &[&[LShift]]);
+        //other keys are still shifted
+        keyboard.pc(B, &[&[LShift, B]]);
+        keyboard.rc(B, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[]]);
+        //no shift, no difference
+        keyboard.pc(A, &[&[Kb1]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_layer_rewrite_with_mods() {
+        use crate::key_codes::KeyCode::*;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.add_handler(Box::new(Layer::new(
+            vec![
+                (A, LayerAction::RewriteToWithMods(Home.into(), &[Shift])),
+                (B, LayerAction::RewriteToWithMods(Left.into(), &[Ctrl, Shift])),
+            ],
+            AutoOff::No,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().enable_handler(layer_id);
+        keyboard.pc(A, &[&[LShift, Home]]);
+        assert!(keyboard.output.state().modifier(Shift));
+        keyboard.rc(A, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+
+        //a held modifier stays held
+        keyboard.pc(LCtrl, &[&[LCtrl]]);
+        keyboard.pc(B, &[&[LCtrl, LShift, Left]]);
+        keyboard.rc(B, &[&[LCtrl]]);
+        assert!(keyboard.output.state().modifier(Ctrl));
+        assert!(!keyboard.output.state().modifier(Shift));
+        keyboard.rc(LCtrl, &[&[]]);
+
+        //released while the layer is off
+        keyboard.pc(A, &[&[LShift, Home]]);
+        keyboard.output.state().disable_handler(layer_id);
+        keyboard.rc(A, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+        assert!(keyboard.events.is_empty());
+    }
 }
@@ -1,50 +1,196 @@
-/*
-use crate::handlers::ProcessKeys;
-use crate::key_codes::AcceptsKeycode;
+use crate::handlers::{Action, HandlerResult, ProcessKeys};
+use crate::key_codes::{describe_keycode, AcceptsKeycode};
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
 use crate::USBKeyOut;
 use no_std_compat::prelude::v1::*;
 #[derive(PartialEq)]
-enum MatchResult<'a> {
-    Match(&'a str),
+#[derive(Debug)]
+enum MatchResult {
+    Match(usize),
     WontMatch,
     NeedsMoreInput,
 }
+/// Leader mappings that can't work
+#[derive(Debug, PartialEq)]
+pub enum LeaderError {
+    /// shorter is a prefix of longer - which can therefore never be typed
+    PrefixShadow { shorter: Vec<u32>, longer: Vec<u32> },
+}
+
+/// A leader key: press the trigger, then a sequence of keys
+/// to trigger an Action (on release of the last key).
+///
+/// Sequences that can't match anymore trigger the failure string.
+///
+/// Use new() to map sequences to strings,
+/// new_with_actions() for arbitrary Actions.
+/// Both panic if a sequence is a prefix of another one,
+/// use try_new()/try_new_with_actions() to get a LeaderError instead.
+///
+/// By default, the leader waits forever for the next key.
+/// Use with_timeout() to abort (with the failure string) a started
+/// sequence if no key is released for timeout_ms.
+///
+/// Use new_held() to not need a dedicated trigger: holding the key
+/// for at least threshold_ms activates the leader, while a quick tap
+/// (or pressing another key before the threshold) passes the key through.
+///
+/// Use with_prefix_callback() for feedback (LEDs, debug output...)
+/// while a sequence is being typed, or with_suggestions() to
+/// have the remaining completions send to output.debug.
 pub struct Leader<'a> {
     trigger: u32,
-    mappings: Vec<(Vec<u32>, &'a str)>,
+    mappings: Vec<(Vec<u32>, Box<dyn Action + 'a>)>,
+    previews: Vec<&'a str>, // output strings of the mappings, if known (new())
     failure: &'a str,
     prefix: Vec<u32>, //todo: refactor to not need this but use repeated iterators?
     active: bool,
+    timeout_ms: u16, // 0 = no timeout
+    idle_ms: u16,    // since the last release of a started sequence
+    hold_to_activate: Option<(u32, u16)>, // key, threshold_ms
+    candidate: Option<u8>,                // running_number of the held key press
+    candidate_ms: u16,
+    hold_key_down: bool, // activated by holding, release still to come
+    on_prefix_change: Option<PrefixCallback<'a>>,
+    suggestions: bool,
 }
+type PrefixCallback<'a> = Box<dyn FnMut(&[u32], &mut dyn USBKeyOut) + Send + 'a>;
 impl<'a> Leader<'a> {
     pub fn new<T: AcceptsKeycode>(
         trigger: impl AcceptsKeycode,
         mappings: Vec<(Vec<T>, &'a str)>,
         failure: &'a str,
     ) -> Leader<'a> {
-        //Todo: Figure out how to check for mappings that are prefixes of other mappings
-        //(and therefore impossible) at compile time
-        Leader {
+        Leader::try_new(trigger, mappings, failure).unwrap_or_else(|e| panic!("{:?}", e))
+    }
+    pub fn try_new<T: AcceptsKeycode>(
+        trigger: impl AcceptsKeycode,
+        mappings: Vec<(Vec<T>, &'a str)>,
+        failure: &'a str,
+    ) -> Result<Leader<'a>, LeaderError> {
+        let previews = mappings.iter().map(|(_a, b)| *b).collect();
+        Ok(Leader {
+            previews,
+            ..Leader::try_new_with_actions(
+                trigger,
+                mappings
+                    .into_iter()
+                    .map(|(a, b)| (a, Box::new(b) as Box<dyn Action + 'a>))
+                    .collect(),
+                failure,
+            )?
+        })
+    }
+    pub fn new_with_actions<T: AcceptsKeycode>(
+        trigger: impl AcceptsKeycode,
+        mappings: Vec<(Vec<T>, Box<dyn Action + 'a>)>,
+        failure: &'a str,
+    ) -> Leader<'a> {
+        Leader::try_new_with_actions(trigger, mappings, failure)
+            .unwrap_or_else(|e| panic!("{:?}", e))
+    }
+    pub fn try_new_with_actions<T: AcceptsKeycode>(
+        trigger: impl AcceptsKeycode,
+        mappings: Vec<(Vec<T>, Box<dyn Action + 'a>)>,
+        failure: &'a str,
+    ) -> Result<Leader<'a>, LeaderError> {
+        let leader = Leader {
             trigger: trigger.to_u32(),
             mappings: mappings
                 .into_iter()
                 .map(|(a, b)| (a.into_iter().map(|x| x.to_u32()).collect(), b))
                 .collect(),
+            previews: Vec::new(),
             failure,
             prefix: Vec::new(),
             active: false,
+            timeout_ms: 0,
+            idle_ms: 0,
+            hold_to_activate: None,
+            candidate: None,
+            candidate_ms: 0,
+            hold_key_down: false,
+            on_prefix_change: None,
+            suggestions: false,
+        };
+        leader.check_prefixes()?;
+        Ok(leader)
+    }
+    /// the first mapping that is a strict prefix of another one
+    fn check_prefixes(&self) -> Result<(), LeaderError> {
+        for (shorter, _action) in self.mappings.iter() {
+            for (longer, _action) in self.mappings.iter() {
+                if shorter.len() < longer.len() && longer.starts_with(shorter) {
+                    return Err(LeaderError::PrefixShadow {
+                        shorter: shorter.clone(),
+                        longer: longer.clone(),
+                    });
+                }
+            }
         }
+        Ok(())
+    }
+    pub fn new_held<T: AcceptsKeycode>(
+        key: impl AcceptsKeycode,
+        threshold_ms: u16,
+        mappings: Vec<(Vec<T>, &'a str)>,
+        failure: &'a str,
+    ) -> Leader<'a> {
+        let key = key.to_u32();
+        Leader {
+            hold_to_activate: Some((key, threshold_ms)),
+            ..Leader::new(key, mappings, failure)
+        }
+    }
+    pub fn with_timeout(self, timeout_ms: u16) -> Leader<'a> {
+        Leader { timeout_ms, ..self }
+    }
+    /// call callback with the keys typed so far
+    /// every time a key is added to the sequence
+    pub fn with_prefix_callback(
+        self,
+        callback: impl FnMut(&[u32], &mut dyn USBKeyOut) + Send + 'a,
+    ) -> Leader<'a> {
+        Leader {
+            on_prefix_change: Some(Box::new(callback)),
+            ..self
+        }
+    }
+    /// after every key of a sequence that's not complete yet,
+    /// send the possible completions to output.debug,
+    /// e.g. "Leader: hello, help".
+    ///
+    /// Mappings from new_with_actions() are shown as their key sequence.
+    pub fn with_suggestions(self) -> Leader<'a> {
+        Leader {
+            suggestions: true,
+            ..self
+        }
+    }
+    fn send_suggestions(&self, output: &mut dyn USBKeyOut) {
+        let mut candidates: Vec<String> = Vec::new();
+        for (ii, (seq, _action)) in self.mappings.iter().enumerate() {
+            if seq.len() > self.prefix.len() && seq.starts_with(&self.prefix) {
+                candidates.push(match self.previews.get(ii) {
+                    Some(s) => s.to_string(),
+                    None => {
+                        let keys: Vec<String> = seq.iter().map(|k| describe_keycode(*k)).collect();
+                        keys.join(" ")
+                    }
+                });
+            }
+        }
+        output.debug(&format!("Leader: {}", candidates.join(", ")));
     }
     fn match_prefix(&self) -> MatchResult {
         let mut result = MatchResult::WontMatch;
-        for (seq, out) in self.mappings.iter() {
+        for (ii, (seq, _action)) in self.mappings.iter().enumerate() {
             if seq.len() < self.prefix.len() {
                 continue;
             }
             if self.prefix.iter().zip(seq.iter()).all(|(a, b)| a == b) {
                 if seq.len() == self.prefix.len() {
-                    return MatchResult::Match(out);
+                    return MatchResult::Match(ii);
                 } else {
                     result = MatchResult::NeedsMoreInput;
                 }
@@ -52,17 +198,127 @@ impl<'a> Leader<'a> {
         }
         result
     }
+    /// Decide whether the hold_to_activate key is being tapped or held.
+    ///
+    /// Until that's clear, its press is kept back (Ignored).
+    /// Taps are left unhandled for the downstream handlers,
+    /// holds activate the leader and are swallowed.
+    fn resolve_hold(&mut self, events: &mut [(Event, EventStatus)]) {
+        let (key, threshold_ms) = match self.hold_to_activate {
+            Some(x) => x,
+            None => return,
+        };
+        let mut candidate_index: Option<usize> = None;
+        for ii in 0..events.len() {
+            if !events[ii].1.is_unhandled() {
+                continue;
+            }
+            let mut handle_current = false;
+            let mut activate = false;
+            match &events[ii].0 {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == key
+                        && (kc.flag & 1) == 0
+                        && !self.active
+                        && !self.hold_key_down
+                    {
+                        if self.candidate.is_none() {
+                            self.candidate = Some(kc.running_number);
+                            self.candidate_ms = 0;
+                        }
+                        if self.candidate == Some(kc.running_number) {
+                            candidate_index = Some(ii);
+                        }
+                    } else if candidate_index.is_some() && (kc.flag & 1) == 0 {
+                        // rolling over into another key - it was a tap
+                        self.candidate = None;
+                        candidate_index = None;
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == key {
+                        if self.hold_key_down {
+                            self.hold_key_down = false;
+                            handle_current = true;
+                        } else if candidate_index.is_some() {
+                            self.candidate = None;
+                            if self.candidate_ms.saturating_add(kc.ms_since_last) >= threshold_ms {
+                                handle_current = true;
+                                activate = true;
+                            } else {
+                                candidate_index = None;
+                            }
+                        }
+                    }
+                }
+                Event::TimeOut(ms) => {
+                    if candidate_index.is_some() {
+                        self.candidate_ms = self.candidate_ms.saturating_add(*ms);
+                        if self.candidate_ms >= threshold_ms {
+                            self.candidate = None;
+                            self.hold_key_down = true;
+                            activate = true;
+                        }
+                    }
+                }
+            }
+            if handle_current {
+                events[ii].1 = EventStatus::Handled;
+            }
+            if activate {
+                self.active = true;
+                if let Some(ci) = candidate_index.take() {
+                    events[ci].1 = EventStatus::Handled;
+                }
+            }
+        }
+        if let Some(ci) = candidate_index {
+            events[ci].1 = EventStatus::Ignored;
+        }
+    }
 }
 impl<T: USBKeyOut> ProcessKeys<T> for Leader<'_> {
-    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) {
+    fn describe(&self) -> String {
+        match self.hold_to_activate {
+            Some((key, threshold_ms)) => format!(
+                "Leader(held: {} >= {}ms, {} sequences)",
+                describe_keycode(key),
+                threshold_ms,
+                self.mappings.len()
+            ),
+            None => format!(
+                "Leader(trigger: {}, {} sequences)",
+                describe_keycode(self.trigger),
+                self.mappings.len()
+            ),
+        }
+    }
+    fn reset(&mut self, _output: &mut T) {
+        self.active = false;
+        self.prefix.clear();
+        self.idle_ms = 0;
+        self.candidate = None;
+        self.candidate_ms = 0;
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        self.resolve_hold(events);
+        let has_trigger = self.hold_to_activate.is_none();
         for (event, status) in iter_unhandled_mut(events) {
             match event {
                 Event::KeyRelease(kc) => {
                     if self.active {
+                        self.idle_ms = 0;
                         self.prefix.push(kc.keycode);
+                        if let Some(callback) = self.on_prefix_change.as_mut() {
+                            callback(&self.prefix, output);
+                        }
                         match self.match_prefix() {
-                            MatchResult::Match(s) => {
-                                output.send_string(s);
+                            MatchResult::Match(ii) => {
+                                self.mappings[ii].1.on_trigger(output);
                                 self.active = false;
                                 self.prefix.clear()
                             }
@@ -71,10 +327,14 @@ impl<T: USBKeyOut> ProcessKeys<T> for Leader<'_> {
                                 self.active = false;
                                 self.prefix.clear()
                             }
-                            MatchResult::NeedsMoreInput => {}
+                            MatchResult::NeedsMoreInput => {
+                                if self.suggestions {
+                                    self.send_suggestions(output);
+                                }
+                            }
                         }
                         *status = EventStatus::Handled;
-                    } else if kc.keycode == self.trigger {
+                    } else if has_trigger && kc.keycode == self.trigger {
                         if !self.active {
                             self.active = true;
                         }
@@ -82,23 +342,34 @@ impl<T: USBKeyOut> ProcessKeys<T> for Leader<'_> {
                     }
                 }
                 Event::KeyPress(kc) => {
-                    if kc.keycode == self.trigger {
+                    if has_trigger && kc.keycode == self.trigger {
                         *status = EventStatus::Handled;
                     } else if self.active {
                         // while active, we eat all KeyPresses and only parse KeyRelease
                         *status = EventStatus::Handled;
+                        self.idle_ms = self.idle_ms.saturating_add(kc.ms_since_last);
+                    }
+                }
+                Event::TimeOut(ms_since_last) => {
+                    if self.active && !self.prefix.is_empty() && self.timeout_ms > 0 {
+                        self.idle_ms = self.idle_ms.saturating_add(*ms_since_last);
+                        if self.idle_ms > self.timeout_ms {
+                            output.send_string(self.failure);
+                            self.active = false;
+                            self.prefix.clear()
+                        }
                     }
                 }
-                Event::TimeOut(_) => {}
             }
         }
+        HandlerResult::NoOp
     }
 }
 #[cfg(test)]
 //#[macro_use]
 //extern crate std;
 mod tests {
-    use crate::handlers::{leader::MatchResult, Leader, USBKeyboard};
+    use crate::handlers::{leader::MatchResult, Leader, LeaderError, USBKeyboard};
     #[allow(unused_imports)]
     use crate::key_codes::KeyCode;
     #[allow(unused_imports)]
@@ -112,14 +383,11 @@ mod tests {
     #[test]
     fn test_leader() {
         use crate::key_codes::KeyCode::*;
-        use core::convert::TryInto;
         let mut l = Leader::new(
             KeyCode::X,
             vec![
                 (vec![A, B, C], "A"),
                 (vec![A, B, D], "B"),
-                //Todo: check that none is a prefix of another!
-                //(vec![A], "C"),
             ],
             "E",
         );
@@ -131,7 +399,7 @@ mod tests {
         l.prefix.push(C.into());
         assert!(match l.match_prefix() {
             MatchResult::Match(m) => {
-                assert!(m == "A");
+                assert!(m == 0);
                 true
             }
             _ => false,
@@ -169,7 +437,7 @@ mod tests {
         keyboard.add_keyrelease(KeyCode::C, 0);
         keyboard.handle_keys().unwrap();
         dbg!(&keyboard.output.reports);
-        check_output(&keyboard, &[&[65u8.try_into().unwrap()], &[]]);
+        check_output(&keyboard, &[&[Kb4], &[Kb1], &[]]);
         keyboard.output.clear();
         keyboard.add_keypress(KeyCode::F, 0);
         keyboard.handle_keys().unwrap();
@@ -190,8 +458,246 @@ mod tests {
         keyboard.add_keyrelease(KeyCode::C, 0);
         keyboard.handle_keys().unwrap();
         dbg!(&keyboard.output.reports);
-        check_output(&keyboard, &[&[69u8.try_into().unwrap()], &[]]);
+        check_output(&keyboard, &[&[Kb4], &[Kb5], &[]]);
+    }
+
+    #[test]
+    fn test_leader_prefix_shadow() {
+        use crate::key_codes::KeyCode::*;
+        assert!(Leader::try_new(
+            KeyCode::X,
+            vec![(vec![A, B], "AB"), (vec![A, C], "AC"), (vec![B], "B")],
+            "E"
+        )
+        .is_ok());
+        match Leader::try_new(KeyCode::X, vec![(vec![A, B], "AB"), (vec![A], "A")], "E") {
+            Err(e) => {
+                assert!(
+                    e == LeaderError::PrefixShadow {
+                        shorter: vec![A.to_u32()],
+                        longer: vec![A.to_u32(), B.to_u32()]
+                    }
+                )
+            }
+            Ok(_) => panic!("[A] shadows [A, B]"),
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_leader_prefix_shadow_panics() {
+        use crate::key_codes::KeyCode::*;
+        Leader::new(KeyCode::X, vec![(vec![A], "A"), (vec![A, B], "AB")], "E");
     }
-}
 
-*/
+    #[test]
+    fn test_leader_actions() {
+        use crate::handlers::{Action, AutoOff, Layer};
+        use crate::premade::ActionToggleHandler;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.future_handler_id(2);
+        keyboard.add_handler(Box::new(Leader::new_with_actions(
+            KeyCode::X,
+            vec![
+                (vec![KeyCode::A], Box::new("A") as Box<dyn Action>),
+                (
+                    vec![KeyCode::B],
+                    Box::new(ActionToggleHandler { id: layer_id }),
+                ),
+            ],
+            "E",
+        )));
+        keyboard.add_handler(Box::new(Layer::new(
+            Vec::<(u32, _)>::new(),
+            AutoOff::No,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        keyboard.pc(KeyCode::A, &[&[]]);
+        keyboard.rc(KeyCode::A, &[&[KeyCode::Kb4], &[KeyCode::Kb1], &[]]);
+
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        keyboard.pc(KeyCode::B, &[&[]]);
+        keyboard.rc(KeyCode::B, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(layer_id));
+    }
+
+    #[test]
+    fn test_leader_prefix_callback() {
+        use crate::test_helpers::Checks;
+        use alloc::sync::Arc;
+        use spin::RwLock;
+        let seen: Arc<RwLock<Vec<Vec<u32>>>> = Arc::new(RwLock::new(Vec::new()));
+        let seen2 = seen.clone();
+        let l = Leader::new(KeyCode::X, vec![(vec![KeyCode::A, KeyCode::B], "A")], "E")
+            .with_prefix_callback(move |prefix, _output| seen2.write().push(prefix.to_vec()));
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        assert!(seen.read().is_empty());
+        keyboard.pc(KeyCode::A, &[&[]]);
+        keyboard.rc(KeyCode::A, &[&[]]);
+        keyboard.pc(KeyCode::B, &[&[]]);
+        keyboard.rc(KeyCode::B, &[&[KeyCode::Kb4], &[KeyCode::Kb1], &[]]);
+        assert!(
+            *seen.read()
+                == vec![
+                    vec![KeyCode::A.to_u32()],
+                    vec![KeyCode::A.to_u32(), KeyCode::B.to_u32()]
+                ]
+        );
+    }
+
+    #[test]
+    fn test_leader_timeout() {
+        let l = Leader::new(KeyCode::X, vec![(vec![KeyCode::A, KeyCode::B], "A")], "E")
+            .with_timeout(500);
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        keyboard.add_keypress(KeyCode::X, 0);
+        keyboard.add_keyrelease(KeyCode::X, 0);
+        keyboard.handle_keys().unwrap();
+        //no prefix yet - no timeout
+        keyboard.add_timeout(501);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.reports.is_empty());
+
+        keyboard.add_keypress(KeyCode::A, 0);
+        keyboard.add_keyrelease(KeyCode::A, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_timeout(500);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.reports.is_empty());
+        keyboard.add_timeout(1);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::Kb4], &[KeyCode::Kb5]]);
+        assert!(keyboard.events.is_empty());
+        keyboard.output.clear();
+
+        //the TimeOuts add up
+        keyboard.add_keypress(KeyCode::X, 0);
+        keyboard.add_keyrelease(KeyCode::X, 0);
+        keyboard.add_keypress(KeyCode::A, 0);
+        keyboard.add_keyrelease(KeyCode::A, 0);
+        keyboard.handle_keys().unwrap();
+        for _ in 0..5 {
+            keyboard.add_timeout(100);
+            keyboard.handle_keys().unwrap();
+        }
+        assert!(keyboard.output.reports.is_empty());
+        keyboard.add_timeout(100);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::Kb4], &[KeyCode::Kb5]]);
+        assert!(keyboard.events.is_empty());
+        //no longer active - key presses are not eaten anymore
+        keyboard.add_keypress(KeyCode::A, 0);
+        assert!(keyboard.handle_keys().is_err());
+    }
+
+    #[test]
+    fn test_leader_held_tap() {
+        use crate::test_helpers::Checks;
+        let l = Leader::new_held(KeyCode::Space, 200, vec![(vec![KeyCode::A], "A")], "E");
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+
+        //quick tap - passed through on release
+        keyboard.pc(KeyCode::Space, &[&[]]);
+        keyboard.tc(100, &[&[]]);
+        keyboard.rct(KeyCode::Space, 50, &[&[KeyCode::Space]]);
+        assert!(keyboard.events.is_empty());
+        keyboard.pc(KeyCode::A, &[&[KeyCode::A]]);
+        keyboard.rc(KeyCode::A, &[&[]]);
+
+        //rolling into another key - passed through in order
+        keyboard.pc(KeyCode::Space, &[&[]]);
+        keyboard.pc(KeyCode::B, &[&[KeyCode::Space, KeyCode::B]]);
+        keyboard.rc(KeyCode::Space, &[&[KeyCode::B]]);
+        keyboard.rc(KeyCode::B, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_leader_held_activates() {
+        use crate::test_helpers::Checks;
+        let l = Leader::new_held(KeyCode::Space, 200, vec![(vec![KeyCode::A], "A")], "E");
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+
+        keyboard.pc(KeyCode::Space, &[&[]]);
+        keyboard.tc(100, &[&[]]);
+        keyboard.tc(100, &[&[]]);
+        //held past the threshold - swallowed, leader active
+        keyboard.rc(KeyCode::Space, &[&[]]);
+        assert!(keyboard.events.is_empty());
+        keyboard.pc(KeyCode::A, &[&[]]);
+        keyboard.rc(KeyCode::A, &[&[KeyCode::Kb4], &[KeyCode::Kb1], &[]]);
+
+        //a slow release without timeouts also counts as held
+        keyboard.pc(KeyCode::Space, &[&[]]);
+        keyboard.rct(KeyCode::Space, 250, &[&[]]);
+        keyboard.pc(KeyCode::B, &[&[]]);
+        keyboard.rc(KeyCode::B, &[&[KeyCode::Kb4], &[KeyCode::Kb5], &[]]);
+
+        //and back to normal
+        keyboard.pc(KeyCode::B, &[&[KeyCode::B]]);
+        keyboard.rc(KeyCode::B, &[&[]]);
+    }
+
+    #[test]
+    fn test_leader_suggestions() {
+        use crate::key_codes::KeyCode::*;
+        use crate::test_helpers::Checks;
+        let l = Leader::new(
+            X,
+            vec![(vec![A, B], "hello"), (vec![A, C], "help"), (vec![D], "world")],
+            "E",
+        )
+        .with_suggestions();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        keyboard.pc(X, &[&[]]);
+        keyboard.rc(X, &[&[]]);
+        keyboard.pc(A, &[&[]]);
+        keyboard.add_keyrelease(A, 0);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.debug_messages == vec!["Leader: hello, help".to_string()]);
+        keyboard.output.clear();
+
+        //actions are shown as their sequence
+        let l = Leader::new_with_actions(
+            X,
+            vec![
+                (vec![A, B], Box::new("hello") as Box<dyn crate::handlers::Action>),
+                (vec![A, C], Box::new("help")),
+            ],
+            "E",
+        )
+        .with_suggestions();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(X, &[&[]]);
+        keyboard.rc(X, &[&[]]);
+        keyboard.pc(A, &[&[]]);
+        keyboard.add_keyrelease(A, 0);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.debug_messages == vec!["Leader: A B, A C".to_string()]);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::handlers::Action;
 use crate::handlers::{ProcessKeys, HandlerResult};
-use crate::key_codes::AcceptsKeycode;
+use crate::key_codes::{describe_keycode, AcceptsKeycode};
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
 use crate::USBKeyOut;
 use no_std_compat::prelude::v1::*;
@@ -37,6 +37,13 @@ impl<M1: Action, M2: Action> LongTap<M1, M2> {
 }
 
 impl<T: USBKeyOut, M1: Action, M2: Action> ProcessKeys<T> for LongTap<M1, M2> {
+    fn describe(&self) -> String {
+        format!(
+            "LongTap(trigger: {}, threshold_ms: {})",
+            describe_keycode(self.trigger),
+            self.threshold_ms
+        )
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
         for (event, status) in iter_unhandled_mut(events).rev() {
             match event {
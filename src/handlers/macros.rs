@@ -1,6 +1,6 @@
 use crate::handlers::{Action, OnOff};
 use crate::handlers::{ProcessKeys, HandlerResult};
-use crate::key_codes::AcceptsKeycode;
+use crate::key_codes::{describe_keycode, AcceptsKeycode, KeyCode};
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
 use crate::USBKeyOut;
 use no_std_compat::prelude::v1::*;
@@ -23,6 +23,9 @@ impl<M: Action> PressMacro<M> {
     }
 }
 impl<T: USBKeyOut, M: Action> ProcessKeys<T> for PressMacro<M> {
+    fn describe(&self) -> String {
+        format!("PressMacro(trigger: {})", describe_keycode(self.keycode))
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
         for (event, status) in iter_unhandled_mut(events) {
             match event {
@@ -47,38 +50,119 @@ impl<T: USBKeyOut, M: Action> ProcessKeys<T> for PressMacro<M> {
 
 
 
+/// Send groups of keys with a delay (via send_keys_later),
+/// e.g. for hosts that need time between the steps of a macro.
+///
+/// Each group is a report, sent ms after the previous one -
+/// include empty groups to release the keys.
+/// Your firmware has to call do_send_later regularly.
+pub struct DelayedMacro {
+    keycode: u32,
+    steps: Vec<(Vec<KeyCode>, u16)>, // keys, ms after the previous step
+}
+impl DelayedMacro {
+    pub fn new(trigger: impl AcceptsKeycode, steps: Vec<(Vec<KeyCode>, u16)>) -> DelayedMacro {
+        DelayedMacro {
+            keycode: trigger.to_u32(),
+            steps,
+        }
+    }
+}
+impl<T: USBKeyOut> ProcessKeys<T> for DelayedMacro {
+    fn describe(&self) -> String {
+        format!(
+            "DelayedMacro(trigger: {}, {} steps)",
+            describe_keycode(self.keycode),
+            self.steps.len()
+        )
+    }
+    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.keycode {
+                        *status = EventStatus::Handled;
+                        for (keys, ms) in self.steps.iter() {
+                            output.send_keys_later(keys, *ms);
+                        }
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.keycode {
+                        *status = EventStatus::Handled;
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
 /// A simple callback -
 /// call on_press(output: impl USBKeyOut) on key press
 /// and on_release(output) on release))
 /// trigger may be any keycode,
 /// but consider using the constants in UserKey::*
 /// which is not used by either UnicodeKeyboard or UsbKeyboard
+///
+/// If the handler is disabled while the trigger is pressed,
+/// on_deactivate is called right away.
+///
+/// With new_ex(.., fire_on_release: true), nothing happens on the press
+/// and on_activate is called on the release instead (on_deactivate is never
+/// called) - e.g. for actions that should not fire while the key
+/// is just being held.
 pub struct PressReleaseMacro<M> {
     keycode: u32,
     callbacks: M,
+    active: bool,
+    fire_on_release: bool,
 }
 impl<M: OnOff> PressReleaseMacro<M> {
     pub fn new(trigger: impl AcceptsKeycode, callbacks: M) -> PressReleaseMacro<M> {
+        PressReleaseMacro::new_ex(trigger, callbacks, false)
+    }
+    pub fn new_ex(
+        trigger: impl AcceptsKeycode,
+        callbacks: M,
+        fire_on_release: bool,
+    ) -> PressReleaseMacro<M> {
         PressReleaseMacro {
             keycode: trigger.to_u32(),
             callbacks,
+            active: false,
+            fire_on_release,
         }
     }
 }
 impl<T: USBKeyOut, M: OnOff> ProcessKeys<T> for PressReleaseMacro<M> {
+    fn describe(&self) -> String {
+        format!("PressReleaseMacro(trigger: {})", describe_keycode(self.keycode))
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
         for (event, status) in iter_unhandled_mut(events) {
             match event {
                 Event::KeyPress(kc) => {
                     if kc.keycode == self.keycode {
                         *status = EventStatus::Handled;
-                        self.callbacks.on_activate(output);
+                        self.active = true;
+                        if !self.fire_on_release {
+                            self.callbacks.on_activate(output);
+                        }
                     }
                 }
                 Event::KeyRelease(kc) => {
                     if kc.keycode == self.keycode {
                         *status = EventStatus::Handled;
-                        self.callbacks.on_deactivate(output);
+                        if self.active {
+                            self.active = false;
+                            if self.fire_on_release {
+                                self.callbacks.on_activate(output);
+                            } else {
+                                self.callbacks.on_deactivate(output);
+                            }
+                        }
                     }
                 }
                 Event::TimeOut(_) => {}
@@ -86,6 +170,14 @@ impl<T: USBKeyOut, M: OnOff> ProcessKeys<T> for PressReleaseMacro<M> {
         }
     HandlerResult::NoOp
     }
+    fn on_disabled(&mut self, output: &mut T) {
+        if self.active {
+            self.active = false;
+            if !self.fire_on_release {
+                self.callbacks.on_deactivate(output);
+            }
+        }
+    }
 }
 
 /// a macro that is called 'on' on the the first keypress
@@ -109,6 +201,9 @@ impl<M: OnOff> StickyMacro<M> {
 }
 
 impl<T: USBKeyOut, M: OnOff> ProcessKeys<T> for StickyMacro<M> {
+    fn describe(&self) -> String {
+        format!("StickyMacro(trigger: {})", describe_keycode(self.keycode))
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) ->HandlerResult {
         for (event, status) in iter_unhandled_mut(events) {
             //a sticky key
@@ -144,7 +239,7 @@ impl<T: USBKeyOut, M: OnOff> ProcessKeys<T> for StickyMacro<M> {
 //#[macro_use]
 //extern crate std;
 mod tests {
-    use crate::handlers::{PressReleaseMacro, StickyMacro, USBKeyboard};
+    use crate::handlers::{DelayedMacro, PressReleaseMacro, StickyMacro, USBKeyboard};
     #[allow(unused_imports)]
     use crate::key_codes::{KeyCode, UserKey};
     #[allow(unused_imports)]
@@ -184,6 +279,76 @@ mod tests {
         keyboard.output.clear();
     }
 
+    #[test]
+    fn test_press_release_fire_on_release() {
+        for fire_on_release in [false, true].iter() {
+            let counter = Arc::new(RwLock::new(PressCounter {
+                down_counter: 0,
+                up_counter: 0,
+            }));
+            let t = PressReleaseMacro::new_ex(UserKey::UK0, counter.clone(), *fire_on_release);
+            let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+            keyboard.add_handler(Box::new(t));
+            keyboard.add_handler(Box::new(USBKeyboard::new()));
+            keyboard.add_keypress(UserKey::UK0, 0);
+            keyboard.handle_keys().unwrap();
+            if *fire_on_release {
+                assert!(counter.read().down_counter == 0);
+                check_output(&keyboard, &[&[]]);
+            } else {
+                assert!(counter.read().down_counter == 1);
+                check_output(&keyboard, &[&[KeyCode::H], &[]]);
+            }
+            keyboard.output.clear();
+
+            keyboard.add_keyrelease(UserKey::UK0, 0);
+            keyboard.handle_keys().unwrap();
+            assert!(counter.read().down_counter == 1);
+            if *fire_on_release {
+                assert!(counter.read().up_counter == 0);
+                check_output(&keyboard, &[&[KeyCode::H], &[]]);
+            } else {
+                assert!(counter.read().up_counter == 1);
+                check_output(&keyboard, &[&[KeyCode::I], &[]]);
+            }
+            assert!(keyboard.events.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_press_release_disabled_while_pressed() {
+        let counter = Arc::new(RwLock::new(PressCounter {
+            down_counter: 0,
+            up_counter: 0,
+        }));
+        let t = PressReleaseMacro::new(UserKey::UK0, counter.clone());
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let id = keyboard.add_handler(Box::new(t));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(UserKey::UK0, 0);
+        keyboard.handle_keys().unwrap();
+        assert!(counter.read().down_counter == 1);
+        assert!(counter.read().up_counter == 0);
+        keyboard.output.clear();
+
+        keyboard.output.state().disable_handler(id);
+        keyboard.add_timeout(10);
+        keyboard.handle_keys().unwrap();
+        assert!(counter.read().down_counter == 1);
+        assert!(counter.read().up_counter == 1);
+        check_output(&keyboard, &[&[KeyCode::I], &[]]);
+        keyboard.output.clear();
+
+        //nothing more once reenabled and released
+        keyboard.add_timeout(10);
+        keyboard.handle_keys().unwrap();
+        keyboard.output.state().enable_handler(id);
+        keyboard.add_keyrelease(UserKey::UK0, 0);
+        keyboard.handle_keys().unwrap();
+        assert!(counter.read().down_counter == 1);
+        assert!(counter.read().up_counter == 1);
+    }
+
     #[test]
     fn test_sticky_macro() {
         let counter = Arc::new(RwLock::new(PressCounter {
@@ -222,4 +387,30 @@ mod tests {
         assert!(counter.read().down_counter == 1);
         assert!(counter.read().up_counter == 1);
     }
+
+    #[test]
+    fn test_delayed_macro() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(DelayedMacro::new(
+            UserKey::UK0,
+            vec![(vec![LCtrl, A], 0), (vec![], 20), (vec![X], 50), (vec![], 20)],
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(UserKey::UK0, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keyrelease(UserKey::UK0, 0);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.events.is_empty());
+        //nothing but the USBKeyboard's reports so far
+        check_output(&keyboard, &[&[], &[]]);
+        assert!(
+            keyboard.output.later
+                == vec![(0, vec![LCtrl, A]), (20, vec![]), (50, vec![X]), (20, vec![])]
+        );
+        keyboard.output.reports.clear();
+        keyboard.output.do_send_later();
+        check_output(&keyboard, &[&[LCtrl, A], &[], &[X], &[]]);
+        assert!(keyboard.output.later.is_empty());
+    }
 }
@@ -2,33 +2,69 @@ use crate::key_codes::KeyCode;
 use crate::{Event, EventStatus};
 use no_std_compat::prelude::v1::*;
 
+mod autorepeat;
 mod autoshift;
+mod bootloader;
+mod capsword;
+mod chordlayer;
+mod combo;
+mod consumer;
+mod debounce;
+mod escalatinghold;
+mod group;
+mod holdemit;
+mod homerow;
 mod layer;
 mod leader;
 mod longtap;
 mod macros;
+mod modtap;
+mod mousekeys;
+mod multimodifier;
 mod oneshot;
+mod repeat;
 mod rewrite_layer;
 mod sequence;
 mod spacecadet;
+mod swaphands;
 mod tapdance;
+mod tapdancehold;
 mod unicodekeyboard;
 mod usbkeyboard;
+mod watchdog;
 pub mod debug_handlers;
 
 use crate::USBKeyOut;
+pub use autorepeat::AutoRepeat;
 pub use autoshift::AutoShift;
+pub use bootloader::BootloaderChord;
+pub use capsword::CapsWord;
+pub use chordlayer::ChordLayer;
+pub use combo::Combo;
+pub use consumer::{consumer_usage, ConsumerControl, CONSUMER_USAGES};
+pub use debounce::Debounce;
+pub use escalatinghold::EscalatingHold;
+pub use group::HandlerGroup;
+pub use holdemit::HoldEmit;
+pub use homerow::HomeRowMods;
 pub use layer::{Layer, LayerAction, AutoOff};
-pub use rewrite_layer::RewriteLayer;
-//pub use leader::Leader;
+pub use rewrite_layer::{RewriteLayer, RewriteLayerShifted};
+pub use leader::{Leader, LeaderError};
 pub use longtap::LongTap;
-pub use macros::{PressMacro, PressReleaseMacro, StickyMacro};
+pub use macros::{DelayedMacro, PressMacro, PressReleaseMacro, StickyMacro};
+pub use modtap::ModTap;
+pub use mousekeys::{MouseAction, MouseKeys};
+pub use multimodifier::MultiModifier;
 pub use oneshot::OneShot;
+pub use repeat::{RepeatChord, RepeatKey};
 pub use sequence::Sequence;
-pub use spacecadet::SpaceCadet;
+pub use spacecadet::{SpaceCadet, SpaceCadetAction, SpaceCadetHold};
+pub use swaphands::SwapHands;
 pub use tapdance::{TapDance, TapDanceAction, TapDanceEnd};
-pub use unicodekeyboard::UnicodeKeyboard;
-pub use usbkeyboard::USBKeyboard;
+pub use tapdancehold::TapDanceHold;
+pub use unicodekeyboard::{UnicodeKeyboard, UnicodeKeyboardEx};
+pub use usbkeyboard::{USBKeyboard, USBKeyboardMaxRollover};
+pub use watchdog::ModifierWatchdog;
 /// Handlers are defined by this trait
 ///
 /// they process the events, set their status to either Handled or Ignored
@@ -40,6 +76,47 @@ pub trait ProcessKeys<T: USBKeyOut> {
     fn default_enabled(&self) -> bool {
         true
     }
+    /// called by Keyboard::add_handler, e.g. to register
+    /// keyboard wide information in output.state()
+    fn on_added(&mut self, _output: &mut T) {}
+    /// called by the Keyboard when this handler has been enabled
+    /// (on the next handle_keys, before process_keys)
+    fn on_enabled(&mut self, _output: &mut T) {}
+    /// called by the Keyboard when this handler has been disabled
+    /// (on the next handle_keys), so it can clean up it's state
+    ///
+    /// A handler that is disabled and enabled again before
+    /// the Keyboard checks sees neither.
+    fn on_disabled(&mut self, _output: &mut T) {}
+    /// called instead of process_keys while this handler is disabled,
+    /// so it can finish what it started - e.g. Layers rewrite the release
+    /// of keys whose press they rewrote before being disabled
+    fn process_keys_while_disabled(
+        &mut self,
+        _events: &mut Vec<(Event, EventStatus)>,
+        _output: &mut T,
+    ) {
+    }
+    /// a human readable description of this handler for troubleshooting,
+    /// e.g. it's triggers and thresholds. Defaults to the type name.
+    fn describe(&self) -> String {
+        core::any::type_name::<Self>().to_string()
+    }
+    /// forget any half finished state (e.g. a running tap dance)
+    /// without firing it - see Keyboard::reset_handler.
+    /// Something that is currently active (e.g. a OneShot modifier) is deactivated.
+    fn reset(&mut self, _output: &mut T) {}
+    /// a short name for debug output, see Keyboard::add_named_handler
+    /// and Keyboard::handler_name
+    fn name(&self) -> &str {
+        "unnamed"
+    }
+    /// whether this handler still runs while the keyboard is
+    /// in passthrough mode (KeyboardState::set_passthrough),
+    /// which skips all other handlers
+    fn runs_in_passthrough(&self) -> bool {
+        false
+    }
 }
 
 pub enum HandlerResult {
@@ -92,6 +169,11 @@ impl Action for Vec<KeyCode> {
 pub trait OnOff {
     fn on_activate(&mut self, output: &mut dyn USBKeyOut);
     fn on_deactivate(&mut self, output: &mut dyn USBKeyOut);
+    /// on_activate, knowing which key triggered it
+    /// (e.g. the left or the right one of a OneShot)
+    fn on_activate_by(&mut self, output: &mut dyn USBKeyOut, _trigger: u32) {
+        self.on_activate(output);
+    }
 }
 
 
@@ -0,0 +1,306 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::{describe_keycode, AcceptsKeycode, KeyCode};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::{Modifier, USBKeyOut};
+use no_std_compat::prelude::v1::*;
+
+#[repr(u8)]
+#[derive(Clone, Copy)]
+enum ModTapState {
+    Base,       //not triggered
+    Pressed,    //could be either a tap or a hold
+    Held,       //modifier is set
+    PressedTap, //was a tap, waiting for the release
+    Repeating,  //quick tap - tap_keycode is held until the release
+}
+
+/// A dual function key - a keycode on tap,
+/// a plain modifier when held.
+///
+/// Tap (release before tapping_term_ms): tap_keycode is registered.
+/// Held past tapping_term_ms (or another key pressed after that),
+/// hold_modifier is set until the trigger is released.
+///
+/// If another key is pressed before the tapping term (fast rolling),
+/// the tap_keycode is sent right away, followed by the other key.
+///
+/// Use new_global_timeout to use KeyboardState::tapping_term_ms
+/// (falling back to 200ms if that is not set).
+///
+/// With with_quick_tap_term(), pressing the trigger again within
+/// quick_tap_term_ms of a tap holds down the tap_keycode instead
+/// (so the OS repeats it), no matter how long it's held.
+pub struct ModTap {
+    trigger: u32,
+    tap_keycode: KeyCode,
+    hold_modifier: Modifier,
+    tapping_term_ms: u16,
+    tapping_term_from_state: bool,
+    state: ModTapState,
+    held_ms: u16,
+    quick_tap_term_ms: u16,    // 0 = off
+    since_tap_ms: Option<u16>, // time since the last tap's release
+}
+impl ModTap {
+    pub fn new(
+        trigger: impl AcceptsKeycode,
+        tap_keycode: KeyCode,
+        hold_modifier: Modifier,
+        tapping_term_ms: u16,
+    ) -> ModTap {
+        ModTap {
+            trigger: trigger.to_u32(),
+            tap_keycode,
+            hold_modifier,
+            tapping_term_ms,
+            tapping_term_from_state: false,
+            state: ModTapState::Base,
+            held_ms: 0,
+            quick_tap_term_ms: 0,
+            since_tap_ms: None,
+        }
+    }
+    pub fn new_global_timeout(
+        trigger: impl AcceptsKeycode,
+        tap_keycode: KeyCode,
+        hold_modifier: Modifier,
+    ) -> ModTap {
+        ModTap {
+            tapping_term_from_state: true,
+            ..ModTap::new(trigger, tap_keycode, hold_modifier, 200)
+        }
+    }
+    pub fn with_quick_tap_term(self, quick_tap_term_ms: u16) -> ModTap {
+        ModTap {
+            quick_tap_term_ms,
+            ..self
+        }
+    }
+    fn is_quick_tap(&self, ms_since_last: u16) -> bool {
+        match self.since_tap_ms {
+            Some(ms) => {
+                self.quick_tap_term_ms > 0
+                    && ms.saturating_add(ms_since_last) <= self.quick_tap_term_ms
+            }
+            None => false,
+        }
+    }
+}
+impl<T: USBKeyOut> ProcessKeys<T> for ModTap {
+    fn describe(&self) -> String {
+        format!(
+            "ModTap(trigger: {}, tap: {:?}, tapping_term_ms: {})",
+            describe_keycode(self.trigger),
+            self.tap_keycode,
+            self.tapping_term_ms
+        )
+    }
+    fn on_disabled(&mut self, output: &mut T) {
+        if let ModTapState::Held = self.state {
+            output.state().set_modifier(self.hold_modifier, false);
+        }
+        self.state = ModTapState::Base;
+        self.since_tap_ms = None;
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        let tapping_term_ms = if self.tapping_term_from_state {
+            output.ro_state().tapping_term_ms.unwrap_or(self.tapping_term_ms)
+        } else {
+            self.tapping_term_ms
+        };
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        if self.is_quick_tap(kc.ms_since_last) {
+                            self.state = ModTapState::Repeating;
+                        } else {
+                            self.state = ModTapState::Pressed;
+                        }
+                        self.held_ms = 0;
+                        self.since_tap_ms = None;
+                    } else if kc.flag & 0x1 == 0 {
+                        self.since_tap_ms = None;
+                        if let ModTapState::Pressed = self.state {
+                            if self.held_ms.saturating_add(kc.ms_since_last) >= tapping_term_ms
+                            {
+                                self.state = ModTapState::Held;
+                                output.state().set_modifier(self.hold_modifier, true);
+                            } else {
+                                //rolling over
+                                self.state = ModTapState::PressedTap;
+                                output.register_key(self.tap_keycode);
+                            }
+                        }
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        match self.state {
+                            ModTapState::Pressed => {
+                                if self.held_ms.saturating_add(kc.ms_since_last)
+                                    < tapping_term_ms
+                                {
+                                    output.register_key(self.tap_keycode);
+                                    self.since_tap_ms = Some(0);
+                                }
+                            }
+                            ModTapState::Held => {
+                                output.state().set_modifier(self.hold_modifier, false);
+                            }
+                            ModTapState::Base
+                            | ModTapState::PressedTap
+                            | ModTapState::Repeating => {}
+                        }
+                        self.state = ModTapState::Base;
+                    } else if let Some(ms) = self.since_tap_ms {
+                        self.since_tap_ms = Some(ms.saturating_add(kc.ms_since_last));
+                    }
+                }
+                Event::TimeOut(ms_since_last) => {
+                    if let Some(ms) = self.since_tap_ms {
+                        self.since_tap_ms = Some(ms.saturating_add(*ms_since_last));
+                    }
+                    if let ModTapState::Pressed = self.state {
+                        self.held_ms = self.held_ms.saturating_add(*ms_since_last);
+                        if self.held_ms >= tapping_term_ms {
+                            self.state = ModTapState::Held;
+                            output.state().set_modifier(self.hold_modifier, true);
+                        }
+                    }
+                }
+            }
+        }
+        if let ModTapState::Repeating = self.state {
+            //the press is gone, so keep the key in every report ourselves
+            output.register_key(self.tap_keycode);
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{ModTap, USBKeyboard};
+    use crate::key_codes::{KeyCode, UserKey};
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Modifier::*;
+    use crate::{Keyboard, USBKeyOut};
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_modtap_tap() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(ModTap::new(UserKey::UK0, KeyCode::F, Shift, 200)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[F]]);
+        keyboard.tc(10, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+
+        //fast rolling
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.pct(A, 20, &[&[F, A]]);
+        keyboard.rc(UserKey::UK0, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+    }
+
+    #[test]
+    fn test_modtap_hold() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(ModTap::new(UserKey::UK0, KeyCode::F, Shift, 200)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.pct(A, 250, &[&[LShift, A]]);
+        keyboard.rc(A, &[&[LShift]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+
+        //held via timeouts
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.tc(100, &[&[]]);
+        keyboard.tc(100, &[&[LShift]]);
+        assert!(keyboard.output.state().modifier(Shift));
+        keyboard.pc(A, &[&[LShift, A]]);
+        keyboard.rc(A, &[&[LShift]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+    }
+
+    #[test]
+    fn test_modtap_global_timeout() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(ModTap::new_global_timeout(UserKey::UK0, KeyCode::F, Shift)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().tapping_term_ms = Some(400);
+        //a tap past the default
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.tc(300, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[F]]);
+        //held past the global term
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.tc(300, &[&[]]);
+        keyboard.tc(100, &[&[LShift]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+
+        //falls back to the default
+        keyboard.output.state().tapping_term_ms = None;
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.tc(200, &[&[LShift]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_modtap_quick_tap_repeat() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(
+            ModTap::new(UserKey::UK0, KeyCode::F, Shift, 200).with_quick_tap_term(150),
+        ));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[F]]);
+        //tap then hold - F is held, not shift
+        keyboard.tc(50, &[&[]]);
+        keyboard.pct(UserKey::UK0, 50, &[&[F]]);
+        keyboard.tc(150, &[&[F]]);
+        keyboard.tc(150, &[&[F]]);
+        keyboard.pc(A, &[&[F, A]]);
+        keyboard.rc(A, &[&[F]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        keyboard.tc(10, &[&[]]);
+
+        //outside of the quick tap term - a regular hold
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[F]]);
+        keyboard.tc(100, &[&[]]);
+        keyboard.pct(UserKey::UK0, 100, &[&[]]);
+        keyboard.tc(200, &[&[LShift]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+
+        //another key in between - a regular hold
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[F]]);
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.tc(200, &[&[LShift]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+        assert!(keyboard.events.is_empty());
+    }
+}
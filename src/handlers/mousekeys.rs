@@ -0,0 +1,162 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::AcceptsKeycode;
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::USBKeyOut;
+use no_std_compat::prelude::v1::*;
+
+/// What a MouseKeys key does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseAction {
+    /// hold a button - bitmask, 1: left, 2: right, 4: middle
+    Button(u8),
+    /// move the pointer (dx, dy) on press and on every TimeOut while held
+    Move(i8, i8),
+    /// scroll on press and on every TimeOut while held
+    Wheel(i8),
+}
+
+/// Control the mouse from the keyboard.
+///
+/// Output goes through USBKeyOut::send_mouse,
+/// which needs a mouse HID interface on the firmware side.
+pub struct MouseKeys {
+    mappings: Vec<(u32, MouseAction)>,
+    buttons: u8,
+    held: Vec<usize>, // indices into mappings of held Move/Wheel keys
+}
+impl MouseKeys {
+    pub fn new<T: AcceptsKeycode>(mappings: Vec<(T, MouseAction)>) -> MouseKeys {
+        MouseKeys {
+            mappings: mappings
+                .into_iter()
+                .map(|(k, action)| (k.to_u32(), action))
+                .collect(),
+            buttons: 0,
+            held: Vec::new(),
+        }
+    }
+    fn movement(&self) -> (i8, i8, i8) {
+        let mut dx: i8 = 0;
+        let mut dy: i8 = 0;
+        let mut wheel: i8 = 0;
+        for ii in self.held.iter() {
+            match self.mappings[*ii].1 {
+                MouseAction::Move(x, y) => {
+                    dx = dx.saturating_add(x);
+                    dy = dy.saturating_add(y);
+                }
+                MouseAction::Wheel(w) => wheel = wheel.saturating_add(w),
+                MouseAction::Button(_) => {}
+            }
+        }
+        (dx, dy, wheel)
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for MouseKeys {
+    fn describe(&self) -> String {
+        format!("MouseKeys({} keys)", self.mappings.len())
+    }
+    fn on_disabled(&mut self, output: &mut T) {
+        if self.buttons != 0 {
+            self.buttons = 0;
+            output.send_mouse(0, 0, 0, 0);
+        }
+        self.held.clear();
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if let Some(ii) = self.mappings.iter().position(|(k, _)| *k == kc.keycode) {
+                        *status = EventStatus::Handled;
+                        match self.mappings[ii].1 {
+                            MouseAction::Button(mask) => {
+                                self.buttons |= mask;
+                                output.send_mouse(self.buttons, 0, 0, 0);
+                            }
+                            MouseAction::Move(dx, dy) => {
+                                self.held.push(ii);
+                                output.send_mouse(self.buttons, dx, dy, 0);
+                            }
+                            MouseAction::Wheel(wheel) => {
+                                self.held.push(ii);
+                                output.send_mouse(self.buttons, 0, 0, wheel);
+                            }
+                        }
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if let Some(ii) = self.mappings.iter().position(|(k, _)| *k == kc.keycode) {
+                        *status = EventStatus::Handled;
+                        if let MouseAction::Button(mask) = self.mappings[ii].1 {
+                            self.buttons &= !mask;
+                            output.send_mouse(self.buttons, 0, 0, 0);
+                        } else {
+                            self.held.retain(|x| *x != ii);
+                        }
+                    }
+                }
+                Event::TimeOut(_) => {
+                    let (dx, dy, wheel) = self.movement();
+                    if dx != 0 || dy != 0 || wheel != 0 {
+                        output.send_mouse(self.buttons, dx, dy, wheel);
+                    }
+                }
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{MouseAction, MouseKeys, USBKeyboard};
+    use crate::key_codes::UserKey;
+    use crate::test_helpers::KeyOutCatcher;
+    use crate::Keyboard;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_mousekeys() {
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(MouseKeys::new(vec![
+            (UserKey::UK0, MouseAction::Button(1)),
+            (UserKey::UK1, MouseAction::Move(5, 0)),
+            (UserKey::UK2, MouseAction::Wheel(-1)),
+        ])));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(UserKey::UK0, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keyrelease(UserKey::UK0, 0);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.mouse_reports == vec![(1, 0, 0, 0), (0, 0, 0, 0)]);
+        keyboard.output.clear();
+
+        keyboard.add_keypress(UserKey::UK1, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_timeout(20);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keypress(UserKey::UK2, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_timeout(20);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keyrelease(UserKey::UK1, 0);
+        keyboard.add_keyrelease(UserKey::UK2, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_timeout(20);
+        keyboard.handle_keys().unwrap();
+        assert!(
+            keyboard.output.mouse_reports
+                == vec![(0, 5, 0, 0), (0, 5, 0, 0), (0, 0, 0, -1), (0, 5, 0, -1)]
+        );
+        //no keyboard output
+        assert!(keyboard.output.reports.iter().all(|r| r.is_empty()));
+        assert!(keyboard.events.is_empty());
+    }
+}
@@ -0,0 +1,96 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::{describe_keycode, AcceptsKeycode};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::{Modifier, USBKeyOut, MODIFIER_KEYS};
+use no_std_compat::prelude::v1::*;
+
+/// One key that holds several modifiers at once,
+/// e.g. Ctrl+Shift (see premade::hyper / premade::meh).
+///
+/// The modifiers are set in output.state() while the trigger is held,
+/// the USBKeyboard then adds them to every report.
+/// The trigger itself is not send.
+pub struct MultiModifier {
+    trigger: u32,
+    modifiers: Vec<Modifier>,
+}
+impl MultiModifier {
+    pub fn new(trigger: impl AcceptsKeycode, modifiers: &[Modifier]) -> MultiModifier {
+        MultiModifier {
+            trigger: trigger.to_u32(),
+            modifiers: modifiers.to_vec(),
+        }
+    }
+    fn set_modifiers(&self, output: &mut dyn USBKeyOut, value: bool) {
+        for modifier in self.modifiers.iter() {
+            output.state().set_modifier(*modifier, value);
+        }
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for MultiModifier {
+    fn describe(&self) -> String {
+        let modifiers: Vec<String> = MODIFIER_KEYS
+            .iter()
+            .filter(|(modifier, _left, _right)| self.modifiers.contains(modifier))
+            .map(|(_modifier, left, _right)| describe_keycode(left.to_u32()))
+            .collect();
+        format!(
+            "MultiModifier(trigger: {}, {})",
+            describe_keycode(self.trigger),
+            modifiers.join("+")
+        )
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        self.set_modifiers(output, true);
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        self.set_modifiers(output, false);
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{MultiModifier, USBKeyboard};
+    use crate::key_codes::UserKey;
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Modifier::*;
+    use crate::{Keyboard, USBKeyOut};
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_multimodifier() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(MultiModifier::new(UserKey::UK0, &[Ctrl, Shift])));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[LCtrl, LShift]]);
+        keyboard.pc(A, &[&[LCtrl, LShift, A]]);
+        keyboard.rc(A, &[&[LCtrl, LShift]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+        assert!(!keyboard.output.state().modifier(Shift));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+}
@@ -1,10 +1,8 @@
 use crate::handlers::{OnOff, ProcessKeys, Action, HandlerResult};
-use crate::key_codes::AcceptsKeycode;
+use crate::key_codes::{describe_keycode, AcceptsKeycode};
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
 use crate::USBKeyOut;
-use lazy_static::lazy_static;
 use no_std_compat::prelude::v1::*;
-use spin::RwLock;
 #[repr(u8)]
 #[derive(Debug)]
 pub enum OneShotStatus {
@@ -12,6 +10,7 @@ pub enum OneShotStatus {
     HeldUsed,
     Triggered,
     TriggerUsed,
+    Locked,
     Off,
 }
 /// A OneShot key.
@@ -27,15 +26,29 @@ pub enum OneShotStatus {
 /// and on_deactivate will be called upon release. This typically is useful
 /// for graphics work where the user presses the modifiers while interacting
 /// with the mouse.
+/// Use new_global_timeout to use KeyboardState::tapping_term_ms as held_timeout
+/// (no held_timeout if that is not set).
 ///
 /// You may also define a released_timeout - after this time, without
 /// a different keypress, the OneShot will also deactivate.
+/// Use with_idle_timeout() for the same without changing what counts
+/// as a quick double tap (see below). Both are accumulated from TimeOut events.
+///
+/// Use with_lock() to have a quick double tap lock the OneShot instead -
+/// on_deactivate is then only called on the next trigger press.
+/// 'Quick' means within released_timeout (or held_timeout if that is 0)
+/// of releasing the trigger. If both are 0, any double tap locks.
+///
+/// Use with_hold_on_overlap() to have a trigger pressed while another
+/// (non-oneshot) key is still down act like a plain held modifier
+/// instead of arming the OneShot - which is what rolling typists expect.
 ///
 /// OneShots have two triggers to accomodate the usual left/right modifier keys,
 /// just pass in Keycode::No if you want one trigger to be ignored.
 ///
-/// Note that the oneshots always lead to the left variant of the modifier being sent,
-/// even if they're being triggered by the right one.
+/// Note that the premade modifier oneshots lead to the left variant of the modifier
+/// being sent, even if they're being triggered by the right one - unless
+/// you pass prefer_right (e.g. for AltGr).
 pub struct OneShot<M1, M2, M3> {
     trigger1: u32,
     trigger2: u32,
@@ -44,11 +57,13 @@ pub struct OneShot<M1, M2, M3> {
     on_double_tap_trigger2: M3,
     status: OneShotStatus,
     held_timeout: u16,
+    held_timeout_from_state: bool,
     released_timeout: u16,
-}
-lazy_static! {
-    /// oneshots don't deactive on other oneshots - this stores the keycodes to ignore
-    pub static ref ONESHOT_TRIGGERS: RwLock<Vec<u32>> = RwLock::new(Vec::new());
+    lock_on_double_tap: bool,
+    idle_timeout: u16,
+    triggered_ms: u16, // TimeOut ms accumulated while Triggered
+    hold_on_overlap: bool,
+    keys_down: Vec<u32>, // non-oneshot keys currently down, if hold_on_overlap
 }
 impl<M1: OnOff, M2: Action, M3: Action> OneShot<M1, M2, M3> {
     pub fn new(
@@ -60,8 +75,6 @@ impl<M1: OnOff, M2: Action, M3: Action> OneShot<M1, M2, M3> {
         held_timeout: u16,
         released_timeout: u16,
     ) -> OneShot<M1, M2, M3> {
-        ONESHOT_TRIGGERS.write().push(trigger1.to_u32());
-        ONESHOT_TRIGGERS.write().push(trigger2.to_u32());
         OneShot {
             trigger1: trigger1.to_u32(),
             trigger2: trigger2.to_u32(),
@@ -70,12 +83,98 @@ impl<M1: OnOff, M2: Action, M3: Action> OneShot<M1, M2, M3> {
             on_double_tap_trigger2,
             status: OneShotStatus::Off,
             held_timeout,
+            held_timeout_from_state: false,
             released_timeout,
+            lock_on_double_tap: false,
+            idle_timeout: 0,
+            triggered_ms: 0,
+            hold_on_overlap: false,
+            keys_down: Vec::new(),
+        }
+    }
+    pub fn new_global_timeout(
+        trigger1: impl AcceptsKeycode,
+        trigger2: impl AcceptsKeycode,
+        callbacks: M1,
+        on_double_tap_trigger1: M2,
+        on_double_tap_trigger2: M3,
+    ) -> OneShot<M1, M2, M3> {
+        OneShot {
+            held_timeout_from_state: true,
+            ..OneShot::new(
+                trigger1,
+                trigger2,
+                callbacks,
+                on_double_tap_trigger1,
+                on_double_tap_trigger2,
+                0,
+                0,
+            )
+        }
+    }
+    /// deactivate a triggered (tapped, but not yet used) OneShot
+    /// after idle_timeout ms without a key press
+    pub fn with_idle_timeout(self, idle_timeout: u16) -> OneShot<M1, M2, M3> {
+        OneShot {
+            idle_timeout,
+            ..self
+        }
+    }
+    /// a trigger pressed while another key is down is a plain modifier hold
+    pub fn with_hold_on_overlap(self) -> OneShot<M1, M2, M3> {
+        OneShot {
+            hold_on_overlap: true,
+            ..self
         }
     }
+    pub fn with_lock(self) -> OneShot<M1, M2, M3> {
+        OneShot {
+            lock_on_double_tap: true,
+            ..self
+        }
+    }
+    fn is_quick_double_tap(&self, ms_since_release: u16, held_timeout: u16) -> bool {
+        let window = if self.released_timeout > 0 {
+            self.released_timeout
+        } else {
+            held_timeout
+        };
+        window == 0 || ms_since_release < window
+    }
 }
 impl<T: USBKeyOut, M1: OnOff, M2: Action, M3: Action> ProcessKeys<T> for OneShot<M1, M2, M3> {
+    fn describe(&self) -> String {
+        format!(
+            "OneShot(triggers: {}/{}, held_timeout: {}, released_timeout: {})",
+            describe_keycode(self.trigger1),
+            describe_keycode(self.trigger2),
+            self.held_timeout,
+            self.released_timeout
+        )
+    }
+    fn reset(&mut self, output: &mut T) {
+        match self.status {
+            OneShotStatus::Off => {}
+            _ => self.callbacks.on_deactivate(output),
+        }
+        self.status = OneShotStatus::Off;
+        self.triggered_ms = 0;
+        self.keys_down.clear();
+    }
+    fn on_disabled(&mut self, _output: &mut T) {
+        //we won't see the releases
+        self.keys_down.clear();
+    }
+    fn on_added(&mut self, output: &mut T) {
+        output.state().add_oneshot_trigger(self.trigger1);
+        output.state().add_oneshot_trigger(self.trigger2);
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
+        let held_timeout = if self.held_timeout_from_state {
+            output.ro_state().tapping_term_ms.unwrap_or(self.held_timeout)
+        } else {
+            self.held_timeout
+        };
         for (event, status) in iter_unhandled_mut(events) {
             //a sticky key
             // on press if not active -> active
@@ -87,6 +186,19 @@ impl<T: USBKeyOut, M1: OnOff, M2: Action, M3: Action> ProcessKeys<T> for OneShot
                     if kc.keycode == self.trigger1 || kc.keycode == self.trigger2 {
                         *status = EventStatus::Handled;
                         match self.status {
+                            OneShotStatus::Triggered
+                                if self.lock_on_double_tap
+                                    && self.is_quick_double_tap(
+                                        self.triggered_ms.saturating_add(kc.ms_since_last),
+                                        held_timeout,
+                                    ) =>
+                            {
+                                self.status = OneShotStatus::Locked;
+                            }
+                            OneShotStatus::Locked => {
+                                self.status = OneShotStatus::Off;
+                                self.callbacks.on_deactivate(output);
+                            }
                             OneShotStatus::Triggered => {
                                 self.status = OneShotStatus::Off;
                                 self.callbacks.on_deactivate(output);
@@ -97,14 +209,22 @@ impl<T: USBKeyOut, M1: OnOff, M2: Action, M3: Action> ProcessKeys<T> for OneShot
                                 }
                             }
                             OneShotStatus::Off => {
-                                self.status = OneShotStatus::Held;
-                                self.callbacks.on_activate(output)
+                                if self.keys_down.is_empty() {
+                                    self.status = OneShotStatus::Held;
+                                } else {
+                                    //overlapping - just a regular modifier
+                                    self.status = OneShotStatus::HeldUsed;
+                                }
+                                self.callbacks.on_activate_by(output, kc.keycode)
                             }
                             OneShotStatus::Held
                             | OneShotStatus::HeldUsed
                             | OneShotStatus::TriggerUsed => {}
                         }
-                    } else if !ONESHOT_TRIGGERS.read().contains(&kc.keycode) {
+                    } else if !output.state().is_oneshot_trigger(kc.keycode) {
+                        if self.hold_on_overlap && !self.keys_down.contains(&kc.keycode) {
+                            self.keys_down.push(kc.keycode);
+                        }
                         match self.status {
                             OneShotStatus::Triggered => self.status = OneShotStatus::TriggerUsed,
                             OneShotStatus::TriggerUsed => {
@@ -119,11 +239,12 @@ impl<T: USBKeyOut, M1: OnOff, M2: Action, M3: Action> ProcessKeys<T> for OneShot
                     if kc.keycode == self.trigger1 || kc.keycode == self.trigger2 {
                         match self.status {
                             OneShotStatus::Held => {
-                                if self.held_timeout > 0 && kc.ms_since_last > self.held_timeout {
+                                if held_timeout > 0 && kc.ms_since_last > held_timeout {
                                     self.status = OneShotStatus::Off;
                                     self.callbacks.on_deactivate(output)
                                 } else {
                                     self.status = OneShotStatus::Triggered;
+                                    self.triggered_ms = 0;
                                 }
                             }
 
@@ -134,7 +255,8 @@ impl<T: USBKeyOut, M1: OnOff, M2: Action, M3: Action> ProcessKeys<T> for OneShot
                             _ => {}
                         }
                         *status = EventStatus::Handled;
-                    } else if !ONESHOT_TRIGGERS.read().contains(&kc.keycode) {
+                    } else if !output.state().is_oneshot_trigger(kc.keycode) {
+                        self.keys_down.retain(|k| *k != kc.keycode);
                         match self.status {
                             OneShotStatus::Triggered => {
                                 self.status = OneShotStatus::Off;
@@ -147,7 +269,10 @@ impl<T: USBKeyOut, M1: OnOff, M2: Action, M3: Action> ProcessKeys<T> for OneShot
                 }
                 Event::TimeOut(ms) => {
                     if let OneShotStatus::Triggered = self.status {
-                        if self.released_timeout > 0 && *ms >= self.released_timeout {
+                        self.triggered_ms = self.triggered_ms.saturating_add(*ms);
+                        if (self.released_timeout > 0 && self.triggered_ms >= self.released_timeout)
+                            || (self.idle_timeout > 0 && self.triggered_ms >= self.idle_timeout)
+                        {
                             self.status = OneShotStatus::Off;
                             self.callbacks.on_deactivate(output)
                         }
@@ -155,6 +280,14 @@ impl<T: USBKeyOut, M1: OnOff, M2: Action, M3: Action> ProcessKeys<T> for OneShot
                 }
             }
         }
+        if !self.keys_down.is_empty() {
+            //releases already claimed by an earlier handler count as well
+            for (event, _status) in events.iter() {
+                if let Event::KeyRelease(kc) = event {
+                    self.keys_down.retain(|k| *k != kc.keycode);
+                }
+            }
+        }
         HandlerResult::NoOp
     }
 }
@@ -373,6 +506,87 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_oneshot_global_timeout() {
+        use crate::test_helpers::Checks;
+        let counter = Arc::new(RwLock::new(PressCounter {
+            down_counter: 0,
+            up_counter: 0,
+        }));
+        let t = OneShot::new_global_timeout(UserKey::UK0, UserKey::UK1, counter.clone(), ActionNone{}, ActionNone{});
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(t));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().tapping_term_ms = Some(1000);
+        //held past the tapping term - deactivated on release
+        keyboard.pc(UserKey::UK0, &[&[KeyCode::H], &[]]);
+        keyboard.rct(UserKey::UK0, 1001, &[&[KeyCode::I], &[]]);
+        assert!(counter.read().up_counter == 1);
+        //a tap - stays active
+        keyboard.pc(UserKey::UK0, &[&[KeyCode::H], &[]]);
+        keyboard.rct(UserKey::UK0, 500, &[&[]]);
+        assert!(counter.read().up_counter == 1);
+        keyboard.pc(UserKey::UK0, &[&[KeyCode::I], &[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(counter.read().up_counter == 2);
+
+        //no held timeout without a tapping term
+        keyboard.output.state().tapping_term_ms = None;
+        keyboard.pc(UserKey::UK0, &[&[KeyCode::H], &[]]);
+        keyboard.rct(UserKey::UK0, 5000, &[&[]]);
+        assert!(counter.read().up_counter == 2);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_oneshot_reset() {
+        use crate::key_codes::KeyCode::*;
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        use crate::Modifier::Shift;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let id = keyboard.add_handler(premade::one_shot_shift(0, 0));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[LShift]]);
+        assert!(keyboard.output.state().modifier(Shift));
+        keyboard.reset_handler(id);
+        assert!(!keyboard.output.state().modifier(Shift));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        //and it arms again afterwards
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[LShift]]);
+        keyboard.pc(A, &[&[LShift, A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_oneshot_released_timeout_accumulates() {
+        let counter = Arc::new(RwLock::new(PressCounter {
+            down_counter: 0,
+            up_counter: 0,
+        }));
+        let t = OneShot::new(UserKey::UK0, UserKey::UK1, counter.clone(), ActionNone{}, ActionNone{}, 0, 1000);
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(t));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(UserKey::UK0, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keyrelease(UserKey::UK0, 10);
+        keyboard.handle_keys().unwrap();
+        assert!(counter.read().down_counter == 1);
+        assert!(counter.read().up_counter == 0);
+        //two pending timeouts add up to more than released_timeout
+        keyboard.add_timeout(600);
+        keyboard.add_timeout(600);
+        keyboard.handle_keys().unwrap();
+        assert!(counter.read().down_counter == 1);
+        assert!(counter.read().up_counter == 1);
+        assert!(keyboard.events.is_empty());
+    }
+
     #[test]
     fn test_oneshot_double_tap() {
         use crate::key_codes::KeyCode::*;
@@ -415,4 +629,205 @@ mod tests {
         assert!(counter.read().up_counter == 2);
     }
 
+    #[test]
+    fn test_oneshot_lock_window_counts_timeouts() {
+        use crate::key_codes::KeyCode::*;
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        use crate::Modifier::Shift;
+        let t = (*premade::one_shot_shift(200, 0)).with_lock();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(t));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //the TimeOuts between the taps count - not a quick double tap
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[LShift]]);
+        keyboard.tc(1000, &[&[LShift]]);
+        keyboard.pct(LShift, 5, &[&[]]);
+        keyboard.rc(LShift, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+        //a quick one still locks
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[LShift]]);
+        keyboard.tc(100, &[&[LShift]]);
+        keyboard.pct(LShift, 5, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[LShift]]);
+        keyboard.pc(A, &[&[LShift, A]]);
+        keyboard.rc(A, &[&[LShift]]);
+        assert!(keyboard.output.state().modifier(Shift));
+    }
+
+    #[test]
+    fn test_oneshot_locked() {
+        use crate::key_codes::KeyCode::*;
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        use crate::Modifier::Shift;
+        let t = (*premade::one_shot_shift(0, 500)).with_lock();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(t));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[LShift]]);
+        keyboard.pct(LShift, 100, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[LShift]]);
+        assert!(keyboard.output.state().modifier(Shift));
+
+        //shift stays on across several letters
+        for key in [A, B, C].iter() {
+            keyboard.pc(*key, &[&[LShift, *key]]);
+            keyboard.rc(*key, &[&[LShift]]);
+            assert!(keyboard.output.state().modifier(Shift));
+        }
+        //and timeouts
+        keyboard.tc(1000, &[&[LShift]]);
+        assert!(keyboard.output.state().modifier(Shift));
+
+        //one more tap unlocks
+        keyboard.pc(LShift, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+        keyboard.rc(LShift, &[&[]]);
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+    }
+
+    #[test]
+    fn test_oneshot_hold_on_overlap() {
+        use crate::key_codes::KeyCode::*;
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        use crate::Modifier::Shift;
+        let t = (*premade::one_shot_shift(0, 0)).with_hold_on_overlap();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(t));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.pc(LShift, &[&[A, LShift]]);
+        keyboard.rc(A, &[&[LShift]]);
+        keyboard.pc(B, &[&[LShift, B]]);
+        keyboard.rc(B, &[&[LShift]]);
+        assert!(keyboard.output.state().modifier(Shift));
+        //released - not armed
+        keyboard.rc(LShift, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+        keyboard.pc(C, &[&[C]]);
+        keyboard.rc(C, &[&[]]);
+        //without overlap, it's still a OneShot
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[LShift]]);
+        keyboard.pc(C, &[&[LShift, C]]);
+        keyboard.rc(C, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_oneshot_hold_on_overlap_release_not_seen() {
+        use crate::handlers::HandlerResult;
+        use crate::key_codes::KeyCode::*;
+        use crate::premade;
+        use crate::premade::FnHandler;
+        use crate::test_helpers::Checks;
+        use crate::iter_unhandled_mut;
+        let swallow = Arc::new(RwLock::new(false));
+        let swallow2 = swallow.clone();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(FnHandler::new(
+            move |events: &mut Vec<(Event, EventStatus)>, _output: &mut dyn USBKeyOut| {
+                for (e, status) in iter_unhandled_mut(events) {
+                    if let Event::KeyRelease(_) = e {
+                        if *swallow2.read() {
+                            *status = EventStatus::Handled;
+                        }
+                    }
+                }
+                HandlerResult::NoOp
+            },
+        )));
+        let t = (*premade::one_shot_shift(0, 0)).with_hold_on_overlap();
+        let id = keyboard.add_handler(Box::new(t));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //the release is claimed before the OneShot sees it
+        keyboard.pc(A, &[&[A]]);
+        *swallow.write() = true;
+        keyboard.add_keyrelease(A, 0);
+        keyboard.handle_keys().unwrap();
+        *swallow.write() = false;
+        keyboard.events.clear();
+        keyboard.output.clear();
+        //still arms
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[LShift]]);
+        keyboard.pc(C, &[&[LShift, C]]);
+        keyboard.rc(C, &[&[]]);
+
+        //released while the OneShot was disabled
+        keyboard.pc(A, &[&[A]]);
+        keyboard.output.state().disable_handler(id);
+        keyboard.rc(A, &[&[]]);
+        keyboard.output.state().enable_handler(id);
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.rc(LShift, &[&[LShift]]);
+        keyboard.pc(C, &[&[LShift, C]]);
+        keyboard.rc(C, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_oneshot_triggers_per_keyboard() {
+        use crate::key_codes::KeyCode::*;
+        use crate::premade;
+        use crate::Modifier::Ctrl;
+        let mut keyboard_shift = Keyboard::new(KeyOutCatcher::new());
+        keyboard_shift.add_handler(premade::one_shot_shift(0, 0));
+        keyboard_shift.add_handler(Box::new(USBKeyboard::new()));
+        let mut keyboard_ctrl = Keyboard::new(KeyOutCatcher::new());
+        keyboard_ctrl.add_handler(premade::one_shot_ctrl(0, 0));
+        keyboard_ctrl.add_handler(Box::new(USBKeyboard::new()));
+        assert!(keyboard_shift.output.state().is_oneshot_trigger(LShift.to_u32()));
+        assert!(!keyboard_shift.output.state().is_oneshot_trigger(LCtrl.to_u32()));
+        assert!(keyboard_ctrl.output.state().is_oneshot_trigger(LCtrl.to_u32()));
+        assert!(!keyboard_ctrl.output.state().is_oneshot_trigger(LShift.to_u32()));
+
+        //shift is a regular key on the second keyboard - and uses up the oneshot
+        keyboard_ctrl.add_keypress(LCtrl, 0);
+        keyboard_ctrl.add_keyrelease(LCtrl, 10);
+        keyboard_ctrl.handle_keys().unwrap();
+        assert!(keyboard_ctrl.output.state().modifier(Ctrl));
+        keyboard_ctrl.add_keypress(LShift, 10);
+        keyboard_ctrl.handle_keys().unwrap();
+        keyboard_ctrl.add_keyrelease(LShift, 10);
+        keyboard_ctrl.handle_keys().unwrap();
+        assert!(!keyboard_ctrl.output.state().modifier(Ctrl));
+    }
+
+    #[test]
+    fn test_oneshot_timeouts_accumulate() {
+        use crate::key_codes::KeyCode::*;
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        use crate::Modifier::Shift;
+        for t in [
+            *premade::one_shot_shift(0, 500),
+            (*premade::one_shot_shift(0, 0)).with_idle_timeout(500),
+        ] {
+            let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+            keyboard.add_handler(Box::new(t));
+            keyboard.add_handler(Box::new(USBKeyboard::new()));
+            keyboard.pc(LShift, &[&[LShift]]);
+            keyboard.rct(LShift, 10, &[&[LShift]]);
+            keyboard.tc(200, &[&[LShift]]);
+            keyboard.tc(200, &[&[LShift]]);
+            assert!(keyboard.output.state().modifier(Shift));
+            keyboard.tc(200, &[&[]]);
+            assert!(!keyboard.output.state().modifier(Shift));
+            //counted from the next tap on
+            keyboard.pc(LShift, &[&[LShift]]);
+            keyboard.rct(LShift, 10, &[&[LShift]]);
+            keyboard.tc(400, &[&[LShift]]);
+            keyboard.pc(A, &[&[LShift, A]]);
+            keyboard.rc(A, &[&[]]);
+        }
+    }
 }
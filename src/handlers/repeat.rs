@@ -0,0 +1,243 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::{describe_keycode, AcceptsKeycode, KeyCode, KeyCodeInfo};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::{USBKeyOut, MODIFIER_KEYS};
+use core::convert::TryInto;
+use no_std_compat::prelude::v1::*;
+
+/// Repeat the last chord - all keys that were
+/// down together, plus the active modifiers.
+///
+/// The chord is recorded whenever a new (non modifier) key
+/// is pressed, so releasing the keys one by one does not
+/// change it. On trigger press, the chord is send as a single
+/// report, followed by an empty one.
+///
+/// Needs to be added before the USBKeyboard.
+pub struct RepeatChord {
+    trigger: u32,
+    chord: Vec<KeyCode>,
+}
+impl RepeatChord {
+    pub fn new(trigger: impl AcceptsKeycode) -> RepeatChord {
+        RepeatChord {
+            trigger: trigger.to_u32(),
+            chord: Vec::new(),
+        }
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for RepeatChord {
+    fn describe(&self) -> String {
+        format!("RepeatChord(trigger: {})", describe_keycode(self.trigger))
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        let mut held: Vec<KeyCode> = Vec::new();
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        if !self.chord.is_empty() {
+                            output.send_keys(&self.chord);
+                            output.send_empty();
+                        }
+                    } else if kc.keycode.is_usb_keycode() {
+                        let oc: Result<KeyCode, _> = kc.keycode.try_into();
+                        if let Ok(key) = oc {
+                            if !held.contains(&key) {
+                                held.push(key);
+                            }
+                            if kc.flag & 0x1 == 0 && !key.is_modifier() {
+                                //a fresh key - this is the new chord
+                                self.chord = held.clone();
+                                for (modifier, left, right) in MODIFIER_KEYS.iter() {
+                                    if output.state().modifier(*modifier)
+                                        && !self.chord.contains(left)
+                                        && !self.chord.contains(right)
+                                    {
+                                        self.chord.push(*left);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+/// Repeat the last key (QMK's 'repeat key').
+///
+/// Remembers the last fresh non modifier key press it sees,
+/// and sends it again (followed by an empty report) when the
+/// trigger is pressed.
+///
+/// With with_modifiers(), the modifiers active when the key was
+/// pressed are send along, so Ctrl-Z repeats as Ctrl-Z, not Z.
+///
+/// Needs to be added before the USBKeyboard.
+pub struct RepeatKey {
+    trigger: u32,
+    last: Option<KeyCode>,
+    modifiers: Vec<KeyCode>,
+    with_modifiers: bool,
+}
+impl RepeatKey {
+    pub fn new(trigger: impl AcceptsKeycode) -> RepeatKey {
+        RepeatKey {
+            trigger: trigger.to_u32(),
+            last: None,
+            modifiers: Vec::new(),
+            with_modifiers: false,
+        }
+    }
+    /// re-apply the modifiers that were active when the repeated key was pressed
+    pub fn with_modifiers(mut self) -> RepeatKey {
+        self.with_modifiers = true;
+        self
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for RepeatKey {
+    fn describe(&self) -> String {
+        format!(
+            "RepeatKey(trigger: {}, last: {})",
+            describe_keycode(self.trigger),
+            match self.last {
+                Some(key) => describe_keycode(key.to_u32()),
+                None => "-".to_string(),
+            }
+        )
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        if let Some(key) = self.last {
+                            let mut keys = self.modifiers.clone();
+                            keys.push(key);
+                            output.send_keys(&keys);
+                            output.send_empty();
+                        }
+                    } else if kc.flag & 0x1 == 0 && kc.keycode.is_usb_keycode() {
+                        let oc: Result<KeyCode, _> = kc.keycode.try_into();
+                        if let Ok(key) = oc {
+                            if !key.is_modifier() {
+                                self.last = Some(key);
+                                self.modifiers.clear();
+                                if self.with_modifiers {
+                                    for (modifier, left, _right) in MODIFIER_KEYS.iter() {
+                                        if output.state().modifier(*modifier) {
+                                            self.modifiers.push(*left);
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{RepeatChord, RepeatKey, USBKeyboard};
+    use crate::key_codes::{KeyCode, UserKey};
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Keyboard;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_repeat_chord() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(RepeatChord::new(UserKey::UK0)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //nothing to repeat yet
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+
+        keyboard.pc(LCtrl, &[&[LCtrl]]);
+        keyboard.pc(LShift, &[&[LCtrl, LShift]]);
+        keyboard.pc(A, &[&[LCtrl, LShift, A]]);
+        keyboard.rc(A, &[&[LCtrl, LShift]]);
+        keyboard.rc(LShift, &[&[LCtrl]]);
+        keyboard.rc(LCtrl, &[&[]]);
+
+        keyboard.pc(UserKey::UK0, &[&[LCtrl, LShift, A], &[], &[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        keyboard.pc(UserKey::UK0, &[&[LCtrl, LShift, A], &[], &[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+
+        keyboard.pc(KeyCode::B, &[&[B]]);
+        keyboard.rc(KeyCode::B, &[&[]]);
+        keyboard.pc(UserKey::UK0, &[&[B], &[], &[]]);
+    }
+
+    #[test]
+    fn test_repeat_key() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(RepeatKey::new(UserKey::UK0)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //nothing to repeat yet
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+
+        keyboard.pc(KeyCode::A, &[&[A]]);
+        keyboard.rc(KeyCode::A, &[&[]]);
+        keyboard.pc(UserKey::UK0, &[&[A], &[], &[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+
+        //modifiers are not repeated by default
+        keyboard.pc(LCtrl, &[&[LCtrl]]);
+        keyboard.pc(KeyCode::Z, &[&[LCtrl, Z]]);
+        keyboard.rc(KeyCode::Z, &[&[LCtrl]]);
+        keyboard.rc(LCtrl, &[&[]]);
+        keyboard.pc(UserKey::UK0, &[&[Z], &[], &[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+    }
+
+    #[test]
+    fn test_repeat_key_with_modifiers() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(RepeatKey::new(UserKey::UK0).with_modifiers()));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(LCtrl, &[&[LCtrl]]);
+        keyboard.pc(KeyCode::Z, &[&[LCtrl, Z]]);
+        keyboard.rc(KeyCode::Z, &[&[LCtrl]]);
+        keyboard.rc(LCtrl, &[&[]]);
+        keyboard.pc(UserKey::UK0, &[&[LCtrl, Z], &[], &[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+    }
+}
@@ -1,5 +1,10 @@
+use crate::handlers::layer::{
+    forget_stale_rewrite, rewrite_release_as_recorded, rewrite_releases_as_recorded,
+};
 use crate::handlers::{ProcessKeys, HandlerResult};
+use crate::key_codes::describe_keycode;
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::Modifier::Shift;
 use crate::USBKeyOut;
 
 use no_std_compat::prelude::v1::*;
@@ -10,23 +15,44 @@ use no_std_compat::prelude::v1::*;
 /// The advantage of this is that you can/must use it with a const
 /// array (slice), which greatly saves on ram compared to Layer
 /// (e.g. premade::dvorak)
-/// 
+///
+/// Releases are rewritten like their presses were,
+/// even if the layer has been disabled in the meantime.
 pub struct RewriteLayer {
     rewrites: &'static [(u32, u32)],
+    rewritten: Vec<(u32, u32)>, // original_keycode, keycode of rewritten presses
 }
 
 impl RewriteLayer {
     pub fn new(rewrites: &'static [(u32, u32)]) -> RewriteLayer {
-        RewriteLayer { rewrites }
+        RewriteLayer {
+            rewrites,
+            rewritten: Vec::new(),
+        }
+    }
+    /// the (from, to) table, e.g. for keymap_serialize::encode
+    pub fn rewrites(&self) -> &'static [(u32, u32)] {
+        self.rewrites
     }
 }
 
 impl<T: USBKeyOut> ProcessKeys<T> for RewriteLayer {
+    fn describe(&self) -> String {
+        let mappings: Vec<String> = self
+            .rewrites
+            .iter()
+            .map(|(from, to)| format!("{} -> {}", describe_keycode(*from), describe_keycode(*to)))
+            .collect();
+        format!("RewriteLayer({})", mappings.join(", "))
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, _output: &mut T)->HandlerResult {
         for (event, _status) in iter_unhandled_mut(events) {
             //events.iter_mut() {
             match event {
                 Event::KeyRelease(kc) => {
+                    if rewrite_release_as_recorded(&mut self.rewritten, kc) {
+                        continue;
+                    }
                     for (from, to) in self.rewrites.iter() {
                         if *from == kc.keycode {
                             if (kc.flag & 2) == 0 {
@@ -38,11 +64,13 @@ impl<T: USBKeyOut> ProcessKeys<T> for RewriteLayer {
                     }
                 }
                 Event::KeyPress(kc) => {
+                    forget_stale_rewrite(&mut self.rewritten, kc);
                     for (from, to) in self.rewrites.iter() {
                         if *from == kc.keycode {
                             if (kc.flag & 2) == 0 {
                                 kc.keycode = *to;
                                 kc.flag |= 2;
+                                self.rewritten.push((kc.original_keycode, kc.keycode));
                             }
                             break; //only one rewrite per layer
                         }
@@ -53,10 +81,107 @@ impl<T: USBKeyOut> ProcessKeys<T> for RewriteLayer {
         }
         HandlerResult::NoOp
     }
+    fn process_keys_while_disabled(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        _output: &mut T,
+    ) {
+        rewrite_releases_as_recorded(&mut self.rewritten, events);
+    }
+    fn reset(&mut self, _output: &mut T) {
+        self.rewritten.clear();
+    }
     fn default_enabled(&self) -> bool {
         false
     }
 }
+
+/// A RewriteLayer that considers the shift state,
+/// like Layer's LayerAction::RewriteToShifted.
+///
+/// The const table is (from, to, shifted_to). Shift is checked
+/// when the key is pressed, the release is rewritten to the same key.
+pub struct RewriteLayerShifted {
+    rewrites: &'static [(u32, u32, u32)],
+    rewritten: Vec<(u32, u32)>, // original_keycode, keycode of rewritten presses
+}
+
+impl RewriteLayerShifted {
+    pub fn new(rewrites: &'static [(u32, u32, u32)]) -> RewriteLayerShifted {
+        RewriteLayerShifted {
+            rewrites,
+            rewritten: Vec::new(),
+        }
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for RewriteLayerShifted {
+    fn describe(&self) -> String {
+        let mappings: Vec<String> = self
+            .rewrites
+            .iter()
+            .map(|(from, to, shifted)| {
+                format!(
+                    "{} -> {}/{}",
+                    describe_keycode(*from),
+                    describe_keycode(*to),
+                    describe_keycode(*shifted)
+                )
+            })
+            .collect();
+        format!("RewriteLayerShifted({})", mappings.join(", "))
+    }
+    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
+        let shift = output.state().modifier(Shift);
+        for (event, _status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyRelease(kc) => {
+                    if rewrite_release_as_recorded(&mut self.rewritten, kc) {
+                        continue;
+                    }
+                    for (from, to, shifted) in self.rewrites.iter() {
+                        if *from == kc.keycode {
+                            if (kc.flag & 2) == 0 {
+                                kc.keycode = if shift { *shifted } else { *to };
+                                kc.flag |= 2;
+                            }
+                            break; //only one rewrite per layer
+                        }
+                    }
+                }
+                Event::KeyPress(kc) => {
+                    forget_stale_rewrite(&mut self.rewritten, kc);
+                    for (from, to, shifted) in self.rewrites.iter() {
+                        if *from == kc.keycode {
+                            if (kc.flag & 2) == 0 {
+                                kc.keycode = if shift { *shifted } else { *to };
+                                kc.flag |= 2;
+                                self.rewritten.push((kc.original_keycode, kc.keycode));
+                            }
+                            break; //only one rewrite per layer
+                        }
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+        HandlerResult::NoOp
+    }
+    fn process_keys_while_disabled(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        _output: &mut T,
+    ) {
+        rewrite_releases_as_recorded(&mut self.rewritten, events);
+    }
+    fn reset(&mut self, _output: &mut T) {
+        self.rewritten.clear();
+    }
+    fn default_enabled(&self) -> bool {
+        false
+    }
+}
+
 #[cfg(test)]
 //#[macro_use]
 //extern crate std;
@@ -162,6 +287,37 @@ mod tests {
         keyboard.output.clear();
     }
 
+    #[test]
+    fn test_layer_disable_in_the_middle_release_rewritten() {
+        //the unicode keyboard acts on the release - so the release
+        //must still be rewritten after the layer is disabled
+        const MAP: &[(u32, u32)] = &[(KeyCode::A.to_u32(), 0xDF)];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        let layer_id = keyboard.add_handler(Box::new(RewriteLayer::new(MAP)));
+        keyboard.add_handler(Box::new(UnicodeKeyboard::new()));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().enable_handler(layer_id);
+        keyboard.add_keypress(KeyCode::A, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[]]);
+        keyboard.output.clear();
+
+        keyboard.output.state().disable_handler(layer_id);
+        keyboard.add_keyrelease(KeyCode::A, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::D], &[KeyCode::F], &[]]);
+        assert!(keyboard.events.is_empty());
+        keyboard.output.clear();
+
+        //and it's not rewritten again
+        keyboard.add_keypress(KeyCode::A, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keyrelease(KeyCode::A, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[KeyCode::A], &[]]);
+    }
+
     #[test]
     fn test_layer_rewrite_unicode() {
         const MAP: &[(u32, u32)] = &[(KeyCode::A.to_u32(), 0xDF)];
@@ -184,4 +340,63 @@ mod tests {
         keyboard.handle_keys().unwrap();
         check_output(&keyboard, &[&[KeyCode::D], &[KeyCode::F], &[KeyCode::J]]);
     }
+
+    #[test]
+    fn test_rewrite_layer_shifted() {
+        use crate::handlers::{AutoOff, Layer, LayerAction, RewriteLayerShifted};
+        use crate::Modifier::Shift;
+        const MAP: &[(u32, u32, u32)] = &[(KeyCode::A.to_u32(), 0xC6, 0xF6)];
+        //the same keys through a Layer and the const variant
+        let mut outputs = Vec::new();
+        for use_const in &[false, true] {
+            let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+            let layer_id = if *use_const {
+                keyboard.add_handler(Box::new(RewriteLayerShifted::new(MAP)))
+            } else {
+                keyboard.add_handler(Box::new(Layer::new(
+                    vec![(KeyCode::A, LayerAction::RewriteToShifted(0xC6, 0xF6))],
+                    AutoOff::No,
+                )))
+            };
+            assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+            keyboard.output.state().enable_handler(layer_id);
+            keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+            keyboard.add_handler(Box::new(UnicodeKeyboard::new()));
+            keyboard.add_handler(Box::new(USBKeyboard::new()));
+
+            keyboard.add_keypress(KeyCode::A, 0);
+            keyboard.handle_keys().unwrap();
+            keyboard.add_keyrelease(KeyCode::A, 0);
+            keyboard.handle_keys().unwrap();
+            //shift changes between press and release - the release follows the press
+            keyboard.output.state().set_modifier(Shift, true);
+            keyboard.add_keypress(KeyCode::A, 0);
+            keyboard.handle_keys().unwrap();
+            keyboard.output.state().set_modifier(Shift, false);
+            keyboard.add_keyrelease(KeyCode::A, 0);
+            keyboard.handle_keys().unwrap();
+            //untouched keys
+            keyboard.add_keypress(KeyCode::B, 0);
+            keyboard.handle_keys().unwrap();
+            keyboard.add_keyrelease(KeyCode::B, 0);
+            keyboard.handle_keys().unwrap();
+            assert!(keyboard.events.is_empty());
+            outputs.push(keyboard.output.reports.clone());
+        }
+        assert!(outputs[0] == outputs[1]);
+        use KeyCode::*;
+        let expected: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![C.to_u8()], //c6 unshifted
+            vec![Kb6.to_u8()],
+            vec![],
+            vec![LShift.to_u8()],
+            vec![F.to_u8()], //f6 shifted, though shift is gone by the release
+            vec![Kb6.to_u8()],
+            vec![],
+            vec![B.to_u8()],
+            vec![],
+        ];
+        assert!(outputs[1] == expected);
+    }
 }
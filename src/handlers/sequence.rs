@@ -1,5 +1,5 @@
 use crate::handlers::{Action, ProcessKeys, HandlerResult};
-use crate::key_codes::{KeyCode, KeyCodeInfo};
+use crate::key_codes::{describe_keycode, KeyCode, KeyCodeInfo};
 use crate::{iter_unhandled_mut, Event, EventStatus, USBKeyOut};
 use no_std_compat::prelude::v1::*;
 
@@ -17,6 +17,15 @@ use no_std_compat::prelude::v1::*;
 ///
 /// Note that for a final KeyCode::*, you will need to send a backspace,
 /// but for a final unicode (or private) one you don't.
+/// new_auto counts them for you: one per character producing KeyCode::*
+/// (letters, digits, punctuation, space, keypad digits/operators) and one
+/// per unicode key except a final one. Private keys, modifiers and
+/// other non-printing keys (Enter, Tab, F-keys...) are not counted.
+///
+/// Use new_ex with fire_on_press = true to trigger as soon as the last key
+/// is pressed (e.g. for text expanders). The last key is then swallowed, so
+/// one backspace less is sent if it's a KeyCode::* - pass the same backspaces
+/// as for a release triggered Sequence.
 ///
 /// Sequences that are prefixes of others require you to double
 /// up on the last key stroke of the prefix. Hitting it the first time
@@ -27,10 +36,20 @@ pub struct Sequence<'a, M> {
     callback: M,
     backspaces: u8,
     pos: u8,
+    fire_on_press: bool,
+    swallow_release: Option<u32>, // original_keycode of a press that fired
 }
 
 impl<'a, M: Action> Sequence<'a, M> {
     pub fn new(sequence: &'a [u32], callback: M, backspaces: u8) -> Sequence<'a, M> {
+        Sequence::new_ex(sequence, callback, backspaces, false)
+    }
+    pub fn new_ex(
+        sequence: &'a [u32],
+        callback: M,
+        backspaces: u8,
+        fire_on_press: bool,
+    ) -> Sequence<'a, M> {
         if sequence.len() > 254 {
             panic!("Sequence too long, max 254 key codes");
         }
@@ -39,11 +58,51 @@ impl<'a, M: Action> Sequence<'a, M> {
             callback,
             backspaces,
             pos: 0,
+            fire_on_press,
+            swallow_release: None,
         }
     }
+    /// like new, with the backspaces counted from the sequence
+    /// (see the rules above)
+    pub fn new_auto(sequence: &'a [u32], callback: M) -> Sequence<'a, M> {
+        Sequence::new(sequence, callback, auto_backspaces(sequence))
+    }
+    fn is_last_pos(&self) -> bool {
+        self.pos as usize == self.sequence.len() - 1
+    }
+}
+
+/// whether the USBKeyboard typing this produces a character
+fn is_printing_usb_keycode(keycode: u32) -> bool {
+    let ranges = [
+        KeyCode::A.to_u32()..=KeyCode::Kb0.to_u32(),
+        KeyCode::Space.to_u32()..=KeyCode::Slash.to_u32(),
+        KeyCode::KpSlash.to_u32()..=KeyCode::KpPlus.to_u32(),
+        KeyCode::Kp1.to_u32()..=KeyCode::NonUsBslash.to_u32(),
+    ];
+    ranges.iter().any(|r| r.contains(&keycode))
+}
+
+/// the number of characters typed by the time sequence triggers
+fn auto_backspaces(sequence: &[u32]) -> u8 {
+    let mut count: u8 = 0;
+    for (ii, keycode) in sequence.iter().enumerate() {
+        let is_last = ii == sequence.len() - 1;
+        if is_printing_usb_keycode(*keycode) || (keycode.is_unicode_keycode() && !is_last) {
+            count = count.saturating_add(1);
+        }
+    }
+    count
 }
 
 impl<T: USBKeyOut, M: Action> ProcessKeys<T> for Sequence<'_, M> {
+    fn describe(&self) -> String {
+        let keys: Vec<String> = self.sequence.iter().map(|k| describe_keycode(*k)).collect();
+        format!("Sequence({}, backspaces: {})", keys.join(" "), self.backspaces)
+    }
+    fn reset(&mut self, _output: &mut T) {
+        self.pos = 0;
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) ->HandlerResult {
         let mut codes_to_delete: Vec<u32> = Vec::new();
         // we need to scan for handled key releases if we don't see any unhandled ones -
@@ -53,8 +112,18 @@ impl<T: USBKeyOut, M: Action> ProcessKeys<T> for Sequence<'_, M> {
         for (event, status) in iter_unhandled_mut(events).rev() {
             match event {
                 Event::KeyRelease(kc) => {
+                    if self.swallow_release == Some(kc.original_keycode) {
+                        self.swallow_release = None;
+                        *status = EventStatus::Handled;
+                        continue;
+                    }
                     matched = true;
-                    if kc.keycode == self.sequence[self.pos as usize] {
+                    if self.fire_on_press && self.is_last_pos() {
+                        //the press decides
+                        if kc.keycode != self.sequence[self.pos as usize] {
+                            self.pos = 0;
+                        }
+                    } else if kc.keycode == self.sequence[self.pos as usize] {
                         if kc.keycode.is_private_keycode() {
                             *status = EventStatus::Handled;
                         }
@@ -79,6 +148,27 @@ impl<T: USBKeyOut, M: Action> ProcessKeys<T> for Sequence<'_, M> {
                     if codes_to_delete.contains(&kc.original_keycode) {
                         *status = EventStatus::Handled;
                     }
+                    if self.fire_on_press
+                        && self.is_last_pos()
+                        && kc.keycode == self.sequence[self.pos as usize]
+                        && (kc.flag & 1) == 0
+                    {
+                        //the key never reaches the host - no need to backspace it
+                        let backspaces = if kc.keycode.is_usb_keycode() {
+                            self.backspaces.saturating_sub(1)
+                        } else {
+                            self.backspaces
+                        };
+                        self.pos = 0;
+                        for _ in 0..backspaces {
+                            output.send_keys(&[KeyCode::BSpace]);
+                            output.send_empty();
+                        }
+                        self.callback.on_trigger(output);
+                        *status = EventStatus::Handled;
+                        self.swallow_release = Some(kc.original_keycode);
+                        continue;
+                    }
                     if kc.keycode == self.sequence[self.pos as usize]
                         && kc.keycode.is_private_keycode()
                     {
@@ -222,6 +312,33 @@ mod tests {
         k.pc(0x1234, &[&[]]);
         k.rc(0x1234, &[&[BSpace], &[], &[X]]);
     }
+    #[test]
+    fn test_sequence_auto_backspaces() {
+        use crate::handlers::sequence::auto_backspaces;
+        use crate::key_codes::KeyCode::*;
+        assert!(auto_backspaces(&[A.to_u32(), B.to_u32(), C.to_u32()]) == 3);
+        assert!(auto_backspaces(&[0xDF, B.to_u32(), C.to_u32()]) == 3);
+        //private keys don't type anything
+        assert!(auto_backspaces(&[UserKey::UK1.to_u32(), B.to_u32(), C.to_u32()]) == 2);
+        //nor does a final unicode one
+        assert!(auto_backspaces(&[A.to_u32(), UserKey::UK1.to_u32(), 0x1234]) == 1);
+        //non printing keys
+        assert!(auto_backspaces(&[LShift.to_u32(), A.to_u32(), Enter.to_u32()]) == 1);
+
+        let map = &[A.to_u32(), 0xDF, B.to_u32()];
+        let mut k = Keyboard::new(KeyOutCatcher::new());
+        k.output.state().unicode_mode = UnicodeSendMode::Debug;
+        k.add_handler(Box::new(Sequence::new_auto(map, X)));
+        k.add_handler(Box::new(UnicodeKeyboard::new()));
+        k.add_handler(Box::new(USBKeyboard::new()));
+        k.pc(A, &[&[A]]);
+        k.rc(A, &[&[]]);
+        k.pc(0xDF, &[&[]]);
+        k.rc(0xDF, &[&[D], &[F], &[]]);
+        k.pc(B, &[&[B]]);
+        k.rc(B, &[&[BSpace], &[], &[BSpace], &[], &[BSpace], &[], &[X]]);
+    }
+
     #[test]
     fn test_dual_sequence() {
         use crate::key_codes::KeyCode::*;
@@ -270,4 +387,41 @@ mod tests {
         k.pc(C, &[&[C]]);
         k.rc(C, &[&[BSpace], &[], &[BSpace], &[], &[BSpace], &[], &[Y]]);
     }
+
+    #[test]
+    fn test_sequence_fire_on_press() {
+        use crate::key_codes::KeyCode::*;
+        let map = &[A.to_u32(), B.to_u32(), C.to_u32()];
+        let mut on_release = Keyboard::new(KeyOutCatcher::new());
+        on_release.add_handler(Box::new(Sequence::new_ex(map, X, 3, false)));
+        on_release.add_handler(Box::new(USBKeyboard::new()));
+        let mut on_press = Keyboard::new(KeyOutCatcher::new());
+        on_press.add_handler(Box::new(Sequence::new_ex(map, X, 3, true)));
+        on_press.add_handler(Box::new(USBKeyboard::new()));
+
+        for k in [&mut on_release, &mut on_press].iter_mut() {
+            k.pc(A, &[&[A]]);
+            k.rc(A, &[&[]]);
+            k.pc(B, &[&[B]]);
+            k.rc(B, &[&[]]);
+        }
+
+        on_release.pc(C, &[&[C]]);
+        on_release.rc(C, &[&[BSpace], &[], &[BSpace], &[], &[BSpace], &[], &[X]]);
+
+        //C never reaches the host, so only A and B are backspaced
+        on_press.pc(C, &[&[BSpace], &[], &[BSpace], &[], &[X]]);
+        on_press.rc(C, &[&[]]);
+        assert!(on_press.events.is_empty());
+
+        //and it starts over
+        on_press.pc(A, &[&[A]]);
+        on_press.rc(A, &[&[]]);
+        on_press.pc(B, &[&[B]]);
+        on_press.rc(B, &[&[]]);
+        on_press.pc(D, &[&[D]]);
+        on_press.rc(D, &[&[]]);
+        on_press.pc(C, &[&[C]]);
+        on_press.rc(C, &[&[]]);
+    }
 }
@@ -1,5 +1,5 @@
 use crate::handlers::{Action, OnOff, ProcessKeys, HandlerResult};
-use crate::key_codes::AcceptsKeycode;
+use crate::key_codes::{describe_keycode, AcceptsKeycode};
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
 use crate::USBKeyOut;
 use no_std_compat::prelude::v1::*;
@@ -13,6 +13,37 @@ enum SpaceCadetState {
     PressedTap, //must be a tap
 }
 
+/// What a SpaceCadet does while it is held.
+///
+/// Implemented for every OnOff - activated by the first other keypress,
+/// deactivated when the trigger is released.
+/// SpaceCadetAction instead runs an Action for every other keypress.
+pub trait SpaceCadetHold {
+    fn hold_start(&mut self, output: &mut dyn USBKeyOut);
+    /// a (non trigger) key was pressed while held - including
+    /// the one that started the hold
+    fn hold_keypress(&mut self, output: &mut dyn USBKeyOut);
+    fn hold_end(&mut self, output: &mut dyn USBKeyOut);
+}
+impl<M: OnOff> SpaceCadetHold for M {
+    fn hold_start(&mut self, output: &mut dyn USBKeyOut) {
+        self.on_activate(output);
+    }
+    fn hold_keypress(&mut self, _output: &mut dyn USBKeyOut) {}
+    fn hold_end(&mut self, output: &mut dyn USBKeyOut) {
+        self.on_deactivate(output);
+    }
+}
+/// the hold part of a SpaceCadetAction
+pub struct EachPress<M>(M);
+impl<M: Action> SpaceCadetHold for EachPress<M> {
+    fn hold_start(&mut self, _output: &mut dyn USBKeyOut) {}
+    fn hold_keypress(&mut self, output: &mut dyn USBKeyOut) {
+        self.0.on_trigger(output);
+    }
+    fn hold_end(&mut self, _output: &mut dyn USBKeyOut) {}
+}
+
 /// SpaceCadet Keys
 /// are keys that do one Action on tap,
 /// and an OnOff if depressed while the
@@ -20,15 +51,21 @@ enum SpaceCadetState {
 ///
 /// There is a minimum time the key needs to be depressed,
 /// which you can configure with SpaceCadet.minimum_depress_ms
+/// or with_minimum_depress
 /// (this is to allow fast typing where you actually hit the next
 /// key before the previous one has been released. It does
 /// happend...)
+/// Use new_global_timeout to use KeyboardState::tapping_term_ms instead
+/// (minimum_depress_ms is then the fallback if that is not set).
 ///
 /// They need to be added before
 /// the layer they toggle (if used with a layer),
 /// so you will have to use keyboard.future_handler_id(2)
 /// for the handler id in premade::spacecadet_handler
 ///
+/// The tap action is sent together with any active modifiers,
+/// so a tap with shift held (or a one shot shift armed) is shifted.
+///
 /// Please note if you want a premade::one_shot_* (modifier) to
 /// work correctly with a space cadet,
 /// the one_shot must come first in the list of handlers
@@ -40,9 +77,11 @@ pub struct SpaceCadet<MAction, MOnOff> {
     onoff: MOnOff,
     press_number: u8,
     state: SpaceCadetState,
+    held_presses: Vec<u8>, //running_numbers hold_keypress has been called for
     pub minimum_depress_ms: u16,
+    minimum_depress_from_state: bool,
 }
-impl<MAction: Action, MOnOff: OnOff> SpaceCadet<MAction, MOnOff> {
+impl<MAction: Action, MOnOff: SpaceCadetHold> SpaceCadet<MAction, MOnOff> {
     pub fn new(
         trigger: impl AcceptsKeycode,
         action: MAction,
@@ -54,13 +93,52 @@ impl<MAction: Action, MOnOff: OnOff> SpaceCadet<MAction, MOnOff> {
             onoff,
             press_number: 0, //what was the running id of this?
             state: SpaceCadetState::Base,
+            held_presses: Vec::new(),
             minimum_depress_ms: 100,
+            minimum_depress_from_state: false,
+        }
+    }
+    pub fn new_global_timeout(
+        trigger: impl AcceptsKeycode,
+        action: MAction,
+        onoff: MOnOff,
+    ) -> SpaceCadet<MAction, MOnOff> {
+        SpaceCadet {
+            minimum_depress_from_state: true,
+            ..SpaceCadet::new(trigger, action, onoff)
+        }
+    }
+    pub fn with_minimum_depress(self, minimum_depress_ms: u16) -> SpaceCadet<MAction, MOnOff> {
+        SpaceCadet {
+            minimum_depress_ms,
+            ..self
         }
     }
 }
-impl<T: USBKeyOut, MAction: Action, MOnOff: OnOff> ProcessKeys<T> for SpaceCadet<MAction, MOnOff> {
+impl<T: USBKeyOut, MAction: Action, MOnOff: SpaceCadetHold> ProcessKeys<T>
+    for SpaceCadet<MAction, MOnOff>
+{
+    fn describe(&self) -> String {
+        format!(
+            "SpaceCadet(trigger: {}, minimum_depress_ms: {})",
+            describe_keycode(self.trigger),
+            self.minimum_depress_ms
+        )
+    }
+    fn reset(&mut self, output: &mut T) {
+        if let SpaceCadetState::Activated = self.state {
+            self.onoff.hold_end(output);
+        }
+        self.state = SpaceCadetState::Base;
+        self.held_presses.clear();
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) ->HandlerResult {
         let mut any_other_seen = false;
+        let minimum_depress_ms = if self.minimum_depress_from_state {
+            output.ro_state().tapping_term_ms.unwrap_or(self.minimum_depress_ms)
+        } else {
+            self.minimum_depress_ms
+        };
         for (event, status) in iter_unhandled_mut(events) {
             match event {
                 Event::KeyPress(kc) => {
@@ -79,9 +157,11 @@ impl<T: USBKeyOut, MAction: Action, MOnOff: OnOff> ProcessKeys<T> for SpaceCadet
                     } else {
                         match self.state {
                             SpaceCadetState::Pressed => {
-                                if kc.ms_since_last >= self.minimum_depress_ms {
+                                if kc.ms_since_last >= minimum_depress_ms {
                                     self.state = SpaceCadetState::Activated;
-                                    self.onoff.on_activate(output);
+                                    self.onoff.hold_start(output);
+                                    self.held_presses.push(kc.running_number);
+                                    self.onoff.hold_keypress(output);
                                 } else {
                                     //a 'botched' activation
                                     self.action.on_trigger(output);
@@ -91,7 +171,14 @@ impl<T: USBKeyOut, MAction: Action, MOnOff: OnOff> ProcessKeys<T> for SpaceCadet
                             SpaceCadetState::Base => {
                                 any_other_seen = true;
                             }
-                            _ => {}
+                            SpaceCadetState::Activated => {
+                                //presses stay in the stream until released
+                                if !self.held_presses.contains(&kc.running_number) {
+                                    self.held_presses.push(kc.running_number);
+                                    self.onoff.hold_keypress(output);
+                                }
+                            }
+                            SpaceCadetState::PressedTap => {}
                         }
                     }
                 }
@@ -104,7 +191,8 @@ impl<T: USBKeyOut, MAction: Action, MOnOff: OnOff> ProcessKeys<T> for SpaceCadet
                             }
                             SpaceCadetState::Activated => {
                                 self.state = SpaceCadetState::Base;
-                                self.onoff.on_deactivate(output);
+                                self.held_presses.clear();
+                                self.onoff.hold_end(output);
                             }
                             SpaceCadetState::Base | SpaceCadetState::PressedTap => {}
                         }
@@ -117,6 +205,58 @@ impl<T: USBKeyOut, MAction: Action, MOnOff: OnOff> ProcessKeys<T> for SpaceCadet
     }
 }
 
+/// A SpaceCadet that runs an Action for every keypress
+/// while it is held (instead of an OnOff),
+/// e.g. to send every letter twice.
+///
+/// The keypresses themselves are passed on.
+/// Tapping works as with a SpaceCadet.
+pub struct SpaceCadetAction<MAction, MHold> {
+    cadet: SpaceCadet<MAction, EachPress<MHold>>,
+}
+impl<MAction: Action, MHold: Action> SpaceCadetAction<MAction, MHold> {
+    pub fn new(
+        trigger: impl AcceptsKeycode,
+        action: MAction,
+        hold_action: MHold,
+    ) -> SpaceCadetAction<MAction, MHold> {
+        SpaceCadetAction {
+            cadet: SpaceCadet::new(trigger, action, EachPress(hold_action)),
+        }
+    }
+    pub fn new_global_timeout(
+        trigger: impl AcceptsKeycode,
+        action: MAction,
+        hold_action: MHold,
+    ) -> SpaceCadetAction<MAction, MHold> {
+        SpaceCadetAction {
+            cadet: SpaceCadet::new_global_timeout(trigger, action, EachPress(hold_action)),
+        }
+    }
+    pub fn with_minimum_depress(self, minimum_depress_ms: u16) -> SpaceCadetAction<MAction, MHold> {
+        SpaceCadetAction {
+            cadet: self.cadet.with_minimum_depress(minimum_depress_ms),
+        }
+    }
+}
+impl<T: USBKeyOut, MAction: Action, MHold: Action> ProcessKeys<T>
+    for SpaceCadetAction<MAction, MHold>
+{
+    fn describe(&self) -> String {
+        format!(
+            "SpaceCadetAction(trigger: {}, minimum_depress_ms: {})",
+            describe_keycode(self.cadet.trigger),
+            self.cadet.minimum_depress_ms
+        )
+    }
+    fn reset(&mut self, output: &mut T) {
+        self.cadet.reset(output)
+    }
+    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) ->HandlerResult {
+        self.cadet.process_keys(events, output)
+    }
+}
+
 /*
 let mut initial_keypress_status: Option<EventStatus> = None;
 for (event, status) in iter_unhandled_mut(events) {
@@ -171,7 +311,7 @@ if let Some(new_status) = initial_keypress_status {
 //#[macro_use]
 //extern crate std;
 mod tests {
-    use crate::handlers::{SpaceCadet, USBKeyboard};
+    use crate::handlers::{SpaceCadet, SpaceCadetAction, USBKeyboard};
     #[allow(unused_imports)]
     use crate::key_codes::KeyCode;
     #[allow(unused_imports)]
@@ -229,6 +369,123 @@ mod tests {
         keyboard.output.clear();
     }
 
+    #[test]
+    fn test_space_cadet_action() {
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let l = SpaceCadetAction::new(X, X, vec![Kb1]);
+        let threshold = l.cadet.minimum_depress_ms;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //the tap
+        keyboard.pc(X, &[&[]]);
+        keyboard.rc(X, &[&[X]]);
+        //the hold - once per keypress
+        keyboard.pc(X, &[&[]]);
+        keyboard.pct(A, threshold, &[&[Kb1], &[], &[A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.pc(B, &[&[Kb1], &[], &[B]]);
+        keyboard.rc(B, &[&[]]);
+        keyboard.rc(X, &[&[]]);
+        //and no more
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_space_cadet_reset() {
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let counter = Arc::new(RwLock::new(PressCounter {
+            down_counter: 0,
+            up_counter: 0,
+        }));
+        let l = SpaceCadet::new(X, X, counter.clone());
+        let threshold = l.minimum_depress_ms;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let id = keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(X, &[&[]]);
+        keyboard.pct(Z, threshold, &[&[H], &[Z]]);
+        assert!(counter.read().down_counter == 1);
+        //the hold is ended
+        keyboard.reset_handler(id);
+        assert!(counter.read().up_counter == 1);
+        keyboard.rc(Z, &[&[I], &[]]);
+        //and the release is neither a tap nor a second hold_end
+        keyboard.rc(X, &[&[]]);
+        assert!(counter.read().up_counter == 1);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_space_cadet_global_timeout() {
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let counter = Arc::new(RwLock::new(PressCounter {
+            down_counter: 0,
+            up_counter: 0,
+        }));
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(SpaceCadet::new_global_timeout(X, X, counter.clone())));
+        keyboard.add_handler(Box::new(SpaceCadetAction::new_global_timeout(Y, Y, vec![Kb2])));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().tapping_term_ms = Some(300);
+        //past the default minimum_depress_ms, but within the global term
+        keyboard.pc(X, &[&[]]);
+        keyboard.pct(A, 200, &[&[X, A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.rc(X, &[&[]]);
+        keyboard.pc(Y, &[&[]]);
+        keyboard.pct(A, 200, &[&[Y, A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.rc(Y, &[&[]]);
+        assert!(counter.read().down_counter == 0);
+        //past the global term
+        keyboard.pc(X, &[&[]]);
+        keyboard.pct(A, 300, &[&[H], &[A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.rc(X, &[&[I], &[]]);
+        keyboard.pc(Y, &[&[]]);
+        keyboard.pct(A, 300, &[&[Kb2], &[], &[A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.rc(Y, &[&[]]);
+        assert!(counter.read().down_counter == 1);
+
+        //falls back to minimum_depress_ms
+        keyboard.output.state().tapping_term_ms = None;
+        keyboard.pc(X, &[&[]]);
+        keyboard.pct(A, 200, &[&[H], &[A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.rc(X, &[&[I], &[]]);
+        assert!(counter.read().down_counter == 2);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_space_cadet_action_press_held_back() {
+        use crate::handlers::AutoShift;
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let l = SpaceCadetAction::new(X, X, vec![Kb1]);
+        let threshold = l.cadet.minimum_depress_ms;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        //keeps the press of A from being sent until it's released
+        keyboard.add_handler(Box::new(AutoShift::new(1000)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(X, &[&[]]);
+        keyboard.pct(A, threshold, &[&[Kb1], &[], &[]]);
+        //still once for that keypress
+        keyboard.tc(10, &[&[]]);
+        keyboard.tc(10, &[&[]]);
+        keyboard.rc(A, &[&[A], &[]]);
+        keyboard.rc(X, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
     fn test_space_cadet_fast_typing() {
         let counter = Arc::new(RwLock::new(PressCounter {
             down_counter: 0,
@@ -353,6 +610,38 @@ mod tests {
         k.rc(KeyCode::X, &[&[KeyCode::LShift, KeyCode::X]]);
     }
 
+    #[test]
+    fn test_space_cadet_tap_with_armed_oneshot() {
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        use crate::Modifier::Shift;
+
+        let counter = Arc::new(RwLock::new(PressCounter {
+            down_counter: 0,
+            up_counter: 0,
+        }));
+        let l = SpaceCadet::new(KeyCode::X, KeyCode::X, counter.clone());
+        let mut k = Keyboard::new(KeyOutCatcher::new());
+        k.add_handler(premade::one_shot_shift(0, 0));
+        k.add_handler(Box::new(l));
+        k.add_handler(Box::new(USBKeyboard::new()));
+
+        //arm the OneShot - a tap, not a held shift
+        k.pc(KeyCode::LShift, &[&[KeyCode::LShift]]);
+        k.rc(KeyCode::LShift, &[&[KeyCode::LShift]]);
+        assert!(k.output.state().modifier(Shift));
+        //undecided until the release, then resolved as a tap - and shifted
+        k.pc(KeyCode::X, &[&[KeyCode::LShift]]);
+        k.rc(KeyCode::X, &[&[KeyCode::LShift, KeyCode::X]]);
+        assert!(counter.read().down_counter == 0);
+        assert!(counter.read().up_counter == 0);
+        //the OneShot has been used up
+        k.pc(KeyCode::A, &[&[KeyCode::A]]);
+        k.rc(KeyCode::A, &[&[]]);
+        assert!(!k.output.state().modifier(Shift));
+        assert!(k.events.is_empty());
+    }
+
     /*
         #[test]
         fn test_space_cadet_rewrite() {
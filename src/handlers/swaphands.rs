@@ -0,0 +1,200 @@
+use crate::handlers::layer::{
+    forget_stale_rewrite, rewrite_release_as_recorded, rewrite_releases_as_recorded,
+};
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_codes::{describe_keycode, AcceptsKeycode};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::USBKeyOut;
+use no_std_compat::prelude::v1::*;
+
+/// Swap hands - mirror the keyboard while trigger is held,
+/// for one handed typing.
+///
+/// The mirror table is a list of symmetric pairs (e.g. (F, J)),
+/// each key is rewritten to the other one of its pair.
+///
+/// Like RewriteLayer, releases are rewritten like their presses were,
+/// even if the trigger has been released in the meantime.
+pub struct SwapHands {
+    trigger: u32,
+    mirror: &'static [(u32, u32)],
+    held: bool,
+    rewritten: Vec<(u32, u32)>, // original_keycode, keycode of rewritten presses
+}
+
+impl SwapHands {
+    pub fn new(trigger: impl AcceptsKeycode, mirror: &'static [(u32, u32)]) -> SwapHands {
+        SwapHands {
+            trigger: trigger.to_u32(),
+            mirror,
+            held: false,
+            rewritten: Vec::new(),
+        }
+    }
+    fn mirrored(&self, keycode: u32) -> Option<u32> {
+        for (left, right) in self.mirror.iter() {
+            if *left == keycode {
+                return Some(*right);
+            } else if *right == keycode {
+                return Some(*left);
+            }
+        }
+        None
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for SwapHands {
+    fn describe(&self) -> String {
+        let pairs: Vec<String> = self
+            .mirror
+            .iter()
+            .map(|(left, right)| {
+                format!("{} <-> {}", describe_keycode(*left), describe_keycode(*right))
+            })
+            .collect();
+        format!(
+            "SwapHands(trigger: {}, {})",
+            describe_keycode(self.trigger),
+            pairs.join(", ")
+        )
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        _output: &mut T,
+    ) -> HandlerResult {
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.trigger {
+                        self.held = true;
+                        *status = EventStatus::Handled;
+                        continue;
+                    }
+                    forget_stale_rewrite(&mut self.rewritten, kc);
+                    if self.held && (kc.flag & 2) == 0 {
+                        if let Some(to) = self.mirrored(kc.keycode) {
+                            kc.keycode = to;
+                            kc.flag |= 2;
+                            self.rewritten.push((kc.original_keycode, kc.keycode));
+                        }
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.trigger {
+                        self.held = false;
+                        *status = EventStatus::Handled;
+                    } else {
+                        rewrite_release_as_recorded(&mut self.rewritten, kc);
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+        HandlerResult::NoOp
+    }
+    fn on_disabled(&mut self, _output: &mut T) {
+        self.held = false;
+    }
+    fn reset(&mut self, _output: &mut T) {
+        self.held = false;
+        self.rewritten.clear();
+    }
+    fn process_keys_while_disabled(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        _output: &mut T,
+    ) {
+        rewrite_releases_as_recorded(&mut self.rewritten, events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{SwapHands, USBKeyboard};
+    use crate::key_codes::{KeyCode, UserKey};
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Keyboard;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    const MIRROR: &[(u32, u32)] = &[
+        (KeyCode::F.to_u32(), KeyCode::J.to_u32()),
+        (KeyCode::D.to_u32(), KeyCode::K.to_u32()),
+    ];
+
+    #[test]
+    fn test_swap_hands() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(SwapHands::new(UserKey::UK0, MIRROR)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(F, &[&[F]]);
+        keyboard.rc(F, &[&[]]);
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.pc(F, &[&[J]]);
+        keyboard.rc(F, &[&[]]);
+        //both directions
+        keyboard.pc(K, &[&[D]]);
+        keyboard.rc(K, &[&[]]);
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        //the release follows the press, not the trigger
+        keyboard.pc(F, &[&[J]]);
+        keyboard.rc(UserKey::UK0, &[&[J]]);
+        keyboard.rc(F, &[&[]]);
+        keyboard.pc(F, &[&[F]]);
+        keyboard.rc(F, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_swap_hands_lost_release() {
+        use crate::handlers::{HandlerResult, UnicodeKeyboard};
+        use crate::premade::FnHandler;
+        use crate::{iter_unhandled_mut, Event, EventStatus, USBKeyOut, UnicodeSendMode};
+        use alloc::sync::Arc;
+        use spin::RwLock;
+        const UNICODE_MIRROR: &[(u32, u32)] = &[(KeyCode::F.to_u32(), 0xDF)];
+        //0: pass, 1: abort on the release of F, 2: swallow the release of F
+        let mode = Arc::new(RwLock::new(0));
+        let mode2 = mode.clone();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        keyboard.add_handler(Box::new(FnHandler::new(
+            move |events: &mut Vec<(Event, EventStatus)>, output: &mut dyn USBKeyOut| {
+                for (e, status) in iter_unhandled_mut(events) {
+                    if let Event::KeyRelease(kc) = e {
+                        if kc.keycode == KeyCode::F.to_u32() {
+                            match *mode2.read() {
+                                1 => output.state().abort_and_clear_events(),
+                                2 => *status = EventStatus::Handled,
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                HandlerResult::NoOp
+            },
+        )));
+        keyboard.add_handler(Box::new(SwapHands::new(UserKey::UK0, UNICODE_MIRROR)));
+        keyboard.add_handler(Box::new(UnicodeKeyboard::new()));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        for lost in [1, 2].iter() {
+            keyboard.pc(UserKey::UK0, &[&[]]);
+            keyboard.pc(KeyCode::F, &[&[]]);
+            *mode.write() = *lost;
+            keyboard.add_keyrelease(UserKey::UK0, 0);
+            keyboard.add_keyrelease(KeyCode::F, 0);
+            keyboard.handle_keys().unwrap();
+            keyboard.output.clear();
+            *mode.write() = 0;
+            keyboard.events.clear();
+
+            //the next release of F is not rewritten (and typed as unicode)
+            keyboard.pc(KeyCode::F, &[&[KeyCode::F]]);
+            keyboard.rc(KeyCode::F, &[&[]]);
+            assert!(keyboard.events.is_empty());
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::handlers::{ProcessKeys, HandlerResult};
-use crate::key_codes::AcceptsKeycode;
+use crate::key_codes::{describe_keycode, AcceptsKeycode};
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
 use crate::USBKeyOut;
 use no_std_compat::prelude::v1::*;
@@ -19,12 +19,26 @@ pub trait TapDanceAction {
 ///(within a timeout), do different things.
 ///
 ///You will need to implement a TapDanceAction
-pub struct TapDance<M>{
+///
+///Use new_global_timeout to use KeyboardState::tapping_term_ms
+///(falling back to 250ms if that is not set)
+///
+///Use new_with_each_tap to get called back with the running
+///tap count on every tap (e.g. for visual feedback)
+///
+///Use with_tap_timeouts for a different window after each tap
+///(e.g. a long one for the first tap, shorter ones after that)
+///
+///Timeouts are u16 ms (about 65 seconds at most),
+///the tap count stops at 255.
+pub struct TapDance<M, F = fn(u8, &mut dyn USBKeyOut)> {
     trigger: u32,
     tap_count: u8,
     action: M,
-    //todo: add on_each_tap...
+    on_each_tap: Option<F>,
     timeout_ms: u16,
+    timeout_from_state: bool,
+    tap_timeouts: Option<&'static [u16]>,
 }
 
 impl <M: TapDanceAction> TapDance<M> {
@@ -33,12 +47,64 @@ impl <M: TapDanceAction> TapDance<M> {
             trigger: trigger.to_u32(),
             tap_count: 0,
             action,
+            on_each_tap: None,
             timeout_ms: timeout_ms,
+            timeout_from_state: false,
+            tap_timeouts: None,
+        }
+    }
+    pub fn new_global_timeout(trigger: impl AcceptsKeycode, action: M) -> TapDance<M> {
+        TapDance {
+            timeout_from_state: true,
+            ..TapDance::new(trigger, action, 250)
         }
     }
 }
-impl<T: USBKeyOut, M: TapDanceAction> ProcessKeys<T> for TapDance<M> {
+impl<M: TapDanceAction, F: FnMut(u8, &mut dyn USBKeyOut)> TapDance<M, F> {
+    pub fn new_with_each_tap(
+        trigger: impl AcceptsKeycode,
+        action: M,
+        timeout_ms: u16,
+        on_each_tap: F,
+    ) -> TapDance<M, F> {
+        TapDance {
+            trigger: trigger.to_u32(),
+            tap_count: 0,
+            action,
+            on_each_tap: Some(on_each_tap),
+            timeout_ms,
+            timeout_from_state: false,
+            tap_timeouts: None,
+        }
+    }
+    /// tap_timeouts[n - 1] is the timeout after the n-th tap,
+    /// taps past the end use the regular timeout
+    pub fn with_tap_timeouts(self, tap_timeouts: &'static [u16]) -> TapDance<M, F> {
+        TapDance {
+            tap_timeouts: Some(tap_timeouts),
+            ..self
+        }
+    }
+}
+impl<T: USBKeyOut, M: TapDanceAction, F: FnMut(u8, &mut dyn USBKeyOut)> ProcessKeys<T>
+    for TapDance<M, F>
+{
+    fn describe(&self) -> String {
+        format!(
+            "TapDance(trigger: {}, timeout_ms: {})",
+            describe_keycode(self.trigger),
+            self.timeout_ms
+        )
+    }
+    fn reset(&mut self, _output: &mut T) {
+        self.tap_count = 0;
+    }
     fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) ->HandlerResult {
+        let default_timeout_ms = if self.timeout_from_state {
+            output.ro_state().tapping_term_ms.unwrap_or(self.timeout_ms)
+        } else {
+            self.timeout_ms
+        };
         for (event, status) in iter_unhandled_mut(events) {
             match event {
                 Event::KeyRelease(kc) => {
@@ -53,12 +119,20 @@ impl<T: USBKeyOut, M: TapDanceAction> ProcessKeys<T> for TapDance<M> {
                             self.tap_count = 0;
                         }
                     } else {
-                        self.tap_count += 1;
+                        self.tap_count = self.tap_count.saturating_add(1);
                         *status = EventStatus::Handled;
+                        if let Some(on_each_tap) = self.on_each_tap.as_mut() {
+                            on_each_tap(self.tap_count, output);
+                        }
                     }
                 }
                 Event::TimeOut(ms_since_last) => {
-                    if self.tap_count > 0 && *ms_since_last >= self.timeout_ms {
+                    let timeout_ms = self
+                        .tap_timeouts
+                        .and_then(|timeouts| timeouts.get((self.tap_count as usize).wrapping_sub(1)))
+                        .copied()
+                        .unwrap_or(default_timeout_ms);
+                    if self.tap_count > 0 && *ms_since_last >= timeout_ms {
                             self.action.on_tapdance(self.trigger, output, self.tap_count, TapDanceEnd::Timeout);
                         self.tap_count = 0;
                     }
@@ -163,4 +237,120 @@ mod tests {
         assert!(record.read().other_key_taps == 3);
         assert!(record.read().timeout_taps == 3);
     }
+
+    #[test]
+    fn test_tapdance_many_taps() {
+        let record = Arc::new(RwLock::new(TapDanceLogger::new()));
+        let l = TapDance::new(KeyCode::X, record.clone(), 250);
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        for _ in 0..300 {
+            keyboard.pc(KeyCode::X, &[&[]]);
+            keyboard.rc(KeyCode::X, &[&[]]);
+        }
+        keyboard.tc(250, &[&[KeyCode::A], &[]]);
+        assert!(record.read().timeout_taps == 255);
+    }
+
+    #[test]
+    fn test_tapdance_reset() {
+        let record = Arc::new(RwLock::new(TapDanceLogger::new()));
+        let l = TapDance::new(KeyCode::X, record.clone(), 250);
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let id = keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        keyboard.reset_handler(id);
+        keyboard.tc(250, &[&[]]);
+        keyboard.pc(KeyCode::Z, &[&[KeyCode::Z]]);
+        keyboard.rc(KeyCode::Z, &[&[]]);
+        assert!(record.read().timeout_taps == 0);
+        assert!(record.read().other_key_taps == 0);
+        //and it dances again afterwards
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        keyboard.tc(250, &[&[KeyCode::A], &[]]);
+        assert!(record.read().timeout_taps == 1);
+        //unknown ids are ignored
+        keyboard.reset_handler(id + 10);
+    }
+
+    #[test]
+    fn test_tapdance_tap_timeouts() {
+        const TIMEOUTS: &[u16] = &[400];
+        let record = Arc::new(RwLock::new(TapDanceLogger::new()));
+        let l = TapDance::new(KeyCode::X, record.clone(), 150).with_tap_timeouts(TIMEOUTS);
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //a slow first tap still continues the dance
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        keyboard.tc(300, &[&[]]);
+        assert!(record.read().timeout_taps == 0);
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        //but a slow second tap completes it
+        keyboard.tc(200, &[&[KeyCode::A], &[]]);
+        assert!(record.read().timeout_taps == 2);
+
+        //the first tap times out after 400ms
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        keyboard.tc(400, &[&[KeyCode::A], &[]]);
+        assert!(record.read().timeout_taps == 3);
+    }
+
+    #[test]
+    fn test_tapdance_global_timeout() {
+        let record = Arc::new(RwLock::new(TapDanceLogger::new()));
+        let l = TapDance::new_global_timeout(KeyCode::X, record.clone());
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().tapping_term_ms = Some(400);
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        keyboard.tc(300, &[&[]]);
+        assert!(record.read().timeout_taps == 0);
+        keyboard.tc(400, &[&[KeyCode::A], &[]]);
+        assert!(record.read().timeout_taps == 1);
+
+        //falls back to the default
+        keyboard.output.state().tapping_term_ms = None;
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        keyboard.tc(250, &[&[KeyCode::A], &[]]);
+        assert!(record.read().timeout_taps == 2);
+    }
+
+    #[test]
+    fn test_tapdance_each_tap() {
+        let record = Arc::new(RwLock::new(TapDanceLogger::new()));
+        let taps: Arc<RwLock<Vec<u8>>> = Arc::new(RwLock::new(Vec::new()));
+        let taps_seen = taps.clone();
+        let l = TapDance::new_with_each_tap(
+            KeyCode::X,
+            record.clone(),
+            250,
+            move |tap_count: u8, _output: &mut dyn USBKeyOut| taps_seen.write().push(tap_count),
+        );
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(l));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        assert!(*taps.read() == vec![1]);
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        keyboard.pc(KeyCode::X, &[&[]]);
+        keyboard.rc(KeyCode::X, &[&[]]);
+        assert!(*taps.read() == vec![1, 2, 3]);
+        assert!(record.read().timeout_taps == 0);
+        keyboard.tc(250, &[&[KeyCode::A], &[]]);
+        assert!(record.read().timeout_taps == 3);
+        assert!(*taps.read() == vec![1, 2, 3]);
+    }
 }
@@ -0,0 +1,272 @@
+use crate::handlers::{Action, HandlerResult, OnOff, ProcessKeys};
+use crate::key_codes::{describe_keycode, AcceptsKeycode};
+use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+use crate::USBKeyOut;
+use no_std_compat::prelude::v1::*;
+
+#[repr(u8)]
+#[derive(Clone, Copy)]
+enum TapDanceHoldState {
+    Base,     //not triggered
+    Pressed,  //could be a tap or a hold
+    Released, //tapped - waiting for another tap
+    Held,     //hold is active
+}
+
+/// A TapDance with a hold - one key that
+/// does tap_actions[0] on a single tap, tap_actions[1] on a double tap...
+/// (the last one for more taps) and activates hold while it's held,
+/// e.g. a momentary layer.
+///
+/// A tap is a release before tapping_term_ms, the dance ends tapping_term_ms
+/// after the last release (or when another key is pressed).
+///
+/// Held past tapping_term_ms (or another key pressed after that),
+/// hold is activated until the trigger is released - on any tap,
+/// the taps before that are dropped.
+/// Another key pressed before tapping_term_ms counts as a tap (fast rolling).
+///
+/// TimeOuts need to be send regularly.
+pub struct TapDanceHold {
+    trigger: u32,
+    tap_actions: Vec<Box<dyn Action>>,
+    hold: Box<dyn OnOff + Send>,
+    tapping_term_ms: u16,
+    tap_count: u8,
+    state: TapDanceHoldState,
+    elapsed_ms: u16, //since the last press (Pressed) or release (Released)
+}
+impl TapDanceHold {
+    pub fn new(
+        trigger: impl AcceptsKeycode,
+        tap_actions: Vec<Box<dyn Action>>,
+        hold: Box<dyn OnOff + Send>,
+        tapping_term_ms: u16,
+    ) -> TapDanceHold {
+        TapDanceHold {
+            trigger: trigger.to_u32(),
+            tap_actions,
+            hold,
+            tapping_term_ms,
+            tap_count: 0,
+            state: TapDanceHoldState::Base,
+            elapsed_ms: 0,
+        }
+    }
+    fn finish_taps(&mut self, output: &mut dyn USBKeyOut) {
+        if self.tap_count > 0 && !self.tap_actions.is_empty() {
+            let index = (self.tap_count as usize - 1).min(self.tap_actions.len() - 1);
+            self.tap_actions[index].on_trigger(output);
+        }
+        self.tap_count = 0;
+        self.state = TapDanceHoldState::Base;
+    }
+    fn start_hold(&mut self, output: &mut dyn USBKeyOut) {
+        self.tap_count = 0;
+        self.state = TapDanceHoldState::Held;
+        self.hold.on_activate(output);
+    }
+}
+impl<T: USBKeyOut> ProcessKeys<T> for TapDanceHold {
+    fn describe(&self) -> String {
+        format!(
+            "TapDanceHold(trigger: {}, taps: {}, tapping_term_ms: {})",
+            describe_keycode(self.trigger),
+            self.tap_actions.len(),
+            self.tapping_term_ms
+        )
+    }
+    fn on_disabled(&mut self, output: &mut T) {
+        if let TapDanceHoldState::Held = self.state {
+            self.hold.on_deactivate(output);
+        }
+        self.tap_count = 0;
+        self.state = TapDanceHoldState::Base;
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        for (event, status) in iter_unhandled_mut(events) {
+            match event {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        if let TapDanceHoldState::Base | TapDanceHoldState::Released = self.state {
+                            self.tap_count = self.tap_count.saturating_add(1);
+                            self.state = TapDanceHoldState::Pressed;
+                            self.elapsed_ms = 0;
+                        }
+                    } else if kc.flag & 0x1 == 0 {
+                        match self.state {
+                            TapDanceHoldState::Pressed => {
+                                if self.elapsed_ms.saturating_add(kc.ms_since_last)
+                                    >= self.tapping_term_ms
+                                {
+                                    self.start_hold(output);
+                                } else {
+                                    //rolling over
+                                    self.finish_taps(output);
+                                }
+                            }
+                            TapDanceHoldState::Released => self.finish_taps(output),
+                            TapDanceHoldState::Base | TapDanceHoldState::Held => {}
+                        }
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        match self.state {
+                            TapDanceHoldState::Pressed => {
+                                if self.elapsed_ms.saturating_add(kc.ms_since_last)
+                                    < self.tapping_term_ms
+                                {
+                                    self.state = TapDanceHoldState::Released;
+                                    self.elapsed_ms = 0;
+                                } else {
+                                    //held without anything happening - a 'hold tap'
+                                    self.start_hold(output);
+                                    self.hold.on_deactivate(output);
+                                    self.state = TapDanceHoldState::Base;
+                                }
+                            }
+                            TapDanceHoldState::Held => {
+                                self.hold.on_deactivate(output);
+                                self.state = TapDanceHoldState::Base;
+                            }
+                            TapDanceHoldState::Base | TapDanceHoldState::Released => {}
+                        }
+                    } else if let TapDanceHoldState::Released = self.state {
+                        self.elapsed_ms = self.elapsed_ms.saturating_add(kc.ms_since_last);
+                    }
+                }
+                Event::TimeOut(ms_since_last) => match self.state {
+                    TapDanceHoldState::Pressed => {
+                        self.elapsed_ms = self.elapsed_ms.saturating_add(*ms_since_last);
+                        if self.elapsed_ms >= self.tapping_term_ms {
+                            self.start_hold(output);
+                        }
+                    }
+                    TapDanceHoldState::Released => {
+                        self.elapsed_ms = self.elapsed_ms.saturating_add(*ms_since_last);
+                        if self.elapsed_ms >= self.tapping_term_ms {
+                            self.finish_taps(output);
+                        }
+                    }
+                    TapDanceHoldState::Base | TapDanceHoldState::Held => {}
+                },
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{Action, TapDanceHold, USBKeyboard};
+    use crate::key_codes::UserKey;
+    use crate::test_helpers::{Checks, KeyOutCatcher, PressCounter};
+    use crate::Keyboard;
+    use alloc::sync::Arc;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+    use spin::RwLock;
+
+    #[test]
+    fn test_tapdancehold_single_tap() {
+        use crate::key_codes::KeyCode::*;
+        let counter = Arc::new(RwLock::new(PressCounter {
+            down_counter: 0,
+            up_counter: 0,
+        }));
+        let taps: Vec<Box<dyn Action>> = vec![Box::new(vec![X]), Box::new(vec![Y])];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(TapDanceHold::new(
+            UserKey::UK0,
+            taps,
+            Box::new(counter.clone()),
+            200,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rct(UserKey::UK0, 50, &[&[]]);
+        keyboard.tc(100, &[&[]]);
+        keyboard.tc(100, &[&[X], &[], &[]]);
+        //and another key ends the dance right away
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rct(UserKey::UK0, 50, &[&[]]);
+        keyboard.pc(A, &[&[X], &[], &[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(counter.read().down_counter == 0);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_tapdancehold_double_tap() {
+        use crate::key_codes::KeyCode::*;
+        let counter = Arc::new(RwLock::new(PressCounter {
+            down_counter: 0,
+            up_counter: 0,
+        }));
+        let taps: Vec<Box<dyn Action>> = vec![Box::new(vec![X]), Box::new(vec![Y])];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(TapDanceHold::new(
+            UserKey::UK0,
+            taps,
+            Box::new(counter.clone()),
+            200,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rct(UserKey::UK0, 50, &[&[]]);
+        keyboard.pct(UserKey::UK0, 50, &[&[]]);
+        keyboard.rct(UserKey::UK0, 50, &[&[]]);
+        keyboard.tc(250, &[&[Y], &[], &[]]);
+        //more taps stay on the last action
+        for _ in 0..3 {
+            keyboard.pc(UserKey::UK0, &[&[]]);
+            keyboard.rc(UserKey::UK0, &[&[]]);
+        }
+        keyboard.tc(250, &[&[Y], &[], &[]]);
+        assert!(counter.read().down_counter == 0);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_tapdancehold_hold() {
+        use crate::key_codes::KeyCode::*;
+        let counter = Arc::new(RwLock::new(PressCounter {
+            down_counter: 0,
+            up_counter: 0,
+        }));
+        let taps: Vec<Box<dyn Action>> = vec![Box::new(vec![X]), Box::new(vec![Y])];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(TapDanceHold::new(
+            UserKey::UK0,
+            taps,
+            Box::new(counter.clone()),
+            200,
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.tc(250, &[&[H], &[]]);
+        assert!(counter.read().down_counter == 1);
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[I], &[]]);
+        assert!(counter.read().up_counter == 1);
+
+        //tap, then hold - no tap action
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rct(UserKey::UK0, 50, &[&[]]);
+        keyboard.pct(UserKey::UK0, 50, &[&[]]);
+        keyboard.pct(A, 250, &[&[H], &[A]]);
+        assert!(counter.read().down_counter == 2);
+        keyboard.rc(A, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[I], &[]]);
+        assert!(counter.read().up_counter == 2);
+        assert!(keyboard.events.is_empty());
+    }
+}
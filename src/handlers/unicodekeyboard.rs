@@ -1,10 +1,12 @@
 use crate::handlers::{ProcessKeys, HandlerResult};
+use crate::key_codes::{KeyCodeInfo, UNICODE_BELOW_256};
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
 use crate::USBKeyOut;
 use no_std_compat::prelude::v1::*;
 /// This processor sends unicode 'characters'
 /// just map your keys to unicode 'code points'
 /// sending happens on keyrelease - no key repeat
+/// (see new_on_press for sending on keypress)
 ///
 /// the private ranges of unicode are not send,
 /// but some of them are intpreted as USB-Keycodes
@@ -17,38 +19,53 @@ impl UnicodeKeyboard {
         UnicodeKeyboard {}
     }
 
-    fn is_unicode_keycode(keycode: u32) -> bool {
-        match keycode {
-            0xF0000..=0xFFFFD => false,   //unicode private character range A
-            0x100000..=0x1000FE => false, //these are the usb codes
-            0x1000FF..=0x10FFFD => false, //unicode private character range b (minus those we use for codes < 256)
-            _ => true,
-        }
+    /// send the character on key press for immediate feedback,
+    /// the release is consumed without output
+    pub fn new_on_press() -> UnicodeKeyboardEx {
+        UnicodeKeyboard::new_ex(true)
+    }
+
+    /// new or new_on_press, chosen at runtime (e.g. from a stored config)
+    pub fn new_ex(send_on_press: bool) -> UnicodeKeyboardEx {
+        UnicodeKeyboardEx { send_on_press }
     }
+
     fn keycode_to_unicode(keycode: u32) -> u32 {
-        if keycode < 0x100_000 {
+        if keycode < UNICODE_BELOW_256 {
             keycode
         } else {
-            keycode - 0x100_000
+            keycode - UNICODE_BELOW_256
         }
     }
-}
-impl<T: USBKeyOut> ProcessKeys<T> for UnicodeKeyboard {
-    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) ->HandlerResult {
+    fn process_unicode_keys<T: USBKeyOut>(
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+        send_on_press: bool,
+    ) -> HandlerResult {
         for (event, status) in iter_unhandled_mut(events) {
             match event {
                 Event::KeyPress(kc) => {
-                    if UnicodeKeyboard::is_unicode_keycode(kc.keycode) {
+                    if kc.keycode.is_unicode_keycode() {
+                        if send_on_press {
+                            let c = no_std_compat::char::from_u32(
+                                UnicodeKeyboard::keycode_to_unicode(kc.keycode),
+                            );
+                            if let Some(c) = c {
+                                output.send_unicode(c);
+                            }
+                        }
                         *status = EventStatus::Handled;
                     }
                 }
                 Event::KeyRelease(kc) => {
-                    if UnicodeKeyboard::is_unicode_keycode(kc.keycode) {
-                        let c = no_std_compat::char::from_u32(UnicodeKeyboard::keycode_to_unicode(
-                            kc.keycode,
-                        ));
-                        if let Some(c) = c {
-                            output.send_unicode(c);
+                    if kc.keycode.is_unicode_keycode() {
+                        if !send_on_press {
+                            let c = no_std_compat::char::from_u32(
+                                UnicodeKeyboard::keycode_to_unicode(kc.keycode),
+                            );
+                            if let Some(c) = c {
+                                output.send_unicode(c);
+                            }
                         }
                         *status = EventStatus::Handled;
                     }
@@ -59,6 +76,22 @@ impl<T: USBKeyOut> ProcessKeys<T> for UnicodeKeyboard {
         HandlerResult::NoOp
     }
 }
+impl<T: USBKeyOut> ProcessKeys<T> for UnicodeKeyboard {
+    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) ->HandlerResult {
+        UnicodeKeyboard::process_unicode_keys(events, output, false)
+    }
+}
+
+/// A UnicodeKeyboard that sends on keypress or keyrelease,
+/// see UnicodeKeyboard::new_on_press and UnicodeKeyboard::new_ex
+pub struct UnicodeKeyboardEx {
+    send_on_press: bool,
+}
+impl<T: USBKeyOut> ProcessKeys<T> for UnicodeKeyboardEx {
+    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) ->HandlerResult {
+        UnicodeKeyboard::process_unicode_keys(events, output, self.send_on_press)
+    }
+}
 #[cfg(test)]
 //#[macro_use]
 //extern crate std;
@@ -125,6 +158,86 @@ mod tests {
         assert!(keyboard.events.is_empty()); // we eat the keypress though
     }
     #[test]
+    fn test_unicode_keyboard_macos() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(UnicodeKeyboard::new()));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::MacOs;
+        keyboard.add_keypress(0x00E4u32, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keyrelease(0x00E4u32, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(
+            &keyboard,
+            &[
+                &[LAlt],
+                &[LAlt, Kb0],
+                &[LAlt],
+                &[LAlt, Kb0],
+                &[LAlt],
+                &[LAlt, E],
+                &[LAlt],
+                &[LAlt, Kb4],
+                &[LAlt],
+                &[],
+            ],
+        );
+        keyboard.output.clear();
+
+        //U+1F600 is the surrogate pair D83D DE00
+        keyboard.add_keypress(0x1F600u32, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keyrelease(0x1F600u32, 0);
+        keyboard.handle_keys().unwrap();
+        let pairs: Vec<[KeyCode; 2]> = [D, Kb8, Kb3, D, D, E, Kb0, Kb0]
+            .iter()
+            .map(|digit| [LAlt, *digit])
+            .collect();
+        let mut should: Vec<&[KeyCode]> = vec![&[LAlt]];
+        for pair in pairs.iter() {
+            should.push(pair);
+            should.push(&[LAlt]);
+        }
+        should.push(&[]);
+        check_output(&keyboard, &should);
+        //option is released exactly once
+        assert!(keyboard.output.reports.iter().filter(|r| r.is_empty()).count() == 1);
+    }
+    #[test]
+    fn test_unicode_keyboard_win_alt_numpad() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(UnicodeKeyboard::new()));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::WinAltNumpad;
+        keyboard.add_keypress(0x00E4u32, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keyrelease(0x00E4u32, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(
+            &keyboard,
+            &[
+                &[LAlt],
+                &[LAlt, Kp0],
+                &[LAlt],
+                &[LAlt, Kp2],
+                &[LAlt],
+                &[LAlt, Kp2],
+                &[LAlt],
+                &[LAlt, Kp8],
+                &[LAlt],
+                &[],
+            ],
+        );
+        keyboard.output.clear();
+
+        //outside of the code page - nothing is send
+        keyboard.add_keypress(0x03B4u32, 0);
+        keyboard.handle_keys().unwrap();
+        keyboard.add_keyrelease(0x03B4u32, 0);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.reports.is_empty());
+    }
+    #[test]
     fn test_unicode_while_depressed() {
         use crate::key_codes::KeyCode::*;
         let mut keyboard = Keyboard::new(KeyOutCatcher::new());
@@ -152,4 +265,45 @@ mod tests {
         check_output(&keyboard, &[&[]]);
         assert!(keyboard.events.is_empty());
     }
+    #[test]
+    fn test_unicode_keyboard_on_press() {
+        use crate::key_codes::KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(UnicodeKeyboard::new_on_press()));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        keyboard.add_keypress(0x00E4u32, 0);
+        keyboard.handle_keys().unwrap();
+        check_output(&keyboard, &[&[E], &[Kb4]]);
+        assert!(keyboard.events.is_empty());
+        keyboard.output.clear();
+        keyboard.add_keyrelease(0x00E4u32, 0);
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.reports.is_empty());
+        assert!(keyboard.events.is_empty());
+    }
+    #[test]
+    fn test_unicode_keyboard_new_ex() {
+        use crate::key_codes::KeyCode::*;
+        for send_on_press in &[false, true] {
+            let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+            keyboard.add_handler(Box::new(UnicodeKeyboard::new_ex(*send_on_press)));
+            keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+            keyboard.add_keypress(0x00E4u32, 0);
+            keyboard.handle_keys().unwrap();
+            let on_press = keyboard.output.reports.clone();
+            keyboard.output.clear();
+            keyboard.add_keyrelease(0x00E4u32, 0);
+            keyboard.handle_keys().unwrap();
+            let on_release = keyboard.output.reports.clone();
+            let e4 = vec![vec![E.to_u8()], vec![Kb4.to_u8()]];
+            if *send_on_press {
+                assert!(on_press == e4);
+                assert!(on_release.is_empty());
+            } else {
+                assert!(on_press.is_empty());
+                assert!(on_release == e4);
+            }
+            assert!(keyboard.events.is_empty());
+        }
+    }
 }
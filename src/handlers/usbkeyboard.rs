@@ -2,7 +2,7 @@ use crate::handlers::{ProcessKeys, HandlerResult};
 use crate::key_codes::{KeyCode, KeyCodeInfo};
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
 use crate::Modifier::*;
-use crate::USBKeyOut;
+use crate::{USBKeyOut, MODIFIER_KEYS};
 use core::convert::TryInto;
 use no_std_compat::prelude::v1::*;
 use smallbitvec::sbvec;
@@ -14,19 +14,29 @@ use smallbitvec::sbvec;
 /// Just map your keys to the usb keycodes.
 ///
 /// key repeat is whatever usb does...
+///
+/// Any number of keys is registered (NKRO),
+/// see with_max_rollover for the boot protocol.
 #[derive(Default)]
 pub struct USBKeyboard {}
 impl USBKeyboard {
     pub fn new() -> USBKeyboard {
         USBKeyboard {}
     }
-}
-
-impl<T: USBKeyOut> ProcessKeys<T> for USBKeyboard {
-    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T)->HandlerResult {
+    /// a USBKeyboard for the boot protocol, see USBKeyboardMaxRollover
+    pub fn with_max_rollover(self, max_rollover: u8) -> USBKeyboardMaxRollover {
+        USBKeyboardMaxRollover { max_rollover }
+    }
+    fn process_usb_keys<T: USBKeyOut>(
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+        max_rollover: Option<u8>,
+    ) -> HandlerResult {
         //step 0: on key release, remove all prior key presses.
         let mut codes_to_delete: Vec<u32> = Vec::new();
         let mut modifiers_sent = sbvec![false; 4];
+        let mut registered: Vec<KeyCode> = Vec::new();
+        let mut unshift = false; // a key rewritten to be send without shift is down
         for (e, status) in iter_unhandled_mut(events).rev() {
             //note that we're doing this in reverse, ie. releases happen before presses.
             match e {
@@ -91,7 +101,10 @@ impl<T: USBKeyOut> ProcessKeys<T> for USBKeyboard {
                         match oc {
                             Ok(x) => {
                                 if send {
-                                    output.register_key(x);
+                                    registered.push(x);
+                                    if kc.flag & 8 != 0 {
+                                        unshift = true;
+                                    }
                                 }
                                 if *status != EventStatus::Handled {
                                     *status = EventStatus::Ignored; //so we may resend it...
@@ -105,22 +118,66 @@ impl<T: USBKeyOut> ProcessKeys<T> for USBKeyboard {
                 Event::TimeOut(_) => {}
             }
         }
-        if output.state().modifier(Shift) && !modifiers_sent[0] {
-            output.register_key(KeyCode::LShift);
+        if unshift {
+            registered.retain(|x| *x != KeyCode::LShift && *x != KeyCode::RShift);
         }
-        if output.state().modifier(Ctrl) && !modifiers_sent[1] {
-            output.register_key(KeyCode::LCtrl);
+        if let Some(max_rollover) = max_rollover {
+            let mut keys: Vec<KeyCode> = Vec::new();
+            for x in registered.iter() {
+                if !x.is_modifier() && !keys.contains(x) {
+                    keys.push(*x);
+                }
+            }
+            if keys.len() > max_rollover as usize {
+                registered.retain(|x| x.is_modifier());
+                registered.push(KeyCode::ErrorRollOver);
+            }
         }
-        if output.state().modifier(Alt) && !modifiers_sent[2] {
-            output.register_key(KeyCode::LAlt);
+        for x in registered {
+            output.register_key(x);
         }
-        if output.state().modifier(Gui) && !modifiers_sent[3] {
-            output.register_key(KeyCode::LGui);
+        for (ii, (modifier, left, right)) in MODIFIER_KEYS.iter().enumerate() {
+            if unshift && *modifier == Shift {
+                continue;
+            }
+            if output.state().modifier(*modifier) && !modifiers_sent[ii] {
+                if output.state().modifier_prefers_right(*modifier) {
+                    output.register_key(*right);
+                } else {
+                    output.register_key(*left);
+                }
+            }
         }
         output.send_registered();
         HandlerResult::NoOp
     }
 }
+
+impl<T: USBKeyOut> ProcessKeys<T> for USBKeyboard {
+    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T)->HandlerResult {
+        USBKeyboard::process_usb_keys(events, output, None)
+    }
+    fn runs_in_passthrough(&self) -> bool {
+        true
+    }
+}
+
+/// A USBKeyboard with limited rollover, e.g. 6 for the boot protocol -
+/// with more than max_rollover (non modifier) keys down,
+/// only ErrorRollOver is registered (the firmware should put it
+/// into every slot of the report), as the USB spec demands.
+pub struct USBKeyboardMaxRollover {
+    max_rollover: u8,
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for USBKeyboardMaxRollover {
+    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T)->HandlerResult {
+        USBKeyboard::process_usb_keys(events, output, Some(self.max_rollover))
+    }
+    fn runs_in_passthrough(&self) -> bool {
+        true
+    }
+}
 #[cfg(test)]
 //#[macro_use]
 //extern crate std;
@@ -161,6 +218,38 @@ mod tests {
         keyboard.add_keypress(0xF0000u32, 0);
         assert!(keyboard.handle_keys().is_err());
     }
+    #[test]
+    fn test_max_rollover() {
+        use crate::key_codes::KeyCode::*;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(USBKeyboard::new().with_max_rollover(6)));
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.pc(A, &[&[LShift, A]]);
+        keyboard.pc(B, &[&[LShift, A, B]]);
+        keyboard.pc(C, &[&[LShift, A, B, C]]);
+        keyboard.pc(D, &[&[LShift, A, B, C, D]]);
+        keyboard.pc(E, &[&[LShift, A, B, C, D, E]]);
+        keyboard.pc(F, &[&[LShift, A, B, C, D, E, F]]);
+        //one too many - modifiers are still reported
+        keyboard.pc(G, &[&[LShift, ErrorRollOver]]);
+        keyboard.rc(G, &[&[LShift, A, B, C, D, E, F]]);
+        for key in [A, B, C, D, E, F, LShift].iter() {
+            keyboard.add_keyrelease(*key, 0);
+        }
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.events.is_empty());
+
+        //no limit by default
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        for key in [A, B, C, D, E, F, G].iter() {
+            keyboard.add_keypress(*key, 0);
+        }
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.output.reports.last().unwrap().len() == 7);
+    }
+
     #[test]
     fn test_modifiers_add_left_keycodes() {
         let mut keyboard = Keyboard::new(KeyOutCatcher::new());
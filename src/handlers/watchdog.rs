@@ -0,0 +1,191 @@
+use crate::handlers::{HandlerResult, ProcessKeys};
+use crate::key_stream::{Event, EventStatus};
+use crate::{USBKeyOut, MODIFIER_KEYS};
+use no_std_compat::prelude::v1::*;
+
+/// A safety net against 'stuck' modifiers
+///
+/// If a modifier release ever gets lost, the modifier stays set forever.
+/// This clears any modifier that has been set for longer than timeout_ms
+/// without a fresh press of one of it's keys.
+///
+/// Keys that were down when a modifier got set (e.g. the trigger of
+/// premade::hyper, a ModTap or HomeRowMods hold) keep it alive until
+/// they are released - only then does the clock start.
+/// Time is taken from TimeOut events and the ms_since_last of new presses.
+///
+/// Does not handle any events - add it before the other handlers.
+pub struct ModifierWatchdog {
+    timeout_ms: u32,
+    ms_set: [u32; 4],
+    was_set: [bool; 4],
+    holders: [Vec<u32>; 4], // keys that were down when the modifier got set
+    down: Vec<(u32, u8)>,   // original_keycode, running_number of the press
+}
+impl ModifierWatchdog {
+    pub fn new(timeout_ms: u32) -> ModifierWatchdog {
+        ModifierWatchdog {
+            timeout_ms,
+            ms_set: [0; 4],
+            was_set: [false; 4],
+            holders: Default::default(),
+            down: Vec::new(),
+        }
+    }
+}
+
+impl<T: USBKeyOut> ProcessKeys<T> for ModifierWatchdog {
+    fn describe(&self) -> String {
+        format!("ModifierWatchdog(timeout_ms: {})", self.timeout_ms)
+    }
+    fn reset(&mut self, _output: &mut T) {
+        self.down.clear();
+        for holders in self.holders.iter_mut() {
+            holders.clear();
+        }
+    }
+    fn on_disabled(&mut self, _output: &mut T) {
+        //we won't see the releases
+        self.down.clear();
+        for holders in self.holders.iter_mut() {
+            holders.clear();
+        }
+        self.was_set = [false; 4];
+    }
+    fn process_keys(
+        &mut self,
+        events: &mut Vec<(Event, EventStatus)>,
+        output: &mut T,
+    ) -> HandlerResult {
+        //modifiers set during the last pass - by one of the keys down now
+        for (ii, (modifier, _left, _right)) in MODIFIER_KEYS.iter().enumerate() {
+            let set = output.state().modifier(*modifier);
+            if set && !self.was_set[ii] {
+                self.holders[ii] = self.down.iter().map(|(k, _rn)| *k).collect();
+                self.ms_set[ii] = 0;
+            } else if !set {
+                self.holders[ii].clear();
+                self.ms_set[ii] = 0;
+            }
+            self.was_set[ii] = set;
+        }
+        for (event, _status) in events.iter() {
+            let (ms_since_last, keycode) = match event {
+                Event::KeyPress(kc) => {
+                    //presses stay in the stream until released - only count them once
+                    if self.down.iter().any(|(_k, rn)| *rn == kc.running_number) {
+                        continue;
+                    }
+                    self.down.retain(|(k, _rn)| *k != kc.original_keycode);
+                    self.down.push((kc.original_keycode, kc.running_number));
+                    (kc.ms_since_last, Some(kc.keycode))
+                }
+                Event::KeyRelease(kc) => {
+                    self.down.retain(|(k, _rn)| *k != kc.original_keycode);
+                    for holders in self.holders.iter_mut() {
+                        holders.retain(|k| *k != kc.original_keycode);
+                    }
+                    continue;
+                }
+                Event::TimeOut(ms_since_last) => (*ms_since_last, None),
+            };
+            for (ii, (modifier, left, right)) in MODIFIER_KEYS.iter().enumerate() {
+                if !output.state().modifier(*modifier)
+                    || !self.holders[ii].is_empty()
+                    || keycode == Some(left.to_u32())
+                    || keycode == Some(right.to_u32())
+                {
+                    self.ms_set[ii] = 0;
+                } else {
+                    self.ms_set[ii] = self.ms_set[ii].saturating_add(u32::from(ms_since_last));
+                    if self.ms_set[ii] > self.timeout_ms {
+                        output.debug("ModifierWatchdog cleared a stuck modifier");
+                        output.state().set_modifier(*modifier, false);
+                        self.ms_set[ii] = 0;
+                        self.was_set[ii] = false;
+                    }
+                }
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{ModifierWatchdog, USBKeyboard};
+    #[allow(unused_imports)]
+    use crate::key_codes::KeyCode;
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::Modifier::*;
+    use crate::{Keyboard, USBKeyOut};
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_modifier_watchdog() {
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(ModifierWatchdog::new(1000)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().set_modifier(Shift, true);
+        keyboard.tc(500, &[&[KeyCode::LShift]]);
+        assert!(keyboard.output.state().modifier(Shift));
+        keyboard.tc(600, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+        keyboard.pc(KeyCode::A, &[&[KeyCode::A]]);
+    }
+
+    #[test]
+    fn test_modifier_watchdog_fresh_press() {
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(ModifierWatchdog::new(1000)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(KeyCode::LShift, &[&[KeyCode::LShift]]);
+        keyboard.tc(900, &[&[KeyCode::LShift]]);
+        keyboard.rc(KeyCode::LShift, &[&[]]);
+        keyboard.pc(KeyCode::RShift, &[&[KeyCode::RShift]]);
+        keyboard.tc(900, &[&[KeyCode::RShift]]);
+        keyboard.tc(900, &[&[KeyCode::RShift]]);
+        assert!(keyboard.output.state().modifier(Shift));
+    }
+
+    #[test]
+    fn test_modifier_watchdog_hyper() {
+        use crate::premade;
+        use crate::key_codes::UserKey;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(ModifierWatchdog::new(500)));
+        keyboard.add_handler(premade::hyper(UserKey::UK0));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(UserKey::UK0, 0);
+        keyboard.handle_keys().unwrap();
+        for _ in 0..3 {
+            keyboard.add_timeout(300);
+            keyboard.handle_keys().unwrap();
+        }
+        //still held - left alone
+        for modifier in [Ctrl, Shift, Alt, Gui].iter() {
+            assert!(keyboard.output.state().modifier(*modifier));
+        }
+        keyboard.add_keyrelease(UserKey::UK0, 0);
+        keyboard.handle_keys().unwrap();
+        assert!(!keyboard.output.state().modifier(Shift));
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_modifier_watchdog_held_press_counted_once() {
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(ModifierWatchdog::new(500)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().set_modifier(Shift, true);
+        keyboard.pct(KeyCode::A, 200, &[&[KeyCode::LShift, KeyCode::A]]);
+        keyboard.tc(1, &[&[KeyCode::LShift, KeyCode::A]]);
+        keyboard.tc(1, &[&[KeyCode::LShift, KeyCode::A]]);
+        assert!(keyboard.output.state().modifier(Shift));
+        //but the real time still adds up
+        keyboard.tc(300, &[&[KeyCode::A]]);
+        assert!(!keyboard.output.state().modifier(Shift));
+        keyboard.rc(KeyCode::A, &[&[]]);
+    }
+}
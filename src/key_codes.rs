@@ -1,5 +1,6 @@
 use alloc::{format, string::String};
 use core::convert::{TryFrom, TryInto};
+use core::ops::RangeInclusive;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 pub const UNICODE_BELOW_256: u32 = 0x100_000;
 /// usb key codes mapped into the first private region of unicode
@@ -217,7 +218,200 @@ impl KeyCode {
         let u = self as u32;
         return u as u32;
     }
+
+    /// the variant name, e.g. "LShift" - the inverse of from_name
+    pub fn name(self) -> &'static str {
+        NAMES
+            .iter()
+            .find(|(kc, _name)| *kc == self)
+            .map(|(_kc, name)| *name)
+            .unwrap_or("")
+    }
+
+    /// parse a variant name (as returned by name()),
+    /// e.g. for keymaps read from config files
+    pub fn from_name(name: &str) -> Option<KeyCode> {
+        NAMES
+            .iter()
+            .find(|(_kc, n)| *n == name)
+            .map(|(kc, _name)| *kc)
+    }
 }
+/// KeyCode variant names, for KeyCode::name and KeyCode::from_name
+const NAMES: &[(KeyCode, &str)] = &[
+    (KeyCode::No, "No"),
+    (KeyCode::ErrorRollOver, "ErrorRollOver"),
+    (KeyCode::PostFail, "PostFail"),
+    (KeyCode::ErrorUndefined, "ErrorUndefined"),
+    (KeyCode::A, "A"),
+    (KeyCode::B, "B"),
+    (KeyCode::C, "C"),
+    (KeyCode::D, "D"),
+    (KeyCode::E, "E"),
+    (KeyCode::F, "F"),
+    (KeyCode::G, "G"),
+    (KeyCode::H, "H"),
+    (KeyCode::I, "I"),
+    (KeyCode::J, "J"),
+    (KeyCode::K, "K"),
+    (KeyCode::L, "L"),
+    (KeyCode::M, "M"),
+    (KeyCode::N, "N"),
+    (KeyCode::O, "O"),
+    (KeyCode::P, "P"),
+    (KeyCode::Q, "Q"),
+    (KeyCode::R, "R"),
+    (KeyCode::S, "S"),
+    (KeyCode::T, "T"),
+    (KeyCode::U, "U"),
+    (KeyCode::V, "V"),
+    (KeyCode::W, "W"),
+    (KeyCode::X, "X"),
+    (KeyCode::Y, "Y"),
+    (KeyCode::Z, "Z"),
+    (KeyCode::Kb1, "Kb1"),
+    (KeyCode::Kb2, "Kb2"),
+    (KeyCode::Kb3, "Kb3"),
+    (KeyCode::Kb4, "Kb4"),
+    (KeyCode::Kb5, "Kb5"),
+    (KeyCode::Kb6, "Kb6"),
+    (KeyCode::Kb7, "Kb7"),
+    (KeyCode::Kb8, "Kb8"),
+    (KeyCode::Kb9, "Kb9"),
+    (KeyCode::Kb0, "Kb0"),
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Escape, "Escape"),
+    (KeyCode::BSpace, "BSpace"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::Space, "Space"),
+    (KeyCode::Minus, "Minus"),
+    (KeyCode::Equal, "Equal"),
+    (KeyCode::LBracket, "LBracket"),
+    (KeyCode::RBracket, "RBracket"),
+    (KeyCode::BSlash, "BSlash"),
+    (KeyCode::NonUsHash, "NonUsHash"),
+    (KeyCode::SColon, "SColon"),
+    (KeyCode::Quote, "Quote"),
+    (KeyCode::Grave, "Grave"),
+    (KeyCode::Comma, "Comma"),
+    (KeyCode::Dot, "Dot"),
+    (KeyCode::Slash, "Slash"),
+    (KeyCode::CapsLock, "CapsLock"),
+    (KeyCode::F1, "F1"),
+    (KeyCode::F2, "F2"),
+    (KeyCode::F3, "F3"),
+    (KeyCode::F4, "F4"),
+    (KeyCode::F5, "F5"),
+    (KeyCode::F6, "F6"),
+    (KeyCode::F7, "F7"),
+    (KeyCode::F8, "F8"),
+    (KeyCode::F9, "F9"),
+    (KeyCode::F10, "F10"),
+    (KeyCode::F11, "F11"),
+    (KeyCode::F12, "F12"),
+    (KeyCode::PScreen, "PScreen"),
+    (KeyCode::ScrollLock, "ScrollLock"),
+    (KeyCode::Pause, "Pause"),
+    (KeyCode::Insert, "Insert"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::PgUp, "PgUp"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::End, "End"),
+    (KeyCode::PgDown, "PgDown"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::Left, "Left"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Up, "Up"),
+    (KeyCode::NumLock, "NumLock"),
+    (KeyCode::KpSlash, "KpSlash"),
+    (KeyCode::KpAsterisk, "KpAsterisk"),
+    (KeyCode::KpMinus, "KpMinus"),
+    (KeyCode::KpPlus, "KpPlus"),
+    (KeyCode::KpEnter, "KpEnter"),
+    (KeyCode::Kp1, "Kp1"),
+    (KeyCode::Kp2, "Kp2"),
+    (KeyCode::Kp3, "Kp3"),
+    (KeyCode::Kp4, "Kp4"),
+    (KeyCode::Kp5, "Kp5"),
+    (KeyCode::Kp6, "Kp6"),
+    (KeyCode::Kp7, "Kp7"),
+    (KeyCode::Kp8, "Kp8"),
+    (KeyCode::Kp9, "Kp9"),
+    (KeyCode::Kp0, "Kp0"),
+    (KeyCode::KpDot, "KpDot"),
+    (KeyCode::NonUsBslash, "NonUsBslash"),
+    (KeyCode::Application, "Application"),
+    (KeyCode::Power, "Power"),
+    (KeyCode::KpEqual, "KpEqual"),
+    (KeyCode::F13, "F13"),
+    (KeyCode::F14, "F14"),
+    (KeyCode::F15, "F15"),
+    (KeyCode::F16, "F16"),
+    (KeyCode::F17, "F17"),
+    (KeyCode::F18, "F18"),
+    (KeyCode::F19, "F19"),
+    (KeyCode::F20, "F20"),
+    (KeyCode::F21, "F21"),
+    (KeyCode::F22, "F22"),
+    (KeyCode::F23, "F23"),
+    (KeyCode::F24, "F24"),
+    (KeyCode::Open, "Open"),
+    (KeyCode::Help, "Help"),
+    (KeyCode::Props, "Props"),
+    (KeyCode::Front, "Front"),
+    (KeyCode::Stop, "Stop"),
+    (KeyCode::Again, "Again"),
+    (KeyCode::Undo, "Undo"),
+    (KeyCode::Cut, "Cut"),
+    (KeyCode::Copy, "Copy"),
+    (KeyCode::Paste, "Paste"),
+    (KeyCode::Find, "Find"),
+    (KeyCode::Mute, "Mute"),
+    (KeyCode::VolumeUp, "VolumeUp"),
+    (KeyCode::VolumeDown, "VolumeDown"),
+    (KeyCode::Kpcomma, "Kpcomma"),
+    (KeyCode::Ro, "Ro"),
+    (KeyCode::Katakanahiragana, "Katakanahiragana"),
+    (KeyCode::Yen, "Yen"),
+    (KeyCode::Henkan, "Henkan"),
+    (KeyCode::Muhenkan, "Muhenkan"),
+    (KeyCode::KpJpComma, "KpJpComma"),
+    (KeyCode::Hangeul, "Hangeul"),
+    (KeyCode::Hanja, "Hanja"),
+    (KeyCode::Katakana, "Katakana"),
+    (KeyCode::Hiragana, "Hiragana"),
+    (KeyCode::Zenkakuhankaku, "Zenkakuhankaku"),
+    (KeyCode::KpLeftParen, "KpLeftParen"),
+    (KeyCode::KpRightParen, "KpRightParen"),
+    (KeyCode::LCtrl, "LCtrl"),
+    (KeyCode::LShift, "LShift"),
+    (KeyCode::LAlt, "LAlt"),
+    (KeyCode::LGui, "LGui"),
+    (KeyCode::RCtrl, "RCtrl"),
+    (KeyCode::RShift, "RShift"),
+    (KeyCode::RAlt, "RAlt"),
+    (KeyCode::RGui, "RGui"),
+    (KeyCode::MediaPlayPause, "MediaPlayPause"),
+    (KeyCode::MediaStopCd, "MediaStopCd"),
+    (KeyCode::MediaPrevioussong, "MediaPrevioussong"),
+    (KeyCode::MediaNextsong, "MediaNextsong"),
+    (KeyCode::MediaEjectCd, "MediaEjectCd"),
+    (KeyCode::MediaVolumeUp, "MediaVolumeUp"),
+    (KeyCode::MediaVolumeDown, "MediaVolumeDown"),
+    (KeyCode::MediaMUte, "MediaMUte"),
+    (KeyCode::MediaWww, "MediaWww"),
+    (KeyCode::MediaBack, "MediaBack"),
+    (KeyCode::MediaForward, "MediaForward"),
+    (KeyCode::MediaStop, "MediaStop"),
+    (KeyCode::MediaFind, "MediaFind"),
+    (KeyCode::MediaScrollUp, "MediaScrollUp"),
+    (KeyCode::MediaScrollDown, "MediaScrollDown"),
+    (KeyCode::MediaEdit, "MediaEdit"),
+    (KeyCode::MediaSleep, "MediaSleep"),
+    (KeyCode::MediaCoffee, "MediaCoffee"),
+    (KeyCode::MediaRefresh, "MediaRefresh"),
+    (KeyCode::MediaCalc, "MediaCalc"),
+];
 impl TryFrom<u8> for KeyCode {
     type Error = String;
     fn try_from(ii: u8) -> Result<KeyCode, Self::Error> {
@@ -379,17 +573,53 @@ impl AcceptsKeycode for &UserKey {
     }
 }
 
+/// unicode private use area A - UserKeys live here
+pub const PRIVATE_USE_AREA_A: RangeInclusive<u32> = 0xF0000..=0xFFFFD;
+/// unicode private use area B - it's start is occupied by the usb key codes
+pub const PRIVATE_USE_AREA_B: RangeInclusive<u32> = UNICODE_BELOW_256..=0x10FFFD;
+/// the block reserved for usb key codes (UNICODE_BELOW_256 + 0..=0xFF)
+#[cfg(test)]
+const USB_KEYCODE_BLOCK: RangeInclusive<u32> = UNICODE_BELOW_256..=UNICODE_BELOW_256 + 0xFF;
+/// the usb key codes the USBKeyboard actually sends (up to RGui)
+pub const USB_KEYCODES: RangeInclusive<u32> = UNICODE_BELOW_256..=KeyCode::RGui.to_u32();
+/// the UserKeys
+pub const PRIVATE_KEYCODES: RangeInclusive<u32> = UserKey::UK0.to_u32()..=UserKey::UK99.to_u32();
+
 pub trait KeyCodeInfo {
     fn is_usb_keycode(self) -> bool;
     fn is_private_keycode(self) -> bool;
+    /// whether this is a 'real' unicode codepoint,
+    /// ie. neither a usb key code nor anything else from the private use areas
+    fn is_unicode_keycode(self) -> bool;
 }
 
 impl KeyCodeInfo for u32 {
     fn is_usb_keycode(self) -> bool {
-        return UNICODE_BELOW_256 <= self && self <= UNICODE_BELOW_256 + 0xE7; //RGui
+        USB_KEYCODES.contains(&self)
     }
     fn is_private_keycode(self) -> bool {
-        return UserKey::UK0.to_u32() <= self && self <= UserKey::UK99.to_u32(); //RGui
+        PRIVATE_KEYCODES.contains(&self)
+    }
+    fn is_unicode_keycode(self) -> bool {
+        !(PRIVATE_USE_AREA_A.contains(&self) || PRIVATE_USE_AREA_B.contains(&self))
+    }
+}
+
+/// a human readable name for a keycode,
+/// e.g. for ProcessKeys::describe
+pub fn describe_keycode(keycode: u32) -> String {
+    if keycode.is_usb_keycode() {
+        let kc: Result<KeyCode, _> = keycode.try_into();
+        if let Ok(kc) = kc {
+            return format!("{:?}", kc);
+        }
+    }
+    if keycode.is_private_keycode() {
+        return format!("UK{}", keycode - UserKey::UK0.to_u32());
+    }
+    match core::char::from_u32(keycode) {
+        Some(c) => format!("{:?}", c),
+        None => format!("{:#x}", keycode),
     }
 }
 
@@ -403,4 +633,56 @@ mod test {
         assert!(!KeyCode::Power.to_u32().is_private_keycode());
 
     }
+    #[test]
+    fn test_keycode_names() {
+        use super::KeyCode;
+        use core::convert::TryInto;
+        for kc in &[KeyCode::A, KeyCode::Kb0, KeyCode::LGui, KeyCode::MediaPlayPause] {
+            assert!(KeyCode::from_name(kc.name()) == Some(*kc));
+        }
+        assert!(KeyCode::LShift.name() == "LShift");
+        assert!(KeyCode::from_name("LShift") == Some(KeyCode::LShift));
+        assert!(KeyCode::from_name("NoSuchKey").is_none());
+        assert!(KeyCode::from_name("lshift").is_none());
+        //every variant
+        for ii in KeyCode::No.to_u32()..=KeyCode::MediaCalc.to_u32() {
+            let kc: Result<KeyCode, _> = ii.try_into();
+            if let Ok(kc) = kc {
+                assert!(KeyCode::from_name(kc.name()) == Some(kc));
+            }
+        }
+    }
+    #[test]
+    fn test_keycode_regions() {
+        use super::{KeyCode, KeyCodeInfo, UserKey, USB_KEYCODE_BLOCK};
+        //plain unicode
+        for c in &[0x41, 0xDF, 0xFF, 0x1F600, 0xEFFFF, 0xFFFFE, 0x10FFFE] {
+            assert!(c.is_unicode_keycode());
+            assert!(!c.is_usb_keycode());
+            assert!(!c.is_private_keycode());
+        }
+        //usb key codes
+        for c in &[KeyCode::No.to_u32(), KeyCode::A.to_u32(), KeyCode::RGui.to_u32()] {
+            assert!(c.is_usb_keycode());
+            assert!(!c.is_unicode_keycode());
+            assert!(!c.is_private_keycode());
+        }
+        //the rest of the usb block is reserved, but not send
+        for c in &[KeyCode::MediaPlayPause.to_u32(), *USB_KEYCODE_BLOCK.end()] {
+            assert!(!c.is_usb_keycode());
+            assert!(!c.is_unicode_keycode());
+        }
+        //user keys
+        for c in &[UserKey::UK0.to_u32(), UserKey::UK99.to_u32()] {
+            assert!(c.is_private_keycode());
+            assert!(!c.is_usb_keycode());
+            assert!(!c.is_unicode_keycode());
+        }
+        //the remaining private use areas
+        for c in &[0xF0000, 0xF00FF, 0xF0164, 0xFFFFD, 0x100100, 0x10FFFD] {
+            assert!(!c.is_unicode_keycode());
+            assert!(!c.is_usb_keycode());
+            assert!(!c.is_private_keycode());
+        }
+    }
 }
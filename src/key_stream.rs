@@ -3,13 +3,15 @@ use no_std_compat::prelude::v1::*;
 pub struct Key {
     pub keycode: u32,
     pub original_keycode: u32, //used to match key press/release pairs - can we save on this anyhow?
-    pub ms_since_last: u16,
+    pub ms_since_last: u16, // at most u16::MAX - about 65 seconds
     pub running_number: u8,
     pub flag: u8, //Todo: express this better
                   //bit 0 is used by Usbkeyboard to decide whether a KeyPress has ever been sent
                   //(or kept back by a different handler so far)
                   //bit1 is used to protect against double rewrites in Layers
                   //bit2 is used by SpaceCadet.
+                  //bit3 asks the USBKeyboard to send the key without Shift
+                  //(LayerAction::RewriteUnshifted)
 }
 impl Key {
     pub fn new(keycode: u32) -> Key {
@@ -33,14 +35,29 @@ pub enum EventStatus {
     Unhandled,
     Handled,
     Ignored,
+    /// Seen (e.g. by a logger or LED effect), but not claimed:
+    /// later handlers still get it as if it was Unhandled.
+    /// If none of them claims it, it stays in the stream like an
+    /// Unhandled event, but handle_keys does not return an Err for it.
+    Observed,
+}
+impl EventStatus {
+    /// Unhandled or Observed - still up for grabs
+    pub fn is_unhandled(self) -> bool {
+        match self {
+            EventStatus::Unhandled | EventStatus::Observed => true,
+            EventStatus::Handled | EventStatus::Ignored => false,
+        }
+    }
 }
 
+/// iterate over the events still up for grabs (Unhandled or Observed)
 pub fn iter_unhandled_mut(
     events: &mut Vec<(Event, EventStatus)>,
 ) -> impl DoubleEndedIterator<Item = &mut (Event, EventStatus)> {
     events
         .iter_mut()
-        .filter(|(_e, status)| EventStatus::Unhandled == *status)
+        .filter(|(_e, status)| status.is_unhandled())
 }
 /*
 pub fn iter_unhandled_mut_matching(
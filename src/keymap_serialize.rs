@@ -0,0 +1,101 @@
+//! A compact binary format for (from, to) rewrite tables,
+//! e.g. to keep keymaps in flash.
+//!
+//! Layout (all little endian):
+//! u32 number of pairs, followed by that many (u32 from, u32 to) pairs.
+//!
+//! Decoded tables are owned - use them with a Layer (LayerAction::RewriteTo),
+//! or leak them to get the &'static slice a RewriteLayer needs.
+use no_std_compat::prelude::v1::*;
+
+#[derive(Debug, PartialEq)]
+pub enum SerializeError {
+    /// the buffer can't hold (encode) or doesn't contain (decode) the whole table
+    BufferTooShort,
+}
+
+const COUNT_SIZE: usize = 4;
+const PAIR_SIZE: usize = 8;
+
+/// bytes needed to encode rewrites
+pub fn encoded_len(rewrites: &[(u32, u32)]) -> usize {
+    COUNT_SIZE + rewrites.len() * PAIR_SIZE
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// encode rewrites into buf, returning the number of bytes written
+///
+/// Returns Err(BufferTooShort) if buf is too short (see encoded_len) - buf is left
+/// untouched in that case.
+pub fn encode(rewrites: &[(u32, u32)], buf: &mut [u8]) -> Result<usize, SerializeError> {
+    let len = encoded_len(rewrites);
+    if buf.len() < len {
+        return Err(SerializeError::BufferTooShort);
+    }
+    buf[..COUNT_SIZE].copy_from_slice(&(rewrites.len() as u32).to_le_bytes());
+    for (ii, (from, to)) in rewrites.iter().enumerate() {
+        let offset = COUNT_SIZE + ii * PAIR_SIZE;
+        buf[offset..offset + 4].copy_from_slice(&from.to_le_bytes());
+        buf[offset + 4..offset + 8].copy_from_slice(&to.to_le_bytes());
+    }
+    Ok(len)
+}
+
+/// decode a table written by encode
+///
+/// Returns Err(BufferTooShort) if buf is shorter than the table it announces.
+/// Trailing bytes are ignored.
+pub fn decode(buf: &[u8]) -> Result<Vec<(u32, u32)>, SerializeError> {
+    if buf.len() < COUNT_SIZE {
+        return Err(SerializeError::BufferTooShort);
+    }
+    let count = read_u32(buf, 0) as usize;
+    let available = (buf.len() - COUNT_SIZE) / PAIR_SIZE;
+    if count > available {
+        return Err(SerializeError::BufferTooShort);
+    }
+    let mut result = Vec::with_capacity(count);
+    for ii in 0..count {
+        let offset = COUNT_SIZE + ii * PAIR_SIZE;
+        result.push((read_u32(buf, offset), read_u32(buf, offset + 4)));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keymap_serialize::{decode, encode, encoded_len, SerializeError};
+    use crate::premade;
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_roundtrip_dvorak() {
+        let map = premade::dvorak().rewrites();
+        let mut buf = vec![0u8; encoded_len(map)];
+        assert!(encode(map, &mut buf) == Ok(4 + map.len() * 8));
+        assert!(decode(&buf).unwrap() == map.to_vec());
+        //fixed endianess
+        assert!(buf[..4] == (map.len() as u32).to_le_bytes());
+        assert!(buf[4..8] == map[0].0.to_le_bytes());
+
+        //short buffers
+        assert!(encode(map, &mut buf[..10]) == Err(SerializeError::BufferTooShort));
+        assert!(decode(&buf[..buf.len() - 1]) == Err(SerializeError::BufferTooShort));
+        assert!(decode(&buf[..3]) == Err(SerializeError::BufferTooShort));
+    }
+
+    #[test]
+    fn test_roundtrip_empty() {
+        let mut buf = [0xFFu8; 6];
+        assert!(encode(&[], &mut buf) == Ok(4));
+        assert!(buf == [0, 0, 0, 0, 0xFF, 0xFF]);
+        assert!(decode(&buf).unwrap().is_empty());
+        assert!(encode(&[], &mut buf[..3]).is_err());
+    }
+}
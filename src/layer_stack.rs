@@ -0,0 +1,118 @@
+//! An ordered stack of active layers (HandlerIDs),
+//! see KeyboardState::push_layer / pop_layer.
+//!
+//! Every layer on the stack is enabled - which layer takes precedence
+//! for a key is still decided by the handler order,
+//! so add the layers meant to go on top first.
+use crate::HandlerID;
+use no_std_compat::prelude::v1::*;
+
+#[derive(Debug, Default)]
+pub struct LayerStack {
+    stack: Vec<HandlerID>,
+}
+impl LayerStack {
+    pub fn new() -> LayerStack {
+        LayerStack { stack: Vec::new() }
+    }
+    /// put id on top - moving it there if it's already on the stack
+    pub fn push(&mut self, id: HandlerID) {
+        self.remove(id);
+        self.stack.push(id);
+    }
+    pub fn pop(&mut self) -> Option<HandlerID> {
+        self.stack.pop()
+    }
+    pub fn top(&self) -> Option<HandlerID> {
+        self.stack.last().copied()
+    }
+    /// take id off the stack, wherever it is. Returns whether it was on it
+    pub fn remove(&mut self, id: HandlerID) -> bool {
+        match self.stack.iter().position(|x| *x == id) {
+            Some(pos) => {
+                self.stack.remove(pos);
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn contains(&self, id: HandlerID) -> bool {
+        self.stack.contains(&id)
+    }
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::{PressReleaseMacro, RewriteLayer, USBKeyboard};
+    use crate::key_codes::{KeyCode, UserKey};
+    use crate::layer_stack::LayerStack;
+    use crate::handlers::Action;
+    use crate::premade::{layer_pop, layer_push};
+    use crate::test_helpers::{Checks, KeyOutCatcher};
+    use crate::{Keyboard, USBKeyOut};
+    #[allow(unused_imports)]
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_layer_stack() {
+        let mut stack = LayerStack::new();
+        assert!(stack.top().is_none());
+        stack.push(5);
+        stack.push(6);
+        stack.push(5);
+        assert!(stack.len() == 2);
+        assert!(stack.top() == Some(5));
+        assert!(stack.pop() == Some(5));
+        assert!(stack.pop() == Some(6));
+        assert!(stack.pop().is_none());
+    }
+
+    #[test]
+    fn test_push_pop_layers() {
+        use crate::key_codes::KeyCode::*;
+        const UPPER: &[(u32, u32)] = &[(A.to_u32(), Y.to_u32())];
+        const LOWER: &[(u32, u32)] = &[(A.to_u32(), X.to_u32()), (B.to_u32(), Z.to_u32())];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let upper = keyboard.future_handler_id(2);
+        let lower = keyboard.future_handler_id(3);
+        keyboard.add_handler(Box::new(PressReleaseMacro::new(UserKey::UK0, layer_push(upper))));
+        assert!(keyboard.add_handler(Box::new(RewriteLayer::new(UPPER))) == upper);
+        assert!(keyboard.add_handler(Box::new(RewriteLayer::new(LOWER))) == lower);
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+
+        keyboard.output.state().push_layer(lower);
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        assert!(keyboard.output.state().top_layer() == Some(upper));
+        assert!(keyboard.output.state().is_handler_enabled(upper));
+        assert!(keyboard.output.state().is_handler_enabled(lower));
+        keyboard.pc(A, &[&[Y]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.pc(B, &[&[Z]]);
+        keyboard.rc(B, &[&[]]);
+
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(keyboard.output.state().top_layer() == Some(lower));
+        assert!(!keyboard.output.state().is_handler_enabled(upper));
+        keyboard.pc(A, &[&[X]]);
+        keyboard.rc(A, &[&[]]);
+
+        assert!(keyboard.output.state().pop_layer() == Some(lower));
+        assert!(!keyboard.output.state().is_handler_enabled(lower));
+        assert!(keyboard.output.state().pop_layer().is_none());
+        keyboard.pc(KeyCode::A, &[&[A]]);
+        keyboard.rc(KeyCode::A, &[&[]]);
+        assert!(keyboard.events.is_empty());
+
+        //as Actions
+        layer_push(lower).on_trigger(&mut keyboard.output);
+        assert!(keyboard.output.state().is_handler_enabled(lower));
+        layer_pop().on_trigger(&mut keyboard.output);
+        assert!(!keyboard.output.state().is_handler_enabled(lower));
+    }
+}
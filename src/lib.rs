@@ -6,6 +6,8 @@ pub mod debug_handlers;
 pub mod handlers;
 mod key_codes;
 mod key_stream;
+pub mod keymap_serialize;
+pub mod layer_stack;
 pub mod premade;
 pub mod test_helpers;
 extern crate alloc;
@@ -14,11 +16,11 @@ extern crate spin;
 pub use crate::handlers::{HandlerResult, ProcessKeys};
 
 pub use crate::key_codes::{AcceptsKeycode, KeyCode, UserKey};
-use crate::key_stream::Key;
-pub use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
+pub use crate::key_stream::{iter_unhandled_mut, Event, EventStatus, Key};
 use core::convert::TryInto;
 use no_std_compat::prelude::v1::*;
 use smallbitvec::{sbvec, SmallBitVec};
+use crate::layer_stack::LayerStack;
 
 /// current keyboard state.
 ///
@@ -31,20 +33,50 @@ pub enum Modifier {
     Alt = 2,
     Gui = 3,
 }
+/// each Modifier with it's left and right key
+pub(crate) const MODIFIER_KEYS: [(Modifier, KeyCode, KeyCode); 4] = [
+    (Modifier::Shift, KeyCode::LShift, KeyCode::RShift),
+    (Modifier::Ctrl, KeyCode::LCtrl, KeyCode::RCtrl),
+    (Modifier::Alt, KeyCode::LAlt, KeyCode::RAlt),
+    (Modifier::Gui, KeyCode::LGui, KeyCode::RGui),
+];
 
 const KEYBOARD_STATE_RESERVED_BITS: usize = 5;
 const ABORT_BIT: usize = 4;
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct KeyboardState {
     pub unicode_mode: UnicodeSendMode,
+    /// the key WinCompose has been configured to use
+    /// (RAlt by default, Menu/Application or RWin are popular alternatives)
+    pub compose_key: KeyCode,
+    /// keyboard wide tapping term.
+    /// Used by handlers constructed without an explicit timeout
+    /// (e.g. TapDance::new_global_timeout), which fall back
+    /// to their own default if this is None
+    pub tapping_term_ms: Option<u16>,
     modifiers_and_enabled_handlers: SmallBitVec,
+    modifiers_prefer_right: [bool; 4],
+    passthrough: bool,
+    oneshot_triggers: Vec<u32>,
+    layer_stack: LayerStack,
+}
+impl Default for KeyboardState {
+    fn default() -> KeyboardState {
+        KeyboardState::new()
+    }
 }
 impl KeyboardState {
     pub fn new() -> KeyboardState {
         KeyboardState {
             unicode_mode: UnicodeSendMode::Linux,
+            compose_key: KeyCode::RAlt,
+            tapping_term_ms: None,
             modifiers_and_enabled_handlers: sbvec![false; KEYBOARD_STATE_RESERVED_BITS],
+            modifiers_prefer_right: [false; 4],
+            passthrough: false,
+            oneshot_triggers: Vec::new(),
+            layer_stack: LayerStack::new(),
         }
     }
 
@@ -57,6 +89,16 @@ impl KeyboardState {
             .set(modifier as usize, value);
     }
 
+    /// whether the USBKeyboard sends the right variant (e.g. RAlt)
+    /// of a modifier that is set without it's key being held
+    pub fn modifier_prefers_right(&self, modifier: Modifier) -> bool {
+        self.modifiers_prefer_right[modifier as usize]
+    }
+
+    pub fn set_modifier_prefers_right(&mut self, modifier: Modifier, value: bool) {
+        self.modifiers_prefer_right[modifier as usize] = value;
+    }
+
     pub fn enable_handler(&mut self, no: HandlerID) {
         self.modifiers_and_enabled_handlers.set(no, true);
     }
@@ -69,6 +111,15 @@ impl KeyboardState {
         self.modifiers_and_enabled_handlers.set(no, enabled);
     }
 
+    /// enable/disable multiple handlers at once,
+    /// e.g. to switch between 'modes'.
+    /// No handler runs before all changes are applied.
+    pub fn set_handlers(&mut self, changes: &[(HandlerID, bool)]) {
+        for (no, enabled) in changes {
+            self.modifiers_and_enabled_handlers.set(*no, *enabled);
+        }
+    }
+
     pub fn toggle_handler(&mut self, no: HandlerID) {
         self.modifiers_and_enabled_handlers
             .set(no, !self.modifiers_and_enabled_handlers[no]);
@@ -79,13 +130,63 @@ impl KeyboardState {
     }
 
     ///tell the Keyboard to
-    /// * reset handlers to their default state, clear
+    /// * reset handlers to their default state (ProcessKeys::reset)
     /// * clear all remaining events - unhandled or not
     /// * reset all modifiers to default
     pub fn abort_and_clear_events(&mut self) {
         self.modifiers_and_enabled_handlers.set(ABORT_BIT, true); // signal the handle_events loop to abort
     }
 
+    /// whether handle_keys skips all handlers but those
+    /// that run in passthrough mode (the USBKeyboard)
+    pub fn passthrough(&self) -> bool {
+        self.passthrough
+    }
+
+    /// 'raw' mode, e.g. for gaming - no layers, tap dances etc.
+    /// See premade::passthrough_toggle to switch it with a key.
+    pub fn set_passthrough(&mut self, value: bool) {
+        self.passthrough = value;
+    }
+
+    /// enable a layer and put it on top of the layer stack
+    /// (see layer_stack::LayerStack)
+    pub fn push_layer(&mut self, id: HandlerID) {
+        self.layer_stack.push(id);
+        self.enable_handler(id);
+    }
+
+    /// take the top layer off the stack and disable it
+    pub fn pop_layer(&mut self) -> Option<HandlerID> {
+        let id = self.layer_stack.pop()?;
+        self.disable_handler(id);
+        Some(id)
+    }
+
+    /// take a layer off the stack wherever it is, and disable it
+    /// (e.g. when momentary layers are released out of order)
+    pub fn remove_layer(&mut self, id: HandlerID) {
+        if self.layer_stack.remove(id) {
+            self.disable_handler(id);
+        }
+    }
+
+    pub fn top_layer(&self) -> Option<HandlerID> {
+        self.layer_stack.top()
+    }
+
+    /// oneshots don't deactivate on other oneshots -
+    /// OneShot registers it's triggers here when it's added
+    pub fn add_oneshot_trigger(&mut self, keycode: u32) {
+        if !self.oneshot_triggers.contains(&keycode) {
+            self.oneshot_triggers.push(keycode);
+        }
+    }
+
+    pub fn is_oneshot_trigger(&self, keycode: u32) -> bool {
+        self.oneshot_triggers.contains(&keycode)
+    }
+
     fn _clear_abort(&mut self) {
         self.modifiers_and_enabled_handlers.set(ABORT_BIT, false);
     }
@@ -106,6 +207,10 @@ pub struct Keyboard<'a, T: USBKeyOut> {
     events: Vec<(Event, EventStatus)>,
     running_number: u8,
     handlers: Vec<Box<dyn ProcessKeys<T> + Send + 'a>>,
+    handler_names: Vec<Option<&'a str>>, // set by add_named_handler
+    handlers_enabled: SmallBitVec, // as seen by the last handle_keys
+    ignored_event_ttl: Option<u16>,
+    ignored_event_ages: Vec<(u8, u16)>, // running_number, passes
     pub output: T,
 }
 #[allow(clippy::new_without_default)]
@@ -115,6 +220,10 @@ impl<'a, T: USBKeyOut> Keyboard<'a, T> {
             events: Vec::new(),
             running_number: 0,
             handlers: Vec::new(),
+            handler_names: Vec::new(),
+            handlers_enabled: SmallBitVec::new(),
+            ignored_event_ttl: None,
+            ignored_event_ages: Vec::new(),
             output,
         }
     }
@@ -122,34 +231,161 @@ impl<'a, T: USBKeyOut> Keyboard<'a, T> {
     /// which you may use with keyboard.output.state().enable_handler / disable_handler / toggle_handler / is_handler_enabled
     ///
     /// by default, most handlers start in the enabled state (with the notable exception of Layers).
-    pub fn add_handler(&mut self, handler: Box<dyn ProcessKeys<T> + Send + 'a>) -> HandlerID {
+    pub fn add_handler(&mut self, mut handler: Box<dyn ProcessKeys<T> + Send + 'a>) -> HandlerID {
         self.output
             .state()
             .modifiers_and_enabled_handlers
             .push(handler.default_enabled());
+        self.handlers_enabled.push(handler.default_enabled());
+        handler.on_added(&mut self.output);
         self.handlers.push(handler);
+        self.handler_names.push(None);
         return self.output.state().modifiers_and_enabled_handlers.len() - 1;
     }
 
+    /// add_handler, with a name for debug output (see handler_name)
+    /// that overrides ProcessKeys::name
+    pub fn add_named_handler(
+        &mut self,
+        name: &'a str,
+        handler: Box<dyn ProcessKeys<T> + Send + 'a>,
+    ) -> HandlerID {
+        let id = self.add_handler(handler);
+        if let Some(last) = self.handler_names.last_mut() {
+            *last = Some(name);
+        }
+        id
+    }
+
+    /// the name of the handler with this id - as given to add_named_handler,
+    /// or ProcessKeys::name. None if there is no such handler
+    pub fn handler_name(&self, id: HandlerID) -> Option<&str> {
+        let index = id.checked_sub(KEYBOARD_STATE_RESERVED_BITS)?;
+        let handler = self.handlers.get(index)?;
+        match self.handler_names[index] {
+            Some(name) => Some(name),
+            None => Some(handler.name()),
+        }
+    }
+
+    /// reset the internal state of one handler (see ProcessKeys::reset),
+    /// e.g. to cancel a running TapDance, without clearing all events
+    /// like KeyboardState::abort_and_clear_events
+    pub fn reset_handler(&mut self, id: HandlerID) {
+        if let Some(index) = id.checked_sub(KEYBOARD_STATE_RESERVED_BITS) {
+            if let Some(handler) = self.handlers.get_mut(index) {
+                handler.reset(&mut self.output);
+            }
+        }
+    }
+
     /// predict the next or further out hander_ids returned by add_handler
     /// Needed to add space cadets before the layers they toggle.
+    ///
+    /// offset 1 is the id of the next add_handler, 2 the one after that...
+    /// Panics on offset 0 - that's the most recently added handler
+    /// (see handler_index_of_last).
     pub fn future_handler_id(&self, offset: usize) -> HandlerID {
+        if offset == 0 {
+            panic!("future_handler_id(0) is the last added handler, the next one is offset 1");
+        }
         let current = self.output.ro_state().modifiers_and_enabled_handlers.len() - 1;
         current + offset
     }
 
+    /// number of handlers added so far
+    pub fn handler_count(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// the HandlerID of the most recently added handler, if any
+    ///
+    /// HandlerIDs are not indices into the handler list - the first
+    /// KEYBOARD_STATE_RESERVED_BITS ids are the modifiers, so the first handler
+    /// is id KEYBOARD_STATE_RESERVED_BITS, the n-th one
+    /// KEYBOARD_STATE_RESERVED_BITS + n - 1.
+    pub fn handler_index_of_last(&self) -> Option<HandlerID> {
+        if self.handlers.is_empty() {
+            None
+        } else {
+            Some(self.handlers.len() - 1 + KEYBOARD_STATE_RESERVED_BITS)
+        }
+    }
+
+    /// Drop Ignored events after they have been Ignored
+    /// for more than ttl handle_keys passes in a row.
+    ///
+    /// By default (None) Ignored events are kept until a handler
+    /// handles them. Note that held keys are usually Ignored by the
+    /// USBKeyboard until they're released, so choose a generous ttl.
+    pub fn set_ignored_event_ttl(&mut self, ttl: Option<u16>) {
+        self.ignored_event_ttl = ttl;
+        self.ignored_event_ages.clear();
+    }
+
+    /// the events still in the buffer (after the last handle_keys)
+    /// for inspection, e.g. by debugging UIs
+    pub fn pending_events(&self) -> &[(Event, EventStatus)] {
+        &self.events
+    }
+
+    /// number of events left Unhandled by the last handle_keys
+    pub fn unhandled_count(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|(_e, status)| EventStatus::Unhandled == *status)
+            .count()
+    }
+
+    /// describe all handlers (in order) for troubleshooting
+    pub fn describe_handlers(&self) -> Vec<String> {
+        self.handlers.iter().map(|h| h.describe()).collect()
+    }
+
     /// handle an update to the event stream
     ///
     /// This returns OK(()) if all keys are handled by the handlers
-    /// and an Err(()) otherwise.
+    /// (or at least Observed) and an Err(()) otherwise.
     /// that way the down stream can decide what to do
     /// (tests: panic. Firmare/MatrixToStream -> drop unhandled events)
+    ///
+    /// See handle_keys_collect if you want to know which events were unhandled.
     pub fn handle_keys(&mut self) -> Result<(), ()> {
+        if self.handle_keys_unchecked() {
+            return Err(());
+        }
+        Ok(())
+    }
+
+    /// handle an update to the event stream, like handle_keys
+    ///
+    /// But returns the unhandled events (in order) as Err(...),
+    /// so the firmware can log which keycode had no handler.
+    /// These (and unclaimed Observed events) are removed from
+    /// the event stream (see clear_unhandled).
+    pub fn handle_keys_collect(&mut self) -> Result<(), Vec<Event>> {
+        if self.handle_keys_unchecked() {
+            let (unhandled, rest): (Vec<_>, Vec<_>) = self
+                .events
+                .drain(..)
+                .partition(|(_event, status)| status.is_unhandled());
+            self.events = rest;
+            return Err(unhandled.into_iter().map(|(event, _status)| event).collect());
+        }
+        Ok(())
+    }
+
+    /// run the handlers, returns whether unhandled events remain
+    fn handle_keys_unchecked(&mut self) -> bool {
         for (_e, status) in self.events.iter_mut() {
             *status = EventStatus::Unhandled;
         }
+        self.notify_toggled_handlers();
         //skip the modifiers
         for (ii, h) in self.handlers.iter_mut().enumerate() {
+            if self.output.ro_state().passthrough() && !h.runs_in_passthrough() {
+                continue;
+            }
             if self.output.state().modifiers_and_enabled_handlers[ii + KEYBOARD_STATE_RESERVED_BITS]
             {
                 match h.process_keys(&mut self.events, &mut self.output) {
@@ -161,13 +397,21 @@ impl<'a, T: USBKeyOut> Keyboard<'a, T> {
                     }
                 }
                 if self.output.state()._aborted() {
-                    self.output.state()._clear_abort();
-                    self.events.clear();
                     break; // no more handlers being done
                 }
+            } else {
+                h.process_keys_while_disabled(&mut self.events, &mut self.output);
+            }
+        }
+        if self.output.state()._aborted() {
+            self.output.state()._clear_abort();
+            self.events.clear();
+            for h in self.handlers.iter_mut() {
+                h.reset(&mut self.output);
             }
         }
         // remove handled & timeout events.
+        // unclaimed Observed events stay, like Unhandled ones
         self.events.drain_filter(|(event, status)| {
             (EventStatus::Handled == *status)
                 || (match event {
@@ -175,19 +419,58 @@ impl<'a, T: USBKeyOut> Keyboard<'a, T> {
                     _ => false,
                 })
         });
-        if self
-            .events
+        if let Some(ttl) = self.ignored_event_ttl {
+            self.purge_stale_ignored_events(ttl);
+        }
+        self.notify_toggled_handlers();
+        self.events
             .iter()
             .any(|(_e, status)| EventStatus::Unhandled == *status)
-        {
-            return Err(());
+    }
+    /// age the Ignored events and drop those older than ttl
+    fn purge_stale_ignored_events(&mut self, ttl: u16) {
+        let previous_ages = &self.ignored_event_ages;
+        let mut ages = Vec::new();
+        self.events.retain(|(event, status)| {
+            let kc = match event {
+                Event::KeyPress(kc) | Event::KeyRelease(kc) => kc,
+                Event::TimeOut(_) => return true,
+            };
+            if EventStatus::Ignored != *status {
+                return true;
+            }
+            let age = previous_ages
+                .iter()
+                .find(|(running_number, _age)| *running_number == kc.running_number)
+                .map_or(0, |(_running_number, age)| *age)
+                .saturating_add(1);
+            if age > ttl {
+                false
+            } else {
+                ages.push((kc.running_number, age));
+                true
+            }
+        });
+        self.ignored_event_ages = ages;
+    }
+    /// call on_enabled/on_disabled on all handlers that have been
+    /// enabled/disabled since we last checked
+    fn notify_toggled_handlers(&mut self) {
+        for (ii, h) in self.handlers.iter_mut().enumerate() {
+            let enabled = self.output.ro_state().modifiers_and_enabled_handlers
+                [ii + KEYBOARD_STATE_RESERVED_BITS];
+            if self.handlers_enabled[ii] && !enabled {
+                h.on_disabled(&mut self.output);
+            } else if !self.handlers_enabled[ii] && enabled {
+                h.on_enabled(&mut self.output);
+            }
+            self.handlers_enabled.set(ii, enabled);
         }
-        Ok(())
     }
-    //throw away unhandled key events
+    //throw away unhandled (and unclaimed Observed) key events
     pub fn clear_unhandled(&mut self) {
         self.events
-            .drain_filter(|(_event, status)| (EventStatus::Unhandled == *status));
+            .drain_filter(|(_event, status)| status.is_unhandled());
     }
     /// add a KeyPress event
     pub fn add_keypress<X: AcceptsKeycode>(&mut self, keycode: X, ms_since_last: u16) {
@@ -198,7 +481,7 @@ impl<'a, T: USBKeyOut> Keyboard<'a, T> {
             running_number: self.running_number,
             flag: 0,
         };
-        self.running_number += 1;
+        self.running_number = self.running_number.wrapping_add(1);
         self.events
             .push((Event::KeyPress(e), EventStatus::Unhandled));
     }
@@ -211,19 +494,42 @@ impl<'a, T: USBKeyOut> Keyboard<'a, T> {
             running_number: self.running_number,
             flag: 0,
         };
-        self.running_number += 1;
+        self.running_number = self.running_number.wrapping_add(1);
         self.events
             .push((Event::KeyRelease(e), EventStatus::Unhandled));
     }
     pub fn add_timeout(&mut self, ms_since_last: u16) {
-        if let Some((event, _status)) = self.events.iter().last() {
-            if let Event::TimeOut(_) = event {
-                self.events.pop();
-            }
+        //a pending TimeOut is merged, so handlers see the whole elapsed time
+        if let Some((Event::TimeOut(pending), _status)) = self.events.last_mut() {
+            *pending = pending.saturating_add(ms_since_last);
+            return;
         }
         self.events
             .push((Event::TimeOut(ms_since_last), EventStatus::Unhandled));
     }
+    /// add a batch of events (e.g. one matrix scan),
+    /// then handle them in one handle_keys pass
+    #[allow(clippy::result_unit_err)] // same as handle_keys
+    pub fn feed(&mut self, events: &[InputEvent]) -> Result<(), ()> {
+        for event in events {
+            match *event {
+                InputEvent::Press(keycode, ms_since_last) => self.add_keypress(keycode, ms_since_last),
+                InputEvent::Release(keycode, ms_since_last) => {
+                    self.add_keyrelease(keycode, ms_since_last)
+                }
+                InputEvent::Timeout(ms_since_last) => self.add_timeout(ms_since_last),
+            }
+        }
+        self.handle_keys()
+    }
+}
+/// Input for Keyboard::feed - keycode and/or ms_since_last,
+/// like the add_keypress / add_keyrelease / add_timeout arguments
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    Press(u32, u16),
+    Release(u32, u16),
+    Timeout(u16),
 }
 /// Different operating systems expect random unicode input
 /// as different key combinations
@@ -235,8 +541,17 @@ pub enum UnicodeSendMode {
     Linux = 1,
     LinuxDvorak,
     /// use https://github.com/samhocevar/wincompose
+    /// (compose key is KeyboardState::compose_key)
     WinCompose,
     WinComposeDvorak,
+    /// macOS 'Unicode Hex Input' source:
+    /// hold Option, type the (UTF-16) hex digits, release
+    MacOs,
+    /// plain Windows: hold Alt, type 0 and the decimal code on the numpad.
+    /// The leading zero selects the ANSI code page, which only
+    /// covers 0-255 (and matches unicode only for 0-127 and 160-255) -
+    /// other characters are not send.
+    WinAltNumpad,
     // used by the tests
     Debug,
 }
@@ -270,6 +585,21 @@ fn hex_digit_to_keycode(digit: char) -> KeyCode {
         _ => panic!("Passed more than one digit to hex_digit_to_keycode"),
     }
 }
+/// transform a decimal digit (0..=9) to it's numpad USB keycode
+fn decimal_digit_to_numpad_keycode(digit: u32) -> KeyCode {
+    match digit {
+        0 => KeyCode::Kp0,
+        1 => KeyCode::Kp1,
+        2 => KeyCode::Kp2,
+        3 => KeyCode::Kp3,
+        4 => KeyCode::Kp4,
+        5 => KeyCode::Kp5,
+        6 => KeyCode::Kp6,
+        7 => KeyCode::Kp7,
+        8 => KeyCode::Kp8,
+        _ => KeyCode::Kp9,
+    }
+}
 fn hex_digit_to_keycode_dvorak(digit: char) -> KeyCode {
     //todo which way it's shorter in machine code this or
     //with the derived nums...
@@ -304,6 +634,12 @@ pub trait USBKeyOut {
     fn send_registered(&mut self);
     /// helper that sends an empty status
     fn send_empty(&mut self);
+    /// send keys as a single report, immediately followed by an empty one,
+    /// i.e. tap a chord
+    fn tap_keys(&mut self, keys: &[KeyCode]) {
+        self.send_keys(keys);
+        self.send_empty();
+    }
     /// retrieve a mutable KeyboardState
     fn state(&mut self) -> &mut KeyboardState;
     fn ro_state(&self) -> &KeyboardState;
@@ -311,8 +647,18 @@ pub trait USBKeyOut {
     fn bootloader(&mut self); // start the boot loader
     //
     // register to send later.
+    // Keys are send in the order they were registered,
+    // each group ms after the previous one.
     fn send_keys_later(&mut self, keys: &[KeyCode], ms: u16);
     fn do_send_later(&mut self);
+    /// send a mouse report - buttons is a bitmask (1: left, 2: right, 4: middle),
+    /// the rest are relative movements.
+    /// Default does nothing, for keyboards without a mouse interface.
+    fn send_mouse(&mut self, _buttons: u8, _dx: i8, _dy: i8, _wheel: i8) {}
+    /// send a consumer control report (media keys etc) - usage is the
+    /// USB HID consumer page usage id, 0 releases it.
+    /// Default does nothing, for keyboards without a consumer control interface.
+    fn send_consumer(&mut self, _usage: u16) {}
 
     fn send_unicode(&mut self, c: char) {
         match self.state().unicode_mode {
@@ -347,7 +693,8 @@ pub trait USBKeyOut {
                 self.send_empty();
             }
             UnicodeSendMode::WinCompose => {
-                self.send_keys(&[KeyCode::RAlt]);
+                let compose_key = self.state().compose_key;
+                self.send_keys(&[compose_key]);
                 self.send_keys(&[KeyCode::U]);
                 let escaped = c.escape_unicode();
                 for out_c in escaped.skip(3).take_while(|x| *x != '}') {
@@ -357,7 +704,8 @@ pub trait USBKeyOut {
                 self.send_empty();
             }
             UnicodeSendMode::WinComposeDvorak => {
-                self.send_keys(&[KeyCode::RAlt]);
+                let compose_key = self.state().compose_key;
+                self.send_keys(&[compose_key]);
                 self.send_keys(&[KeyCode::F]);
                 let escaped = c.escape_unicode();
                 for out_c in escaped.skip(3).take_while(|x| *x != '}') {
@@ -366,6 +714,32 @@ pub trait USBKeyOut {
                 self.send_keys(&[KeyCode::Enter]);
                 self.send_empty();
             }
+            UnicodeSendMode::MacOs => {
+                let mut buf = [0u16; 2];
+                self.send_keys(&[KeyCode::LAlt]);
+                for unit in c.encode_utf16(&mut buf).iter() {
+                    for shift in [12, 8, 4, 0].iter() {
+                        let digit = core::char::from_digit(u32::from((unit >> shift) & 0xf), 16)
+                            .unwrap();
+                        self.send_keys(&[KeyCode::LAlt, hex_digit_to_keycode(digit)]);
+                        self.send_keys(&[KeyCode::LAlt]);
+                    }
+                }
+                self.send_empty();
+            }
+            UnicodeSendMode::WinAltNumpad => {
+                let code = c as u32;
+                if code <= 255 {
+                    self.send_keys(&[KeyCode::LAlt]);
+                    self.send_keys(&[KeyCode::LAlt, KeyCode::Kp0]);
+                    self.send_keys(&[KeyCode::LAlt]);
+                    for digit in [100, 10, 1].iter().map(|div| (code / div) % 10) {
+                        self.send_keys(&[KeyCode::LAlt, decimal_digit_to_numpad_keycode(digit)]);
+                        self.send_keys(&[KeyCode::LAlt]);
+                    }
+                    self.send_empty();
+                }
+            }
 
             UnicodeSendMode::Debug => {
                 let escaped = c.escape_unicode();
@@ -382,25 +756,93 @@ pub trait USBKeyOut {
     /// all characters are converted into unicode input!
     fn send_string(&mut self, s: &str) {
         for c in s.chars() {
-            /* the problem with this approach: it is dependant on the shift/caps lock state
-            match c {
-                'a'..='z' => {self.send_keys(&[ascii_to_keycode(c, 97, KeyCode::A)]); self.send_empty()},
-                '1'..='9' => {self.send_keys(&[ascii_to_keycode(c, 49, KeyCode::Kb1)]); self.send_empty()},
-                '0' => {self.send_keys(&[KeyCode::Kb0]); self.send_empty()},
-                'A'..='Z' => {self.send_keys(&[ascii_to_keycode(c, 65, KeyCode::A), KeyCode::LShift]); self.send_empty()},
-                _ => self.send_unicode(c),
-            }
-            */
             //probably best to unicode everything
+            //(see send_string_ascii for the direct keycode route)
             self.send_unicode(c);
-
-            // option: send simple ones directly?
-            /*
-            if 'a' <= c && c <= 'z' {
-                            }
-            */
         }
     }
+    /// send a string to the host, typing ASCII characters as keycodes
+    ///
+    /// a-z, A-Z (shifted), 0-9 and the common punctuation are sent as
+    /// (US layout) key presses, everything else falls back to send_unicode.
+    /// Faster than send_string and independent of the OS unicode input setup,
+    /// but dependant on the host's layout and shift/caps lock state.
+    fn send_string_ascii(&mut self, s: &str) {
+        for c in s.chars() {
+            match ascii_to_keycodes(c) {
+                Some((keycode, true)) => self.send_keys(&[KeyCode::LShift, keycode]),
+                Some((keycode, false)) => self.send_keys(&[keycode]),
+                None => {
+                    self.send_unicode(c);
+                    continue;
+                }
+            }
+            self.send_empty();
+        }
+    }
+    /// send a utf-8 string to the host, waiting per_char_ms before each character
+    ///
+    /// For hosts that drop characters when they arrive too fast (RDP, VMs...).
+    /// Uses send_keys_later, so your do_send_later has to be called regularly.
+    fn send_string_delayed(&mut self, s: &str, per_char_ms: u16) {
+        for c in s.chars() {
+            let mut out = DelayedOutput {
+                inner: self,
+                ms: per_char_ms,
+            };
+            out.send_unicode(c);
+        }
+    }
+}
+/// redirects the output of send_unicode into send_keys_later
+/// only the first group of keys waits for ms, the rest follow right after
+pub(crate) struct DelayedOutput<'a, T: USBKeyOut + ?Sized> {
+    inner: &'a mut T,
+    ms: u16,
+}
+impl<'a, T: USBKeyOut + ?Sized> DelayedOutput<'a, T> {
+    pub(crate) fn new(inner: &'a mut T, ms: u16) -> DelayedOutput<'a, T> {
+        DelayedOutput { inner, ms }
+    }
+}
+impl<T: USBKeyOut + ?Sized> USBKeyOut for DelayedOutput<'_, T> {
+    fn send_keys(&mut self, keys: &[KeyCode]) {
+        self.inner.send_keys_later(keys, self.ms);
+        self.ms = 0;
+    }
+    fn register_key(&mut self, key: KeyCode) {
+        self.inner.register_key(key);
+    }
+    fn send_registered(&mut self) {
+        self.inner.send_registered();
+    }
+    fn send_empty(&mut self) {
+        self.send_keys(&[]);
+    }
+    fn state(&mut self) -> &mut KeyboardState {
+        self.inner.state()
+    }
+    fn ro_state(&self) -> &KeyboardState {
+        self.inner.ro_state()
+    }
+    fn debug(&mut self, s: &str) {
+        self.inner.debug(s);
+    }
+    fn bootloader(&mut self) {
+        self.inner.bootloader();
+    }
+    fn send_keys_later(&mut self, keys: &[KeyCode], ms: u16) {
+        self.inner.send_keys_later(keys, ms);
+    }
+    fn do_send_later(&mut self) {
+        self.inner.do_send_later();
+    }
+    fn send_mouse(&mut self, buttons: u8, dx: i8, dy: i8, wheel: i8) {
+        self.inner.send_mouse(buttons, dx, dy, wheel);
+    }
+    fn send_consumer(&mut self, usage: u16) {
+        self.inner.send_consumer(usage);
+    }
 }
 fn ascii_to_keycode(c: char, ascii_offset: u8, keycode_offset: KeyCode) -> KeyCode {
     let mut ascii = [0 as u8]; // buffer
@@ -411,6 +853,51 @@ fn ascii_to_keycode(c: char, ascii_offset: u8, keycode_offset: KeyCode) -> KeyCo
     let keycode: KeyCode = keycode.try_into().unwrap();
     keycode
 }
+/// map an ASCII character to it's (US layout) keycode and whether it needs shift
+fn ascii_to_keycodes(c: char) -> Option<(KeyCode, bool)> {
+    Some(match c {
+        'a'..='z' => (ascii_to_keycode(c, 97, KeyCode::A), false),
+        'A'..='Z' => (ascii_to_keycode(c, 65, KeyCode::A), true),
+        '1'..='9' => (ascii_to_keycode(c, 49, KeyCode::Kb1), false),
+        '0' => (KeyCode::Kb0, false),
+        ' ' => (KeyCode::Space, false),
+        '\n' => (KeyCode::Enter, false),
+        '\t' => (KeyCode::Tab, false),
+        '-' => (KeyCode::Minus, false),
+        '_' => (KeyCode::Minus, true),
+        '=' => (KeyCode::Equal, false),
+        '+' => (KeyCode::Equal, true),
+        '[' => (KeyCode::LBracket, false),
+        '{' => (KeyCode::LBracket, true),
+        ']' => (KeyCode::RBracket, false),
+        '}' => (KeyCode::RBracket, true),
+        '\\' => (KeyCode::BSlash, false),
+        '|' => (KeyCode::BSlash, true),
+        ';' => (KeyCode::SColon, false),
+        ':' => (KeyCode::SColon, true),
+        '\'' => (KeyCode::Quote, false),
+        '"' => (KeyCode::Quote, true),
+        '`' => (KeyCode::Grave, false),
+        '~' => (KeyCode::Grave, true),
+        ',' => (KeyCode::Comma, false),
+        '<' => (KeyCode::Comma, true),
+        '.' => (KeyCode::Dot, false),
+        '>' => (KeyCode::Dot, true),
+        '/' => (KeyCode::Slash, false),
+        '?' => (KeyCode::Slash, true),
+        '!' => (KeyCode::Kb1, true),
+        '@' => (KeyCode::Kb2, true),
+        '#' => (KeyCode::Kb3, true),
+        '$' => (KeyCode::Kb4, true),
+        '%' => (KeyCode::Kb5, true),
+        '^' => (KeyCode::Kb6, true),
+        '&' => (KeyCode::Kb7, true),
+        '*' => (KeyCode::Kb8, true),
+        '(' => (KeyCode::Kb9, true),
+        ')' => (KeyCode::Kb0, true),
+        _ => return None,
+    })
+}
 //so the tests 'just work'.
 #[cfg(test)]
 #[macro_use]
@@ -426,4 +913,379 @@ mod tests {
             }
         }
     }
+    #[test]
+    fn test_feed() {
+        use crate::handlers::USBKeyboard;
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::{InputEvent, KeyCode, Keyboard};
+        use no_std_compat::prelude::v1::*;
+        let a = KeyCode::A.to_u32();
+        let b = KeyCode::B.to_u32();
+        let mut fed = Keyboard::new(KeyOutCatcher::new());
+        fed.add_handler(Box::new(USBKeyboard::new()));
+        assert!(fed
+            .feed(&[
+                InputEvent::Press(a, 0),
+                InputEvent::Press(b, 10),
+                InputEvent::Timeout(5),
+                InputEvent::Timeout(10),
+                InputEvent::Release(a, 20),
+            ])
+            .is_ok());
+        assert!(fed.feed(&[InputEvent::Release(b, 10)]).is_ok());
+
+        let mut individual = Keyboard::new(KeyOutCatcher::new());
+        individual.add_handler(Box::new(USBKeyboard::new()));
+        individual.add_keypress(a, 0);
+        individual.add_keypress(b, 10);
+        individual.add_timeout(5);
+        individual.add_timeout(10);
+        individual.add_keyrelease(a, 20);
+        individual.handle_keys().unwrap();
+        individual.add_keyrelease(b, 10);
+        individual.handle_keys().unwrap();
+
+        assert!(fed.output.reports == individual.output.reports);
+        assert!(fed.output.reports.len() == 2);
+        assert!(fed.events.is_empty());
+    }
+
+    #[test]
+    fn test_observed_events() {
+        use crate::handlers::{HandlerResult, USBKeyboard};
+        use crate::premade::FnHandler;
+        use crate::test_helpers::{Checks, KeyOutCatcher};
+        use crate::{iter_unhandled_mut, Event, EventStatus, KeyCode, Keyboard, USBKeyOut, UserKey};
+        use alloc::sync::Arc;
+        use no_std_compat::prelude::v1::*;
+        use spin::RwLock;
+        let seen = Arc::new(RwLock::new(0));
+        let seen2 = seen.clone();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(FnHandler::new(
+            move |events: &mut Vec<(Event, EventStatus)>, _output: &mut dyn USBKeyOut| {
+                for (e, status) in iter_unhandled_mut(events) {
+                    if let Event::KeyPress(_) = e {
+                        *seen2.write() += 1;
+                    }
+                    *status = EventStatus::Observed;
+                }
+                HandlerResult::NoOp
+            },
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //the USBKeyboard still gets them
+        keyboard.pc(KeyCode::A, &[&[KeyCode::A]]);
+        keyboard.rc(KeyCode::A, &[&[]]);
+        assert!(*seen.read() == 2); //held press is seen again on release
+        //nobody claims it - not an error, but kept for a later pass
+        keyboard.add_keypress(UserKey::UK0, 0);
+        assert!(keyboard.handle_keys().is_ok());
+        assert!(keyboard.events.len() == 1);
+        assert!(*seen.read() == 3);
+        assert!(keyboard.handle_keys_collect().is_ok());
+        assert!(keyboard.events.len() == 1);
+        keyboard.clear_unhandled();
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_future_handler_id() {
+        use crate::handlers::USBKeyboard;
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::{Keyboard, KEYBOARD_STATE_RESERVED_BITS};
+        use no_std_compat::prelude::v1::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        assert!(keyboard.handler_count() == 0);
+        assert!(keyboard.handler_index_of_last().is_none());
+        let next = keyboard.future_handler_id(1);
+        let second = keyboard.future_handler_id(2);
+        assert!(next == KEYBOARD_STATE_RESERVED_BITS);
+        assert!(keyboard.add_handler(Box::new(USBKeyboard::new())) == next);
+        assert!(keyboard.handler_index_of_last() == Some(next));
+        assert!(keyboard.add_handler(Box::new(USBKeyboard::new())) == second);
+        assert!(keyboard.handler_index_of_last() == Some(second));
+        assert!(keyboard.handler_count() == 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_future_handler_id_zero() {
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::Keyboard;
+        let keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.future_handler_id(0);
+    }
+
+    #[test]
+    fn test_set_handlers() {
+        use crate::handlers::{AutoOff, HandlerResult, Layer, USBKeyboard};
+        use crate::premade::FnHandler;
+        use crate::test_helpers::{Checks, KeyOutCatcher};
+        use crate::{iter_unhandled_mut, Event, EventStatus, KeyCode, Keyboard, USBKeyOut, UserKey};
+        use no_std_compat::prelude::v1::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let l1 = keyboard.future_handler_id(3);
+        let l2 = keyboard.future_handler_id(4);
+        let l3 = keyboard.future_handler_id(5);
+        //switch from l1 to l2 + l3
+        keyboard.add_handler(Box::new(FnHandler::new(
+            move |events: &mut Vec<(Event, EventStatus)>, output: &mut dyn USBKeyOut| {
+                for (e, status) in iter_unhandled_mut(events) {
+                    match e {
+                        Event::KeyPress(kc) | Event::KeyRelease(kc) => {
+                            if kc.keycode == UserKey::UK0.to_u32() {
+                                *status = EventStatus::Handled;
+                                if let Event::KeyPress(_) = e {
+                                    output
+                                        .state()
+                                        .set_handlers(&[(l1, false), (l2, true), (l3, true)]);
+                                }
+                            }
+                        }
+                        Event::TimeOut(_) => {}
+                    }
+                }
+                HandlerResult::NoOp
+            },
+        )));
+        //would misbehave on a partial mode switch
+        keyboard.add_handler(Box::new(FnHandler::new(
+            move |_events: &mut Vec<(Event, EventStatus)>, output: &mut dyn USBKeyOut| {
+                let state = output.state();
+                assert!(state.is_handler_enabled(l2) == state.is_handler_enabled(l3));
+                assert!(state.is_handler_enabled(l1) != state.is_handler_enabled(l2));
+                HandlerResult::NoOp
+            },
+        )));
+        for _ in 0..3 {
+            keyboard.add_handler(Box::new(Layer::new(Vec::<(u32, _)>::new(), AutoOff::No)));
+        }
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().enable_handler(l1);
+        keyboard.pc(KeyCode::A, &[&[KeyCode::A]]);
+        keyboard.rc(KeyCode::A, &[&[]]);
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(l1));
+        assert!(keyboard.output.state().is_handler_enabled(l2));
+        assert!(keyboard.output.state().is_handler_enabled(l3));
+        keyboard.rc(UserKey::UK0, &[&[]]);
+    }
+    #[test]
+    fn test_on_enabled_on_disabled() {
+        use crate::handlers::HandlerResult;
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::{Event, EventStatus, Keyboard, ProcessKeys, USBKeyOut};
+        use alloc::sync::Arc;
+        use no_std_compat::prelude::v1::*;
+        use spin::RwLock;
+        struct ToggleCounter {
+            counts: Arc<RwLock<(u8, u8)>>, // enabled, disabled
+        }
+        impl<T: USBKeyOut> ProcessKeys<T> for ToggleCounter {
+            fn process_keys(
+                &mut self,
+                _events: &mut Vec<(Event, EventStatus)>,
+                _output: &mut T,
+            ) -> HandlerResult {
+                HandlerResult::NoOp
+            }
+            fn on_enabled(&mut self, _output: &mut T) {
+                self.counts.write().0 += 1;
+            }
+            fn on_disabled(&mut self, _output: &mut T) {
+                self.counts.write().1 += 1;
+            }
+        }
+        let counts = Arc::new(RwLock::new((0, 0)));
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let id = keyboard.add_handler(Box::new(ToggleCounter {
+            counts: counts.clone(),
+        }));
+        keyboard.handle_keys().unwrap();
+        assert!(*counts.read() == (0, 0));
+        keyboard.output.state().disable_handler(id);
+        keyboard.handle_keys().unwrap();
+        assert!(*counts.read() == (0, 1));
+        keyboard.handle_keys().unwrap();
+        assert!(*counts.read() == (0, 1));
+        keyboard.output.state().enable_handler(id);
+        keyboard.handle_keys().unwrap();
+        assert!(*counts.read() == (1, 1));
+        keyboard.output.state().toggle_handler(id);
+        keyboard.handle_keys().unwrap();
+        keyboard.output.state().toggle_handler(id);
+        keyboard.handle_keys().unwrap();
+        assert!(*counts.read() == (2, 2));
+    }
+    #[test]
+    fn test_pending_events() {
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::{Event, EventStatus, KeyCode, Keyboard};
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        assert!(keyboard.pending_events().is_empty());
+        keyboard.add_keypress(KeyCode::A, 0);
+        assert!(keyboard.handle_keys().is_err());
+        assert!(keyboard.unhandled_count() == 1);
+        match keyboard.pending_events() {
+            [(Event::KeyPress(kc), EventStatus::Unhandled)] => {
+                assert!(kc.keycode == KeyCode::A.to_u32())
+            }
+            _ => panic!("unexpected events"),
+        }
+        keyboard.clear_unhandled();
+        assert!(keyboard.unhandled_count() == 0);
+    }
+    #[test]
+    fn test_ignored_event_ttl() {
+        use crate::handlers::HandlerResult;
+        use crate::premade::FnHandler;
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::{iter_unhandled_mut, Event, EventStatus, Keyboard, USBKeyOut, UserKey};
+        use no_std_compat::prelude::v1::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        //never resolves UK0
+        keyboard.add_handler(Box::new(FnHandler::new(
+            |events: &mut Vec<(Event, EventStatus)>, _output: &mut dyn USBKeyOut| {
+                for (_e, status) in iter_unhandled_mut(events) {
+                    *status = EventStatus::Ignored;
+                }
+                HandlerResult::NoOp
+            },
+        )));
+        keyboard.add_keypress(UserKey::UK0, 0);
+        for _ in 0..5 {
+            keyboard.handle_keys().unwrap();
+        }
+        assert!(keyboard.events.len() == 1);
+
+        keyboard.set_ignored_event_ttl(Some(3));
+        for _ in 0..3 {
+            keyboard.handle_keys().unwrap();
+            assert!(keyboard.events.len() == 1);
+        }
+        keyboard.handle_keys().unwrap();
+        assert!(keyboard.events.is_empty());
+    }
+    #[test]
+    fn test_wincompose_compose_key() {
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::{KeyCode, USBKeyOut, UnicodeSendMode};
+        let mut output = KeyOutCatcher::new();
+        output.state().unicode_mode = UnicodeSendMode::WinCompose;
+        output.send_unicode('a');
+        assert!(output.reports[0] == vec![KeyCode::RAlt.to_u8()]);
+        output.clear();
+        output.state().compose_key = KeyCode::Application;
+        output.send_unicode('a');
+        assert!(output.reports[0] == vec![KeyCode::Application.to_u8()]);
+        assert!(output.reports[1] == vec![KeyCode::U.to_u8()]);
+    }
+    #[test]
+    fn test_handle_keys_collect() {
+        use crate::handlers::USBKeyboard;
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::{Event, KeyCode, Keyboard, UserKey};
+        use no_std_compat::prelude::v1::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.add_keypress(KeyCode::A, 0);
+        assert!(keyboard.handle_keys_collect().is_ok());
+        keyboard.add_keypress(UserKey::UK3, 0);
+        match keyboard.handle_keys_collect() {
+            Err(unhandled) => {
+                assert!(unhandled.len() == 1);
+                match &unhandled[0] {
+                    Event::KeyPress(kc) => assert!(kc.keycode == UserKey::UK3.to_u32()),
+                    _ => panic!("expected a KeyPress"),
+                }
+            }
+            Ok(()) => panic!("UK3 should have been unhandled"),
+        }
+        //removed from the stream - only the A press is left
+        assert!(keyboard.events.len() == 1);
+        //the old interface just reports that something was unhandled
+        keyboard.add_keypress(UserKey::UK3, 0);
+        assert!(keyboard.handle_keys().is_err());
+        assert!(keyboard.events.len() == 2);
+    }
+    #[test]
+    fn test_handler_names() {
+        use crate::handlers::USBKeyboard;
+        use crate::premade::CopyPaste;
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::Keyboard;
+        use no_std_compat::prelude::v1::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let copy_paste = keyboard.add_named_handler("copy paste", Box::new(CopyPaste {}));
+        let unnamed = keyboard.add_handler(Box::new(CopyPaste {}));
+        let usb = keyboard.add_named_handler("usb", Box::new(USBKeyboard::new()));
+        assert!(keyboard.handler_name(copy_paste) == Some("copy paste"));
+        assert!(keyboard.handler_name(unnamed) == Some("unnamed"));
+        assert!(keyboard.handler_name(usb) == Some("usb"));
+        assert!(keyboard.handler_name(usb + 1).is_none());
+        assert!(keyboard.handler_name(0).is_none());
+    }
+    #[test]
+    fn test_tap_keys() {
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::{KeyCode, USBKeyOut};
+        let mut output = KeyOutCatcher::new();
+        output.tap_keys(&[KeyCode::LCtrl, KeyCode::C]);
+        assert!(
+            output.reports
+                == vec![vec![KeyCode::LCtrl.to_u8(), KeyCode::C.to_u8()], vec![]]
+        );
+    }
+    #[test]
+    fn test_send_string_delayed() {
+        use crate::test_helpers::KeyOutCatcher;
+        use crate::{KeyCode, USBKeyOut, UnicodeSendMode};
+        let mut output = KeyOutCatcher::new();
+        output.state().unicode_mode = UnicodeSendMode::Debug;
+        output.send_string_delayed("abc", 20);
+        assert!(output.reports.is_empty());
+        assert!(
+            output.later
+                == vec![
+                    (20, vec![KeyCode::Kb6]),
+                    (0, vec![KeyCode::Kb1]),
+                    (20, vec![KeyCode::Kb6]),
+                    (0, vec![KeyCode::Kb2]),
+                    (20, vec![KeyCode::Kb6]),
+                    (0, vec![KeyCode::Kb3]),
+                ]
+        );
+    }
+    #[test]
+    fn test_send_string_ascii() {
+        use crate::test_helpers::{check_output, KeyOutCatcher};
+        use crate::{KeyCode, Keyboard, USBKeyOut, UnicodeSendMode};
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Debug;
+        keyboard.output.send_string_ascii("Ab1");
+        check_output(
+            &keyboard,
+            &[
+                &[KeyCode::LShift, KeyCode::A],
+                &[],
+                &[KeyCode::B],
+                &[],
+                &[KeyCode::Kb1],
+                &[],
+            ],
+        );
+        keyboard.output.clear();
+        //non-ascii falls back to unicode
+        keyboard.output.send_string_ascii("?\u{DF}");
+        check_output(
+            &keyboard,
+            &[
+                &[KeyCode::LShift, KeyCode::Slash],
+                &[],
+                &[KeyCode::D],
+                &[KeyCode::F],
+            ],
+        );
+    }
 }
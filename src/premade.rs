@@ -1,9 +1,11 @@
-use crate::handlers::RewriteLayer;
+use crate::handlers::{MultiModifier, RewriteLayer};
 /// premade handlers for various occacions
-use crate::handlers::{Action, OnOff, OneShot, PressReleaseMacro, SpaceCadet, HandlerResult, ProcessKeys};
+use crate::handlers::{Action, OnOff, OneShot, PressMacro, PressReleaseMacro, SpaceCadet, HandlerResult, ProcessKeys};
+use crate::handlers::{TapDance, TapDanceAction, TapDanceEnd};
 use crate::key_stream::{iter_unhandled_mut, Event, EventStatus};
 use crate::Modifier::*;
-use crate::{AcceptsKeycode, HandlerID, KeyCode, USBKeyOut};
+use crate::{AcceptsKeycode, DelayedOutput, HandlerID, KeyCode, Modifier, USBKeyOut, UnicodeSendMode, MODIFIER_KEYS};
+use core::convert::TryFrom;
 use no_std_compat::prelude::v1::*;
 ///toggle a handler on activate
 /// do noting on deactivate
@@ -35,63 +37,211 @@ pub fn toggle_handler(
         ActionToggleHandler { id },
     ))
 }
+/// toggle a handler on activate and then
+/// trigger on_action or off_action depending on the new state.
+/// do noting on deactivate
+/// used by toggle_handler_with_action()
+pub struct ActionToggleHandlerWithAction<A1, A2> {
+    pub toggle: ActionToggleHandler,
+    pub on_action: A1,
+    pub off_action: A2,
+}
+impl<A1: Action, A2: Action> OnOff for ActionToggleHandlerWithAction<A1, A2> {
+    fn on_activate(&mut self, output: &mut dyn USBKeyOut) {
+        self.toggle.on_activate(output);
+        if output.state().is_handler_enabled(self.toggle.id) {
+            self.on_action.on_trigger(output);
+        } else {
+            self.off_action.on_trigger(output);
+        }
+    }
+    fn on_deactivate(&mut self, _output: &mut dyn USBKeyOut) {}
+}
+
+/// Toggles a handler on and off when a key is pressed,
+/// and triggers on_action/off_action after it has been enabled/disabled
+pub fn toggle_handler_with_action<A1: Action, A2: Action>(
+    trigger: impl AcceptsKeycode,
+    id: HandlerID,
+    on_action: A1,
+    off_action: A2,
+) -> Box<PressReleaseMacro<ActionToggleHandlerWithAction<A1, A2>>> {
+    Box::new(PressReleaseMacro::new(
+        trigger.to_u32(),
+        ActionToggleHandlerWithAction {
+            toggle: ActionToggleHandler { id },
+            on_action,
+            off_action,
+        },
+    ))
+}
+/// the dvorak rewrites, plus any extra (from, to) pairs
+macro_rules! dvorak_rewrites {
+    ($($extra:expr),*) => {
+        &[
+            (Q.to_u32(), Quote.to_u32()),
+            (W.to_u32(), Comma.to_u32()),
+            (E.to_u32(), Dot.to_u32()),
+            (R.to_u32(), P.to_u32()),
+            (T.to_u32(), Y.to_u32()),
+            (Y.to_u32(), F.to_u32()),
+            (U.to_u32(), G.to_u32()),
+            (I.to_u32(), C.to_u32()),
+            (O.to_u32(), R.to_u32()),
+            (P.to_u32(), L.to_u32()),
+            //(A.to_u32(), (A.to_u32()),
+            (S.to_u32(), O.to_u32()),
+            (D.to_u32(), E.to_u32()),
+            (F.to_u32(), U.to_u32()),
+            (G.to_u32(), I.to_u32()),
+            (H.to_u32(), D.to_u32()),
+            (J.to_u32(), H.to_u32()),
+            (K.to_u32(), T.to_u32()),
+            (L.to_u32(), N.to_u32()),
+            (SColon.to_u32(), S.to_u32()),
+            (Quote.to_u32(), Minus.to_u32()),
+            (Z.to_u32(), SColon.to_u32()),
+            (X.to_u32(), Q.to_u32()),
+            (C.to_u32(), J.to_u32()),
+            (V.to_u32(), K.to_u32()),
+            (B.to_u32(), X.to_u32()),
+            (N.to_u32(), B.to_u32()),
+            (M.to_u32(), M.to_u32()),
+            (Comma.to_u32(), W.to_u32()),
+            (Dot.to_u32(), V.to_u32()),
+            (Slash.to_u32(), Z.to_u32()),
+            //(BSlash.to_u32(), Bslash.to_u32()),
+            (Equal.to_u32(), RBracket.to_u32()),
+            (Quote.to_u32(), Minus.to_u32()),
+            (RBracket.to_u32(), Equal.to_u32()),
+            //(Grave.to_u32(), (Grave.to_u32()),
+            (Minus.to_u32(), LBracket.to_u32()),
+            (LBracket.to_u32(), Slash.to_u32()),
+            $($extra),*
+        ]
+    };
+}
+/// the colemak rewrites, plus any extra (from, to) pairs
+macro_rules! colemak_rewrites {
+    ($($extra:expr),*) => {
+        &[
+            (E.to_u32(), F.to_u32()),
+            (R.to_u32(), P.to_u32()),
+            (T.to_u32(), G.to_u32()),
+            (Y.to_u32(), J.to_u32()),
+            (U.to_u32(), L.to_u32()),
+            (I.to_u32(), U.to_u32()),
+            (O.to_u32(), Y.to_u32()),
+            (P.to_u32(), SColon.to_u32()),
+            (S.to_u32(), R.to_u32()),
+            (D.to_u32(), S.to_u32()),
+            (F.to_u32(), T.to_u32()),
+            (G.to_u32(), D.to_u32()),
+            (J.to_u32(), N.to_u32()),
+            (K.to_u32(), E.to_u32()),
+            (L.to_u32(), I.to_u32()),
+            (SColon.to_u32(), O.to_u32()),
+            (N.to_u32(), K.to_u32()),
+            $($extra),*
+        ]
+    };
+}
+/// the workman rewrites, plus any extra (from, to) pairs
+macro_rules! workman_rewrites {
+    ($($extra:expr),*) => {
+        &[
+            (W.to_u32(), D.to_u32()),
+            (E.to_u32(), R.to_u32()),
+            (R.to_u32(), W.to_u32()),
+            (T.to_u32(), B.to_u32()),
+            (Y.to_u32(), J.to_u32()),
+            (U.to_u32(), F.to_u32()),
+            (I.to_u32(), U.to_u32()),
+            (O.to_u32(), P.to_u32()),
+            (P.to_u32(), SColon.to_u32()),
+            (D.to_u32(), H.to_u32()),
+            (F.to_u32(), T.to_u32()),
+            (H.to_u32(), Y.to_u32()),
+            (J.to_u32(), N.to_u32()),
+            (K.to_u32(), E.to_u32()),
+            (L.to_u32(), O.to_u32()),
+            (SColon.to_u32(), I.to_u32()),
+            (C.to_u32(), M.to_u32()),
+            (V.to_u32(), C.to_u32()),
+            (B.to_u32(), V.to_u32()),
+            (N.to_u32(), K.to_u32()),
+            (M.to_u32(), L.to_u32()),
+            $($extra),*
+        ]
+    };
+}
+/// A RewriteLayer from a const (from, to) table,
+/// see dvorak() for an example
+pub fn rewrite_layer_from(map: &'static [(u32, u32)]) -> Box<RewriteLayer> {
+    Box::new(RewriteLayer::new(map))
+}
 /// A layer that maps qwerty to dvorak.
 /// Don't forget to enable it, layers are off by default
 pub fn dvorak() -> Box<RewriteLayer> {
     use crate::key_codes::KeyCode::*;
-    const MAP: &[(u32, u32)] = &[
-        (Q.to_u32(), Quote.to_u32()),
-        (W.to_u32(), Comma.to_u32()),
-        (E.to_u32(), Dot.to_u32()),
-        (R.to_u32(), P.to_u32()),
-        (T.to_u32(), Y.to_u32()),
-        (Y.to_u32(), F.to_u32()),
-        (U.to_u32(), G.to_u32()),
-        (I.to_u32(), C.to_u32()),
-        (O.to_u32(), R.to_u32()),
-        (P.to_u32(), L.to_u32()),
-        //(A.to_u32(), (A.to_u32()),
-        (S.to_u32(), O.to_u32()),
-        (D.to_u32(), E.to_u32()),
-        (F.to_u32(), U.to_u32()),
-        (G.to_u32(), I.to_u32()),
-        (H.to_u32(), D.to_u32()),
-        (J.to_u32(), H.to_u32()),
-        (K.to_u32(), T.to_u32()),
-        (L.to_u32(), N.to_u32()),
-        (SColon.to_u32(), S.to_u32()),
-        (Quote.to_u32(), Minus.to_u32()),
-        (Z.to_u32(), SColon.to_u32()),
-        (X.to_u32(), Q.to_u32()),
-        (C.to_u32(), J.to_u32()),
-        (V.to_u32(), K.to_u32()),
-        (B.to_u32(), X.to_u32()),
-        (N.to_u32(), B.to_u32()),
-        (M.to_u32(), M.to_u32()),
-        (Comma.to_u32(), W.to_u32()),
-        (Dot.to_u32(), V.to_u32()),
-        (Slash.to_u32(), Z.to_u32()),
-        //(BSlash.to_u32(), Bslash.to_u32()),
-        (Equal.to_u32(), RBracket.to_u32()),
-        (Quote.to_u32(), Minus.to_u32()),
-        (RBracket.to_u32(), Equal.to_u32()),
-        //(Grave.to_u32(), (Grave.to_u32()),
-        (Minus.to_u32(), LBracket.to_u32()),
-        (LBracket.to_u32(), Slash.to_u32()),
-    ];
-    Box::new(RewriteLayer::new(MAP))
+    const MAP: &[(u32, u32)] = dvorak_rewrites!();
+    rewrite_layer_from(MAP)
+}
+/// dvorak() for ISO keyboards, assuming the host layout is US (like dvorak()).
+/// NonUsBslash (the extra key next to LShift) becomes backslash,
+/// where UK Dvorak puts it - US hosts otherwise send '<>' or nothing for it.
+/// NonUsHash (next to Enter) already sends backslash on a US host,
+/// just like BSlash does on ANSI, and is left alone.
+pub fn dvorak_iso() -> Box<RewriteLayer> {
+    use crate::key_codes::KeyCode::*;
+    const MAP: &[(u32, u32)] = dvorak_rewrites!((NonUsBslash.to_u32(), BSlash.to_u32()));
+    rewrite_layer_from(MAP)
+}
+/// A layer that maps qwerty to colemak.
+/// Don't forget to enable it, layers are off by default
+pub fn colemak() -> Box<RewriteLayer> {
+    use crate::key_codes::KeyCode::*;
+    const MAP: &[(u32, u32)] = colemak_rewrites!();
+    rewrite_layer_from(MAP)
+}
+/// A layer that maps qwerty to workman.
+/// Don't forget to enable it, layers are off by default
+pub fn workman() -> Box<RewriteLayer> {
+    use crate::key_codes::KeyCode::*;
+    const MAP: &[(u32, u32)] = workman_rewrites!();
+    rewrite_layer_from(MAP)
+}
+/// A layer that maps qwerty to colemak on ISO keyboards
+/// (see dvorak_iso for the extra keys).
+pub fn colemak_iso() -> Box<RewriteLayer> {
+    use crate::key_codes::KeyCode::*;
+    const MAP: &[(u32, u32)] = colemak_rewrites!((NonUsBslash.to_u32(), BSlash.to_u32()));
+    rewrite_layer_from(MAP)
 }
 
 /// Enable/disable handler (layer) on activation/deactivation
 /// for use with PressRelease, StickyKeys, OneShot, SpaceCadet
 ///
 /// Can also be used with Modifier::* (pass in mod as HandelerID)
+///
+/// With new_prefer_right, activation by the right modifier key
+/// leads to the right modifier keycode being sent.
 pub struct ActionHandler {
     id: HandlerID,
+    prefer_right: bool,
 }
 impl ActionHandler {
     pub fn new(id: HandlerID) -> ActionHandler {
-        ActionHandler{id}
+        ActionHandler {
+            id,
+            prefer_right: false,
+        }
+    }
+    pub fn new_prefer_right(id: HandlerID) -> ActionHandler {
+        ActionHandler {
+            id,
+            prefer_right: true,
+        }
     }
 }
 impl OnOff for ActionHandler {
@@ -100,6 +250,21 @@ impl OnOff for ActionHandler {
     }
     fn on_deactivate(&mut self, output: &mut dyn USBKeyOut) {
         output.state().disable_handler(self.id);
+        for (modifier, _left, _right) in MODIFIER_KEYS.iter() {
+            if *modifier as HandlerID == self.id {
+                output.state().set_modifier_prefers_right(*modifier, false);
+            }
+        }
+    }
+    fn on_activate_by(&mut self, output: &mut dyn USBKeyOut, trigger: u32) {
+        output.state().enable_handler(self.id);
+        if self.prefer_right {
+            for (modifier, _left, right) in MODIFIER_KEYS.iter() {
+                if *modifier as HandlerID == self.id && right.to_u32() == trigger {
+                    output.state().set_modifier_prefers_right(*modifier, true);
+                }
+            }
+        }
     }
 }
 
@@ -130,16 +295,22 @@ impl OnOff for InverseActionHandler {
 
 
 
-/// make the shift keys behave as a OneShot
-/// 
-/// hint: use before space cadet
-pub fn one_shot_shift(held_timeout: u16, released_timeout: u16) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
+fn one_shot_modifier(
+    modifier: Modifier,
+    held_timeout: u16,
+    released_timeout: u16,
+    prefer_right: bool,
+) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
+    let (_modifier, left, right) = MODIFIER_KEYS[modifier as usize];
+    let callbacks = if prefer_right {
+        ActionHandler::new_prefer_right(modifier as HandlerID)
+    } else {
+        ActionHandler::new(modifier as HandlerID)
+    };
     Box::new(OneShot::new(
-        KeyCode::LShift,
-        KeyCode::RShift,
-        ActionHandler {
-            id: Shift as HandlerID,
-        },
+        left,
+        right,
+        callbacks,
         ActionNone{},
         ActionNone{},
         held_timeout,
@@ -147,53 +318,97 @@ pub fn one_shot_shift(held_timeout: u16, released_timeout: u16) -> Box<OneShot<A
     ))
 }
 
+/// make the shift keys behave as a OneShot
+/// 
+/// hint: use before space cadet
+pub fn one_shot_shift(held_timeout: u16, released_timeout: u16) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
+    one_shot_modifier(Shift, held_timeout, released_timeout, false)
+}
+
 /// make the ctrl keys behave as a OneShot
 /// 
 /// hint: use before space cadet
 pub fn one_shot_ctrl(held_timeout: u16, released_timeout: u16) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
-    Box::new(OneShot::new(
-        KeyCode::LCtrl,
-        KeyCode::RCtrl,
-        ActionHandler {
-            id: Ctrl as HandlerID,
-        },
-        ActionNone{},
-        ActionNone{},
-        held_timeout,
-        released_timeout,
-    ))
+    one_shot_modifier(Ctrl, held_timeout, released_timeout, false)
 }
 /// make the alt keys behave as a OneShot
 /// 
 /// hint: use before space cadet
 pub fn one_shot_alt(held_timeout: u16, released_timeout: u16) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
-    Box::new(OneShot::new(
-        KeyCode::LAlt,
-        KeyCode::RAlt,
-        ActionHandler {
-            id: Alt as HandlerID,
-        },
-        ActionNone{},
-        ActionNone{},
-        held_timeout,
-        released_timeout,
-    ))
+    one_shot_modifier(Alt, held_timeout, released_timeout, false)
 }
 /// make the gui/windows key behave as a OneShot
 /// 
 /// hint: use before space cadet
 pub fn one_shot_gui(held_timeout: u16, released_timeout: u16) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
-    Box::new(OneShot::new(
-        KeyCode::LGui,
-        KeyCode::RGui,
-        ActionHandler {
-            id: Gui as HandlerID,
-        },
-        ActionNone{},
-        ActionNone{},
-        held_timeout,
-        released_timeout,
-    ))
+    one_shot_modifier(Gui, held_timeout, released_timeout, false)
+}
+/// one_shot_shift, but triggering it by RShift sends RShift
+pub fn one_shot_shift_prefer_right(held_timeout: u16, released_timeout: u16) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
+    one_shot_modifier(Shift, held_timeout, released_timeout, true)
+}
+/// one_shot_ctrl, but triggering it by RCtrl sends RCtrl
+pub fn one_shot_ctrl_prefer_right(held_timeout: u16, released_timeout: u16) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
+    one_shot_modifier(Ctrl, held_timeout, released_timeout, true)
+}
+/// one_shot_alt, but triggering it by RAlt sends RAlt (e.g. for AltGr)
+pub fn one_shot_alt_prefer_right(held_timeout: u16, released_timeout: u16) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
+    one_shot_modifier(Alt, held_timeout, released_timeout, true)
+}
+/// one_shot_gui, but triggering it by RGui sends RGui
+pub fn one_shot_gui_prefer_right(held_timeout: u16, released_timeout: u16) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
+    one_shot_modifier(Gui, held_timeout, released_timeout, true)
+}
+/// Enable a handler (layer) exactly while trigger is held
+///
+/// Like space_cadet_handler, this needs to be before the layer it
+/// enables: keyboard.add_handler(momentary_layer(trigger, keyboard.future_handler_id(2)))
+pub fn momentary_layer(
+    trigger: impl AcceptsKeycode,
+    id: HandlerID,
+) -> Box<PressReleaseMacro<ActionHandler>> {
+    Box::new(PressReleaseMacro::new(trigger, ActionHandler::new(id)))
+}
+/// Push a layer onto the layer stack (KeyboardState::push_layer),
+/// see layer_push()
+pub struct ActionLayerPush {
+    id: HandlerID,
+}
+impl Action for ActionLayerPush {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        output.state().push_layer(self.id);
+    }
+}
+impl OnOff for ActionLayerPush {
+    fn on_activate(&mut self, output: &mut dyn USBKeyOut) {
+        output.state().push_layer(self.id);
+    }
+    fn on_deactivate(&mut self, output: &mut dyn USBKeyOut) {
+        output.state().remove_layer(self.id);
+    }
+}
+/// push id on the layer stack - as an Action, or, as OnOff
+/// (e.g. with PressReleaseMacro), just while the trigger is held
+pub fn layer_push(id: HandlerID) -> ActionLayerPush {
+    ActionLayerPush { id }
+}
+
+/// Pop the top layer off the layer stack (KeyboardState::pop_layer),
+/// see layer_pop()
+pub struct ActionLayerPop;
+impl Action for ActionLayerPop {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        output.state().pop_layer();
+    }
+}
+impl OnOff for ActionLayerPop {
+    fn on_activate(&mut self, output: &mut dyn USBKeyOut) {
+        output.state().pop_layer();
+    }
+    fn on_deactivate(&mut self, _output: &mut dyn USBKeyOut) {}
+}
+pub fn layer_pop() -> ActionLayerPop {
+    ActionLayerPop
 }
 /// Toggle a handler (layer) based on OneShot behaviour
 pub fn one_shot_handler(
@@ -205,7 +420,7 @@ pub fn one_shot_handler(
     Box::new(OneShot::new(
         trigger,
         KeyCode::No,
-        ActionHandler { id },
+        ActionHandler::new(id),
         ActionNone{},
         ActionNone{},
         held_timeout,
@@ -213,6 +428,31 @@ pub fn one_shot_handler(
     ))
 }
 
+/// A OneShot layer that locks on a double tap:
+/// a single tap enables the layer for the next key,
+/// two taps within timeout_ms keep it on until the next tap.
+///
+/// Holding the trigger for longer than timeout_ms
+/// makes it a momentary layer.
+pub fn one_shot_or_lock_layer(
+    trigger: impl AcceptsKeycode,
+    layer_id: HandlerID,
+    timeout_ms: u16,
+) -> Box<OneShot<ActionHandler, ActionNone, ActionNone>> {
+    Box::new(
+        OneShot::new(
+            trigger,
+            KeyCode::No,
+            ActionHandler::new(layer_id),
+            ActionNone {},
+            ActionNone {},
+            timeout_ms,
+            0,
+        )
+        .with_lock(),
+    )
+}
+
 /// A space cadet (pass through on tap,
 /// on/off on pressed+other keys)
 /// that turns a handler on/off.
@@ -229,7 +469,65 @@ pub fn space_cadet_handler(
     action: KeyCode,
     id: HandlerID,
 ) -> Box<SpaceCadet<KeyCode, ActionHandler>> {
-    Box::new(SpaceCadet::new(trigger, action, ActionHandler { id }))
+    Box::new(SpaceCadet::new(trigger, action, ActionHandler::new(id)))
+}
+/// space_cadet_handler with a custom minimum_depress_ms
+/// (the default is 100)
+pub fn space_cadet_handler_timed(
+    trigger: impl AcceptsKeycode,
+    action: KeyCode,
+    id: HandlerID,
+    minimum_depress_ms: u16,
+) -> Box<SpaceCadet<KeyCode, ActionHandler>> {
+    Box::new(
+        SpaceCadet::new(trigger, action, ActionHandler::new(id))
+            .with_minimum_depress(minimum_depress_ms),
+    )
+}
+/// CapsLock sends Escape when tapped and is Ctrl when held
+/// (while another key is pressed), see SpaceCadet
+pub fn ctrl_escape() -> Box<SpaceCadet<KeyCode, ActionHandler>> {
+    space_cadet_handler(KeyCode::CapsLock, KeyCode::Escape, Ctrl as HandlerID)
+}
+/// Hyper: Ctrl+Shift+Alt+Gui while trigger is held
+pub fn hyper(trigger: impl AcceptsKeycode) -> Box<MultiModifier> {
+    Box::new(MultiModifier::new(trigger, &[Ctrl, Shift, Alt, Gui]))
+}
+/// Meh: Ctrl+Shift+Alt while trigger is held
+pub fn meh(trigger: impl AcceptsKeycode) -> Box<MultiModifier> {
+    Box::new(MultiModifier::new(trigger, &[Ctrl, Shift, Alt]))
+}
+/// TapDanceAction that sends one of keys depending on the tap count,
+/// used by tap_dance_keys()
+pub struct TapDanceKeys {
+    keys: &'static [KeyCode],
+}
+impl TapDanceAction for TapDanceKeys {
+    fn on_tapdance(
+        &mut self,
+        _trigger: u32,
+        output: &mut impl USBKeyOut,
+        tap_count: u8,
+        _tap_end: TapDanceEnd,
+    ) {
+        if self.keys.is_empty() {
+            return;
+        }
+        let index = (tap_count.max(1) as usize - 1).min(self.keys.len() - 1);
+        output.send_keys(&[self.keys[index]]);
+        output.send_empty();
+    }
+}
+
+/// A tap dance that sends keys[tap_count - 1] (or the last key
+/// for more taps), e.g. '.', ',' and ';' on one key.
+///
+/// Uses the KeyboardState::tapping_term_ms
+pub fn tap_dance_keys(
+    trigger: impl AcceptsKeycode,
+    keys: &'static [KeyCode],
+) -> Box<TapDance<TapDanceKeys>> {
+    Box::new(TapDance::new_global_timeout(trigger, TapDanceKeys { keys }))
 }
 /// Handler for turing Copy/Paste/Cut Keycodes into 'universal'
 /// Ctrl-Insert, Shift-insert, shift-delete keystrokes
@@ -243,18 +541,15 @@ impl<T: USBKeyOut> ProcessKeys<T> for CopyPaste {
             match e {
                 Event::KeyPress(kc) => {
                     if kc.keycode == KeyCode::Copy.into() {
-                        output.send_keys(&[KeyCode::LCtrl, KeyCode::Insert]);
-                        output.send_empty();
+                        output.tap_keys(&[KeyCode::LCtrl, KeyCode::Insert]);
                         *status = EventStatus::Handled;
                     }
                     if kc.keycode == KeyCode::Paste.into() {
-                        output.send_keys(&[KeyCode::LShift, KeyCode::Insert]);
-                        output.send_empty();
+                        output.tap_keys(&[KeyCode::LShift, KeyCode::Insert]);
                         *status = EventStatus::Handled;
                     }
                     if kc.keycode == KeyCode::Cut.into() {
-                        output.send_keys(&[KeyCode::LShift, KeyCode::Delete]);
-                        output.send_empty();
+                        output.tap_keys(&[KeyCode::LShift, KeyCode::Delete]);
                         *status = EventStatus::Handled;
                     }
                 }
@@ -276,6 +571,459 @@ impl<T: USBKeyOut> ProcessKeys<T> for CopyPaste {
     }
 }
 
+/// A layer that's on for exactly one key (a one shot layer),
+/// see sticky_layer()
+pub struct StickyLayer {
+    trigger: u32,
+    layer: HandlerID,
+    armed: bool,
+    consumed: Option<u8>, // running_number of the press the layer got to rewrite
+}
+impl StickyLayer {
+    fn is_modifier(keycode: u32) -> bool {
+        match KeyCode::try_from(keycode) {
+            Ok(keycode) => keycode.is_modifier(),
+            Err(_) => false,
+        }
+    }
+}
+impl<T: USBKeyOut> ProcessKeys<T> for StickyLayer {
+    fn describe(&self) -> String {
+        format!(
+            "StickyLayer(trigger: {}, layer: {})",
+            crate::key_codes::describe_keycode(self.trigger),
+            self.layer
+        )
+    }
+    fn on_disabled(&mut self, output: &mut T) {
+        if self.armed || self.consumed.is_some() {
+            output.state().disable_handler(self.layer);
+        }
+        self.armed = false;
+        self.consumed = None;
+    }
+    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
+        //the layer has had it's pass at the press - it's release
+        //is rewritten by the (now disabled) layer as well
+        if self.consumed.take().is_some() {
+            output.state().disable_handler(self.layer);
+        }
+        for (e, status) in iter_unhandled_mut(events) {
+            match e {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        self.armed = true;
+                        output.state().enable_handler(self.layer);
+                    } else if self.armed
+                        && (kc.flag & 1) == 0
+                        && !StickyLayer::is_modifier(kc.keycode)
+                    {
+                        self.armed = false;
+                        self.consumed = Some(kc.running_number);
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+        HandlerResult::NoOp
+    }
+}
+/// Tap trigger to enable layer_id for the next non-modifier key.
+///
+/// Must be added before the layer (use keyboard.future_handler_id(2)),
+/// so the layer gets to rewrite the key - it's disabled again
+/// on the following handle_keys.
+pub fn sticky_layer(trigger: impl AcceptsKeycode, layer_id: HandlerID) -> Box<StickyLayer> {
+    Box::new(StickyLayer {
+        trigger: trigger.to_u32(),
+        layer: layer_id,
+        armed: false,
+        consumed: None,
+    })
+}
+
+/// Enable a third layer while two others are on, see tri_layer()
+pub struct TriLayer {
+    lower: HandlerID,
+    raise: HandlerID,
+    adjust: HandlerID,
+}
+impl<T: USBKeyOut> ProcessKeys<T> for TriLayer {
+    fn describe(&self) -> String {
+        format!(
+            "TriLayer(lower: {}, raise: {}, adjust: {})",
+            self.lower, self.raise, self.adjust
+        )
+    }
+    fn process_keys(&mut self, _events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
+        let state = output.state();
+        let both = state.is_handler_enabled(self.lower) && state.is_handler_enabled(self.raise);
+        if both != state.is_handler_enabled(self.adjust) {
+            state.set_handler(self.adjust, both);
+        }
+        HandlerResult::NoOp
+    }
+}
+/// Enable adjust exactly while both lower and raise are enabled (QMK's tri layer).
+///
+/// Add it after the handlers switching lower and raise (e.g. momentary_layer),
+/// and before the adjust layer, so the change applies within the same handle_keys.
+pub fn tri_layer(lower: HandlerID, raise: HandlerID, adjust: HandlerID) -> Box<TriLayer> {
+    Box::new(TriLayer {
+        lower,
+        raise,
+        adjust,
+    })
+}
+
+/// Toggle passthrough mode, see passthrough_toggle()
+pub struct PassthroughToggle {
+    trigger: u32,
+}
+impl<T: USBKeyOut> ProcessKeys<T> for PassthroughToggle {
+    fn describe(&self) -> String {
+        format!(
+            "PassthroughToggle(trigger: {})",
+            crate::key_codes::describe_keycode(self.trigger)
+        )
+    }
+    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
+        for (e, status) in iter_unhandled_mut(events) {
+            match e {
+                Event::KeyPress(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                        let state = output.state();
+                        state.set_passthrough(!state.passthrough());
+                    }
+                }
+                Event::KeyRelease(kc) => {
+                    if kc.keycode == self.trigger {
+                        *status = EventStatus::Handled;
+                    }
+                }
+                Event::TimeOut(_) => {}
+            }
+        }
+        HandlerResult::NoOp
+    }
+    fn runs_in_passthrough(&self) -> bool {
+        true
+    }
+}
+/// Tap trigger to switch passthrough mode (KeyboardState::set_passthrough)
+/// on or off - only the USBKeyboard (and this) handle keys while it's on.
+///
+/// Add it first, so the trigger isn't rewritten by a layer.
+pub fn passthrough_toggle(trigger: impl AcceptsKeycode) -> Box<PassthroughToggle> {
+    Box::new(PassthroughToggle {
+        trigger: trigger.to_u32(),
+    })
+}
+
+/// Send a number of backspaces,
+/// e.g. to delete a trigger that has just been typed
+pub struct Backspaces(pub u8);
+impl Action for Backspaces {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        for _ in 0..self.0 {
+            output.send_keys(&[KeyCode::BSpace]);
+            output.send_empty();
+        }
+    }
+}
+
+/// One step of an ActionScript
+pub enum ScriptStep {
+    /// type a string (via send_string)
+    String(&'static str),
+    /// tap a key combination - keys, then an empty report
+    Keys(&'static [KeyCode]),
+    /// wait ms before the following steps
+    Delay(u16),
+}
+
+/// Play back a fixed sequence of strings and key taps,
+/// with optional delays in between.
+///
+/// Steps before the first Delay are send right away, everything after
+/// it goes through send_keys_later, so your do_send_later has to be called regularly.
+pub struct ActionScript {
+    pub steps: Vec<ScriptStep>,
+}
+impl ActionScript {
+    pub fn new(steps: Vec<ScriptStep>) -> ActionScript {
+        ActionScript { steps }
+    }
+}
+impl Action for ActionScript {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        let mut delay: Option<u16> = None;
+        for step in self.steps.iter() {
+            match (step, delay) {
+                (ScriptStep::Delay(ms), _) => {
+                    delay = Some(delay.unwrap_or(0).saturating_add(*ms));
+                }
+                (ScriptStep::String(s), None) => output.send_string(s),
+                (ScriptStep::Keys(keys), None) => output.tap_keys(keys),
+                (ScriptStep::String(s), Some(ms)) => {
+                    DelayedOutput::new(output, ms).send_string(s);
+                    delay = Some(0);
+                }
+                (ScriptStep::Keys(keys), Some(ms)) => {
+                    DelayedOutput::new(output, ms).tap_keys(keys);
+                    delay = Some(0);
+                }
+            }
+        }
+    }
+}
+
+/// Type unicode codepoints (via send_unicode),
+/// e.g. from generated tables, without building a string.
+///
+/// Invalid codepoints are send as U+FFFD (replacement character).
+pub struct ActionCodepoints(pub &'static [u32]);
+impl Action for ActionCodepoints {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        for cp in self.0.iter() {
+            output.send_unicode(core::char::from_u32(*cp).unwrap_or('\u{FFFD}'));
+        }
+    }
+}
+
+/// Type a secret (e.g. a password) once, via send_unicode,
+/// then overwrite the buffer holding it with zeros.
+///
+/// A &'static str can't be wiped, so this keeps its own copy -
+/// and types nothing on further triggers.
+/// The buffer is also wiped when dropped.
+pub struct ActionSecureString {
+    chars: Vec<char>,
+}
+impl ActionSecureString {
+    pub fn new(secret: &str) -> ActionSecureString {
+        ActionSecureString {
+            chars: secret.chars().collect(),
+        }
+    }
+    fn wipe(&mut self) {
+        for c in self.chars.iter_mut() {
+            //volatile, so the compiler can't drop the 'dead' writes
+            unsafe { core::ptr::write_volatile(c, '\0') };
+        }
+        self.chars.clear();
+    }
+    #[cfg(test)]
+    fn buffer(&self) -> &[char] {
+        &self.chars
+    }
+}
+impl Action for ActionSecureString {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        for c in self.chars.iter() {
+            output.send_unicode(*c);
+        }
+        self.wipe();
+    }
+}
+impl Drop for ActionSecureString {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+/// Send a fixed key combination (e.g. Ctrl+Shift+T) as one
+/// report, followed by an empty one.
+///
+/// As OnOff (for PressReleaseMacro), the combo is held
+/// until deactivation.
+pub struct ActionKeyCombo {
+    pub combo: Vec<KeyCode>,
+}
+impl Action for ActionKeyCombo {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        output.send_keys(&self.combo);
+        output.send_empty();
+    }
+}
+impl OnOff for ActionKeyCombo {
+    fn on_activate(&mut self, output: &mut dyn USBKeyOut) {
+        output.send_keys(&self.combo);
+    }
+    fn on_deactivate(&mut self, output: &mut dyn USBKeyOut) {
+        output.send_empty();
+    }
+}
+
+/// Send a key combination (e.g. Ctrl+Shift+T) when trigger is pressed
+pub fn key_combo(trigger: impl AcceptsKeycode, combo: &[KeyCode]) -> Box<PressMacro<ActionKeyCombo>> {
+    Box::new(PressMacro::new(
+        trigger,
+        ActionKeyCombo {
+            combo: combo.to_vec(),
+        },
+    ))
+}
+
+/// Toggle a modifier in the KeyboardState,
+/// see modifier_lock()
+pub struct ActionModifierLock {
+    pub modifier: Modifier,
+}
+impl Action for ActionModifierLock {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        let state = output.state();
+        let value = !state.modifier(self.modifier);
+        state.set_modifier(self.modifier, value);
+    }
+}
+
+/// Lock a modifier (like CapsLock, but for e.g. Ctrl) -
+/// the first press of trigger sets it, the next one clears it.
+///
+/// USBKeyboard adds it to every report while it's set.
+/// Note that releasing the physical modifier key also clears it.
+pub fn modifier_lock(
+    trigger: impl AcceptsKeycode,
+    modifier: Modifier,
+) -> Box<PressMacro<ActionModifierLock>> {
+    Box::new(PressMacro::new(trigger, ActionModifierLock { modifier }))
+}
+
+/// Type the current time as "HH:MM:SS" (via send_string).
+///
+/// The crate has no clock - clock is called on every trigger
+/// and returns (hours, minutes, seconds), e.g. from an RTC.
+pub struct ActionClock<F> {
+    pub clock: F,
+}
+impl<F: FnMut() -> (u8, u8, u8)> ActionClock<F> {
+    pub fn new(clock: F) -> ActionClock<F> {
+        ActionClock { clock }
+    }
+}
+impl<F: FnMut() -> (u8, u8, u8) + Send + Sync> Action for ActionClock<F> {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        let (hours, minutes, seconds) = (self.clock)();
+        output.send_string(&format!("{:02}:{:02}:{:02}", hours, minutes, seconds));
+    }
+}
+
+/// Switch to the next of a list of layers ("layer next" key).
+///
+/// The currently enabled layer of the list is disabled and its
+/// successor enabled, wrapping around. If none of them is enabled,
+/// layers[current] (the last one switched to) is enabled.
+pub struct ActionLayerCycle {
+    pub layers: Vec<HandlerID>,
+    pub current: usize,
+}
+impl ActionLayerCycle {
+    pub fn new(layers: &[HandlerID]) -> ActionLayerCycle {
+        if layers.is_empty() {
+            panic!("ActionLayerCycle needs at least one layer");
+        }
+        ActionLayerCycle {
+            layers: layers.to_vec(),
+            current: 0,
+        }
+    }
+}
+impl Action for ActionLayerCycle {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        let state = output.state();
+        match self
+            .layers
+            .iter()
+            .position(|id| state.is_handler_enabled(*id))
+        {
+            Some(ii) => {
+                state.disable_handler(self.layers[ii]);
+                self.current = (ii + 1) % self.layers.len();
+            }
+            None => self.current %= self.layers.len(),
+        }
+        state.enable_handler(self.layers[self.current]);
+    }
+}
+
+/// Switch KeyboardState::unicode_mode to the next of a list of modes.
+///
+/// If the current mode is not in the list, the first one is used.
+pub struct ActionUnicodeModeCycle {
+    pub modes: &'static [UnicodeSendMode],
+}
+impl Action for ActionUnicodeModeCycle {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        let state = output.state();
+        let next = match self.modes.iter().position(|m| *m == state.unicode_mode) {
+            Some(ii) => (ii + 1) % self.modes.len(),
+            None => 0,
+        };
+        state.unicode_mode = self.modes[next];
+    }
+}
+
+/// Cycle through unicode send modes on each press of trigger,
+/// since we can't detect what the keyboard is connected to.
+pub fn unicode_mode_cycle(
+    trigger: impl AcceptsKeycode,
+    modes: &'static [UnicodeSendMode],
+) -> Box<PressMacro<ActionUnicodeModeCycle>> {
+    if modes.is_empty() {
+        panic!("unicode_mode_cycle needs at least one mode");
+    }
+    Box::new(PressMacro::new(trigger, ActionUnicodeModeCycle { modes }))
+}
+
+/// Set KeyboardState::unicode_mode
+pub struct ActionSetUnicodeMode {
+    pub mode: UnicodeSendMode,
+}
+impl Action for ActionSetUnicodeMode {
+    fn on_trigger(&mut self, output: &mut dyn USBKeyOut) {
+        output.state().unicode_mode = self.mode;
+    }
+}
+
+/// Switch to a specific unicode send mode when trigger is pressed
+pub fn set_unicode_mode(
+    trigger: impl AcceptsKeycode,
+    mode: UnicodeSendMode,
+) -> Box<PressMacro<ActionSetUnicodeMode>> {
+    Box::new(PressMacro::new(trigger, ActionSetUnicodeMode { mode }))
+}
+
+/// Run a closure as a handler - for quick one-off logic
+/// that does not warrant it's own struct.
+///
+/// The closure get's the full event stream, see ProcessKeys
+pub struct FnHandler<F> {
+    pub callback: F,
+}
+impl<F> FnHandler<F>
+where
+    F: FnMut(&mut Vec<(Event, EventStatus)>, &mut dyn USBKeyOut) -> HandlerResult,
+{
+    pub fn new(callback: F) -> FnHandler<F> {
+        FnHandler { callback }
+    }
+}
+impl<T: USBKeyOut, F> ProcessKeys<T> for FnHandler<F>
+where
+    F: FnMut(&mut Vec<(Event, EventStatus)>, &mut dyn USBKeyOut) -> HandlerResult,
+{
+    fn process_keys(&mut self, events: &mut Vec<(Event, EventStatus)>, output: &mut T) -> HandlerResult {
+        (self.callback)(events, output)
+    }
+}
 
 /// Abort all event handling, throw away remaining events,
 /// unset all modifiers and enable/disable handers as requested
@@ -341,6 +1089,541 @@ mod tests {
     };
     use no_std_compat::prelude::v1::*;
 
+    #[test]
+    fn test_backspaces() {
+        use crate::handlers::Action;
+        use crate::premade::Backspaces;
+        let mut output = KeyOutCatcher::new();
+        Backspaces(3).on_trigger(&mut output);
+        let bs = vec![KeyCode::BSpace.to_u8()];
+        assert!(output.reports == vec![bs.clone(), vec![], bs.clone(), vec![], bs, vec![]]);
+    }
+
+    #[test]
+    fn test_action_clock() {
+        use crate::handlers::Action;
+        use crate::premade::ActionClock;
+        use KeyCode::*;
+        let mut output = KeyOutCatcher::new();
+        output.state().unicode_mode = UnicodeSendMode::Debug;
+        ActionClock::new(|| (13, 5, 9)).on_trigger(&mut output);
+        //Debug mode types the hex code points: 31 33 3a 30 35 3a 30 39
+        let expected: Vec<Vec<u8>> = [
+            Kb3, Kb1, Kb3, Kb3, Kb3, A, Kb3, Kb0, Kb3, Kb5, Kb3, A, Kb3, Kb0, Kb3, Kb9,
+        ]
+        .iter()
+        .map(|k| vec![k.to_u8()])
+        .collect();
+        assert!(output.reports == expected);
+    }
+
+    #[test]
+    fn test_hyper_meh() {
+        use crate::key_codes::UserKey;
+        use crate::premade::{hyper, meh};
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(hyper(UserKey::UK0));
+        keyboard.add_handler(meh(UserKey::UK1));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[LCtrl, LShift, LAlt, LGui]]);
+        keyboard.pc(X, &[&[LCtrl, LShift, LAlt, LGui, X]]);
+        keyboard.rc(X, &[&[LCtrl, LShift, LAlt, LGui]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+
+        keyboard.pc(UserKey::UK1, &[&[LCtrl, LShift, LAlt]]);
+        keyboard.pc(X, &[&[LCtrl, LShift, LAlt, X]]);
+        keyboard.rc(X, &[&[LCtrl, LShift, LAlt]]);
+        keyboard.rc(UserKey::UK1, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_one_shot_or_lock_layer() {
+        use crate::handlers::RewriteLayer;
+        use crate::key_codes::UserKey;
+        use crate::premade::one_shot_or_lock_layer;
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        const MAP: &[(u32, u32)] = &[(KeyCode::A.to_u32(), KeyCode::X.to_u32())];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.future_handler_id(2);
+        keyboard.add_handler(one_shot_or_lock_layer(UserKey::UK0, layer_id, 200));
+        keyboard.add_handler(Box::new(RewriteLayer::new(MAP)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+
+        //single tap - for one key only
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(layer_id));
+        keyboard.pc(A, &[&[X]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+
+        //double tap - locked
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        keyboard.pct(UserKey::UK0, 100, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        for _ in 0..3 {
+            keyboard.pc(A, &[&[X]]);
+            keyboard.rc(A, &[&[]]);
+        }
+        assert!(keyboard.output.state().is_handler_enabled(layer_id));
+        //and unlocked by the next tap
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_unicode_mode_cycle() {
+        use crate::key_codes::UserKey;
+        use crate::premade::{set_unicode_mode, unicode_mode_cycle};
+        use crate::test_helpers::Checks;
+        const MODES: &[UnicodeSendMode] = &[
+            UnicodeSendMode::Linux,
+            UnicodeSendMode::WinCompose,
+            UnicodeSendMode::Debug,
+        ];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(unicode_mode_cycle(UserKey::UK0, MODES));
+        keyboard.add_handler(set_unicode_mode(UserKey::UK1, UnicodeSendMode::Linux));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.output.state().unicode_mode = UnicodeSendMode::Linux;
+        for expected in [
+            UnicodeSendMode::WinCompose,
+            UnicodeSendMode::Debug,
+            UnicodeSendMode::Linux,
+        ]
+        .iter()
+        {
+            keyboard.pc(UserKey::UK0, &[&[]]);
+            keyboard.rc(UserKey::UK0, &[&[]]);
+            assert!(keyboard.output.state().unicode_mode == *expected);
+        }
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(keyboard.output.state().unicode_mode == UnicodeSendMode::WinCompose);
+        keyboard.pc(UserKey::UK1, &[&[]]);
+        keyboard.rc(UserKey::UK1, &[&[]]);
+        assert!(keyboard.output.state().unicode_mode == UnicodeSendMode::Linux);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_action_layer_cycle() {
+        use crate::handlers::{Action, RewriteLayer};
+        use crate::premade::ActionLayerCycle;
+        const MAP: &[(u32, u32)] = &[];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let ids: Vec<usize> = (0..3)
+            .map(|_| keyboard.add_handler(Box::new(RewriteLayer::new(MAP))))
+            .collect();
+        let mut cycle = ActionLayerCycle::new(&ids);
+        keyboard.output.state().enable_handler(ids[0]);
+        let enabled = |keyboard: &mut Keyboard<KeyOutCatcher>| -> Vec<bool> {
+            ids.iter()
+                .map(|id| keyboard.output.state().is_handler_enabled(*id))
+                .collect()
+        };
+        for expected in &[1, 2, 0, 1] {
+            cycle.on_trigger(&mut keyboard.output);
+            let mut should = vec![false; 3];
+            should[*expected] = true;
+            assert!(enabled(&mut keyboard) == should);
+        }
+        //nothing enabled - resume with the last one
+        keyboard.output.state().disable_handler(ids[1]);
+        cycle.on_trigger(&mut keyboard.output);
+        assert!(enabled(&mut keyboard) == vec![false, true, false]);
+    }
+
+    #[test]
+    fn test_sticky_layer() {
+        use crate::handlers::RewriteLayer;
+        use crate::key_codes::UserKey;
+        use crate::premade::sticky_layer;
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        const MAP: &[(u32, u32)] = &[(A.to_u32(), X.to_u32())];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.future_handler_id(2);
+        keyboard.add_handler(sticky_layer(UserKey::UK0, layer_id));
+        keyboard.add_handler(Box::new(RewriteLayer::new(MAP)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(layer_id));
+        //modifiers don't count
+        keyboard.pc(LShift, &[&[LShift]]);
+        keyboard.pc(A, &[&[LShift, X]]);
+        keyboard.rc(A, &[&[LShift]]);
+        assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+        keyboard.rc(LShift, &[&[]]);
+        //only the one key
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_momentary_layer() {
+        use crate::handlers::RewriteLayer;
+        use crate::key_codes::UserKey;
+        use crate::premade::momentary_layer;
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        const MAP: &[(u32, u32)] = &[(A.to_u32(), X.to_u32())];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let layer_id = keyboard.future_handler_id(2);
+        keyboard.add_handler(momentary_layer(UserKey::UK0, layer_id));
+        keyboard.add_handler(Box::new(RewriteLayer::new(MAP)));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(layer_id));
+        keyboard.pc(A, &[&[X]]);
+        keyboard.rc(A, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(layer_id));
+        keyboard.pc(A, &[&[A]]);
+        keyboard.rc(A, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_tri_layer() {
+        use crate::handlers::RewriteLayer;
+        use crate::key_codes::UserKey;
+        use crate::premade::{momentary_layer, tri_layer};
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        const LOWER: &[(u32, u32)] = &[(A.to_u32(), X.to_u32())];
+        const RAISE: &[(u32, u32)] = &[(B.to_u32(), Y.to_u32())];
+        const ADJUST: &[(u32, u32)] = &[(C.to_u32(), Z.to_u32())];
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let lower = keyboard.future_handler_id(4);
+        let raise = keyboard.future_handler_id(5);
+        let adjust = keyboard.future_handler_id(6);
+        keyboard.add_handler(momentary_layer(UserKey::UK0, lower));
+        keyboard.add_handler(momentary_layer(UserKey::UK1, raise));
+        keyboard.add_handler(tri_layer(lower, raise, adjust));
+        assert!(keyboard.add_handler(Box::new(RewriteLayer::new(LOWER))) == lower);
+        assert!(keyboard.add_handler(Box::new(RewriteLayer::new(RAISE))) == raise);
+        assert!(keyboard.add_handler(Box::new(RewriteLayer::new(ADJUST))) == adjust);
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(adjust));
+        keyboard.pc(C, &[&[C]]);
+        keyboard.rc(C, &[&[]]);
+        keyboard.pc(UserKey::UK1, &[&[]]);
+        assert!(keyboard.output.state().is_handler_enabled(adjust));
+        keyboard.pc(C, &[&[Z]]);
+        keyboard.rc(C, &[&[]]);
+        keyboard.pc(A, &[&[X]]);
+        keyboard.rc(A, &[&[]]);
+
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().is_handler_enabled(adjust));
+        keyboard.pc(C, &[&[C]]);
+        keyboard.rc(C, &[&[]]);
+        keyboard.pc(B, &[&[Y]]);
+        keyboard.rc(B, &[&[]]);
+        keyboard.rc(UserKey::UK1, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_action_script() {
+        use crate::handlers::Action;
+        use crate::premade::{ActionScript, ScriptStep};
+        use KeyCode::*;
+        let mut output = KeyOutCatcher::new();
+        output.state().unicode_mode = UnicodeSendMode::Debug;
+        let mut script = ActionScript::new(vec![
+            ScriptStep::String("a"),
+            ScriptStep::Keys(&[LCtrl, Tab]),
+            ScriptStep::Delay(100),
+            ScriptStep::Delay(50),
+            ScriptStep::String("b"),
+            ScriptStep::Keys(&[Enter]),
+        ]);
+        script.on_trigger(&mut output);
+        let to_reports = |reports: &[&[KeyCode]]| -> Vec<Vec<u8>> {
+            reports
+                .iter()
+                .map(|r| r.iter().map(|k| k.to_u8()).collect())
+                .collect()
+        };
+        assert!(output.reports == to_reports(&[&[Kb6], &[Kb1], &[LCtrl, Tab], &[]]));
+        //the rest is scheduled, not send
+        assert!(
+            output.later
+                == vec![
+                    (150, vec![Kb6]),
+                    (0, vec![Kb2]),
+                    (0, vec![Enter]),
+                    (0, vec![])
+                ]
+        );
+        output.do_send_later();
+        assert!(
+            output.reports
+                == to_reports(&[
+                    &[Kb6],
+                    &[Kb1],
+                    &[LCtrl, Tab],
+                    &[],
+                    &[Kb6],
+                    &[Kb2],
+                    &[Enter],
+                    &[]
+                ])
+        );
+    }
+
+    #[test]
+    fn test_modifier_lock() {
+        use crate::key_codes::UserKey;
+        use crate::premade::modifier_lock;
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(modifier_lock(UserKey::UK0, Ctrl));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[LCtrl]]);
+        keyboard.rc(UserKey::UK0, &[&[LCtrl]]);
+        assert!(keyboard.output.state().modifier(Ctrl));
+        keyboard.pc(C, &[&[C, LCtrl]]);
+        keyboard.rc(C, &[&[LCtrl]]);
+        keyboard.pc(X, &[&[X, LCtrl]]);
+        keyboard.rc(X, &[&[LCtrl]]);
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+        keyboard.pc(C, &[&[C]]);
+        keyboard.rc(C, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_action_codepoints() {
+        use crate::handlers::Action;
+        use crate::premade::ActionCodepoints;
+        use KeyCode::*;
+        let mut output = KeyOutCatcher::new();
+        output.state().unicode_mode = UnicodeSendMode::Debug;
+        //'a', an invalid surrogate
+        ActionCodepoints(&[0x61, 0xD800]).on_trigger(&mut output);
+        let expected: Vec<Vec<u8>> = [Kb6, Kb1, F, F, F, D]
+            .iter()
+            .map(|k| vec![k.to_u8()])
+            .collect();
+        assert!(output.reports == expected);
+    }
+
+    #[test]
+    fn test_secure_string() {
+        use crate::handlers::Action;
+        use crate::premade::ActionSecureString;
+        use KeyCode::*;
+        let mut output = KeyOutCatcher::new();
+        output.state().unicode_mode = UnicodeSendMode::Debug;
+        let mut secret = ActionSecureString::new("ab");
+        assert!(secret.buffer() == ['a', 'b']);
+        secret.on_trigger(&mut output);
+        let expected: Vec<Vec<u8>> = [Kb6, Kb1, Kb6, Kb2]
+            .iter()
+            .map(|k| vec![k.to_u8()])
+            .collect();
+        assert!(output.reports == expected);
+        assert!(secret.buffer().is_empty());
+        //it's gone
+        output.clear();
+        secret.on_trigger(&mut output);
+        assert!(output.reports.is_empty());
+    }
+
+    #[test]
+    fn test_copy_paste() {
+        use crate::premade::CopyPaste;
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(CopyPaste {}));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(Copy, &[&[LCtrl, Insert], &[], &[]]);
+        keyboard.rc(Copy, &[&[]]);
+        keyboard.pc(Paste, &[&[LShift, Insert], &[], &[]]);
+        keyboard.rc(Paste, &[&[]]);
+        keyboard.pc(Cut, &[&[LShift, Delete], &[], &[]]);
+        keyboard.rc(Cut, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_key_combo() {
+        use crate::key_codes::UserKey;
+        use crate::premade::key_combo;
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(key_combo(UserKey::UK0, &[LCtrl, LShift, T]));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(UserKey::UK0, &[&[LCtrl, LShift, T], &[], &[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_fn_handler() {
+        use crate::handlers::HandlerResult;
+        use crate::key_stream::iter_unhandled_mut;
+        use crate::premade::FnHandler;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(FnHandler::new(
+            |events: &mut Vec<(Event, EventStatus)>, _output: &mut dyn USBKeyOut| {
+                for (e, _status) in iter_unhandled_mut(events) {
+                    match e {
+                        Event::KeyPress(kc) | Event::KeyRelease(kc) => {
+                            if kc.keycode == KeyCode::A.to_u32() {
+                                kc.keycode = KeyCode::B.to_u32();
+                            }
+                        }
+                        Event::TimeOut(_) => {}
+                    }
+                }
+                HandlerResult::NoOp
+            },
+        )));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(KeyCode::A, &[&[KeyCode::B]]);
+        keyboard.rc(KeyCode::A, &[&[]]);
+        keyboard.pc(KeyCode::C, &[&[KeyCode::C]]);
+        keyboard.rc(KeyCode::C, &[&[]]);
+    }
+
+    #[test]
+    fn test_passthrough_toggle() {
+        use crate::key_codes::UserKey;
+        use crate::premade::{passthrough_toggle, tap_dance_keys};
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(passthrough_toggle(UserKey::UK0));
+        keyboard.add_handler(tap_dance_keys(X, &[Dot, Comma]));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(X, &[&[]]);
+        keyboard.rc(X, &[&[]]);
+        keyboard.tc(250, &[&[Dot], &[], &[]]);
+
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(keyboard.output.state().passthrough());
+        //typed right away
+        keyboard.pc(X, &[&[X]]);
+        keyboard.rc(X, &[&[]]);
+
+        keyboard.pc(UserKey::UK0, &[&[]]);
+        keyboard.rc(UserKey::UK0, &[&[]]);
+        assert!(!keyboard.output.state().passthrough());
+        keyboard.pc(X, &[&[]]);
+        keyboard.rc(X, &[&[]]);
+        keyboard.tc(250, &[&[Dot], &[], &[]]);
+        assert!(keyboard.events.is_empty());
+    }
+
+    #[test]
+    fn test_tap_dance_keys() {
+        use crate::premade::tap_dance_keys;
+        use crate::test_helpers::Checks;
+        use crate::UserKey;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(tap_dance_keys(
+            UserKey::UK0,
+            &[KeyCode::Dot, KeyCode::Comma, KeyCode::SColon],
+        ));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        for (taps, expected) in [
+            (1, KeyCode::Dot),
+            (2, KeyCode::Comma),
+            (3, KeyCode::SColon),
+            (4, KeyCode::SColon),
+        ]
+        .iter()
+        {
+            for _ in 0..*taps {
+                keyboard.pc(UserKey::UK0, &[&[]]);
+                keyboard.rc(UserKey::UK0, &[&[]]);
+            }
+            keyboard.tc(250, &[&[*expected], &[], &[]]);
+        }
+    }
+
+    #[test]
+    fn test_ctrl_escape() {
+        use crate::premade::ctrl_escape;
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(ctrl_escape());
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //tap
+        keyboard.pc(CapsLock, &[&[]]);
+        keyboard.rc(CapsLock, &[&[Escape]]);
+        keyboard.tc(50, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+
+        //hold
+        keyboard.pc(CapsLock, &[&[]]);
+        keyboard.pct(C, 150, &[&[LCtrl, C]]);
+        keyboard.rc(C, &[&[LCtrl]]);
+        keyboard.rc(CapsLock, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+
+        //rolling - the next key comes before the minimum depress time
+        keyboard.pc(CapsLock, &[&[]]);
+        keyboard.pct(C, 20, &[&[Escape, C]]);
+        keyboard.rc(CapsLock, &[&[C]]);
+        keyboard.rc(C, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+    }
+
+    #[test]
+    fn test_space_cadet_handler_timed() {
+        use crate::premade::space_cadet_handler_timed;
+        use crate::test_helpers::Checks;
+        use KeyCode::*;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(space_cadet_handler_timed(
+            CapsLock,
+            Escape,
+            Ctrl as crate::HandlerID,
+            200,
+        ));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //150ms would be a hold with the default - it's a tap here
+        keyboard.pc(CapsLock, &[&[]]);
+        keyboard.pct(C, 150, &[&[Escape, C]]);
+        keyboard.rc(CapsLock, &[&[C]]);
+        keyboard.rc(C, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+
+        keyboard.pc(CapsLock, &[&[]]);
+        keyboard.pct(C, 200, &[&[LCtrl, C]]);
+        keyboard.rc(C, &[&[LCtrl]]);
+        keyboard.rc(CapsLock, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Ctrl));
+    }
+
     #[test]
     fn test_toggle_handler() {
         let mut keyboard = Keyboard::new(KeyOutCatcher::new());
@@ -359,6 +1642,28 @@ mod tests {
         assert!(keyboard.output.state().is_handler_enabled(id));
     }
 
+    #[test]
+    fn test_toggle_handler_with_action() {
+        use crate::premade::toggle_handler_with_action;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let id = keyboard.add_handler(Box::new(crate::handlers::UnicodeKeyboard {}));
+        keyboard.output.state().disable_handler(id);
+        keyboard.add_handler(toggle_handler_with_action(
+            0xF0100u32,
+            id,
+            KeyCode::F1,
+            KeyCode::F2,
+        ));
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(0xF0100u32, &[&[KeyCode::F1]]);
+        assert!(keyboard.output.state().is_handler_enabled(id));
+        keyboard.rc(0xF0100u32, &[&[]]);
+        keyboard.pc(0xF0100u32, &[&[KeyCode::F2]]);
+        assert!(!keyboard.output.state().is_handler_enabled(id));
+        keyboard.rc(0xF0100u32, &[&[]]);
+    }
+
     #[test]
     fn test_layer_double_rewrite_dvorak() {
         let mut keyboard = Keyboard::new(KeyOutCatcher::new());
@@ -376,6 +1681,68 @@ mod tests {
         check_output(&keyboard, &[&[KeyCode::Quote], &[]]);
     }
     #[test]
+    fn test_dvorak_iso() {
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let dvorak_id = keyboard.add_handler(premade::dvorak_iso());
+        keyboard.output.state().enable_handler(dvorak_id);
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        //the extra key next to LShift types backslash
+        keyboard.pc(KeyCode::NonUsBslash, &[&[KeyCode::BSlash]]);
+        keyboard.rc(KeyCode::NonUsBslash, &[&[]]);
+        //the key next to Enter is already backslash on the host
+        keyboard.pc(KeyCode::NonUsHash, &[&[KeyCode::NonUsHash]]);
+        keyboard.rc(KeyCode::NonUsHash, &[&[]]);
+        keyboard.pc(KeyCode::Q, &[&[KeyCode::Quote]]);
+        keyboard.rc(KeyCode::Q, &[&[]]);
+    }
+    #[test]
+    fn test_colemak_iso() {
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let colemak_id = keyboard.add_handler(premade::colemak_iso());
+        keyboard.output.state().enable_handler(colemak_id);
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(KeyCode::NonUsBslash, &[&[KeyCode::BSlash]]);
+        keyboard.rc(KeyCode::NonUsBslash, &[&[]]);
+        keyboard.pc(KeyCode::NonUsHash, &[&[KeyCode::NonUsHash]]);
+        keyboard.rc(KeyCode::NonUsHash, &[&[]]);
+        keyboard.pc(KeyCode::E, &[&[KeyCode::F]]);
+        keyboard.rc(KeyCode::E, &[&[]]);
+    }
+    #[test]
+    fn test_colemak() {
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let colemak_id = keyboard.add_handler(premade::colemak());
+        keyboard.output.state().enable_handler(colemak_id);
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(KeyCode::E, &[&[KeyCode::F]]);
+        keyboard.rc(KeyCode::E, &[&[]]);
+        keyboard.pc(KeyCode::SColon, &[&[KeyCode::O]]);
+        keyboard.rc(KeyCode::SColon, &[&[]]);
+        keyboard.pc(KeyCode::A, &[&[KeyCode::A]]);
+        keyboard.rc(KeyCode::A, &[&[]]);
+    }
+    #[test]
+    fn test_workman() {
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        let workman_id = keyboard.add_handler(premade::workman());
+        keyboard.output.state().enable_handler(workman_id);
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        keyboard.pc(KeyCode::D, &[&[KeyCode::H]]);
+        keyboard.rc(KeyCode::D, &[&[]]);
+        keyboard.pc(KeyCode::M, &[&[KeyCode::L]]);
+        keyboard.rc(KeyCode::M, &[&[]]);
+        keyboard.pc(KeyCode::Q, &[&[KeyCode::Q]]);
+        keyboard.rc(KeyCode::Q, &[&[]]);
+    }
+    #[test]
     fn test_dvorak_brackets() {
         use crate::handlers;
         //use crate::debug_handlers;
@@ -449,6 +1816,26 @@ mod tests {
         keyboard.output.clear();
     }
     #[test]
+    fn test_oneshot_alt_prefer_right() {
+        use crate::handlers;
+        use crate::premade;
+        use crate::test_helpers::Checks;
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(premade::one_shot_alt_prefer_right(0, 0));
+        keyboard.add_handler(Box::new(handlers::USBKeyboard::new()));
+        keyboard.pc(KeyCode::RAlt, &[&[KeyCode::RAlt]]);
+        keyboard.rc(KeyCode::RAlt, &[&[KeyCode::RAlt]]);
+        keyboard.pc(KeyCode::E, &[&[KeyCode::RAlt, KeyCode::E]]);
+        keyboard.rc(KeyCode::E, &[&[]]);
+        assert!(!keyboard.output.state().modifier(Alt));
+        assert!(!keyboard.output.state().modifier_prefers_right(Alt));
+        //the left one still sends LAlt
+        keyboard.pc(KeyCode::LAlt, &[&[KeyCode::LAlt]]);
+        keyboard.rc(KeyCode::LAlt, &[&[KeyCode::LAlt]]);
+        keyboard.pc(KeyCode::E, &[&[KeyCode::LAlt, KeyCode::E]]);
+        keyboard.rc(KeyCode::E, &[&[]]);
+    }
+    #[test]
     fn test_oneshot_interaction() {
         use crate::handlers;
         //use crate::debug_handlers;
@@ -591,7 +1978,7 @@ mod tests {
         use crate::Modifier::Shift;
         let mut keyboard = Keyboard::new(KeyOutCatcher::new());
         let mut aa = premade::ActionAbort::new();
-        let should_enable = keyboard.add_handler(premade::one_shot_alt(0,0));
+        let should_enable = keyboard.add_handler(premade::one_shot_alt(0, 0));
         const MAP: &[(u32, u32)] = &[(KeyCode::A.to_u32(), KeyCode::X.to_u32())];
         let should_disable = keyboard.add_handler(Box::new(RewriteLayer::new(&MAP)));
         keyboard.output.state().enable_handler(should_disable);
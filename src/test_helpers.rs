@@ -1,8 +1,8 @@
 use crate::handlers::{HandlerResult, OnOff, ProcessKeys};
 #[allow(unused_imports)]
 use crate::key_codes::{AcceptsKeycode, KeyCode};
-#[allow(unused_imports)]
-use crate::Keyboard;
+use crate::{InputEvent, Keyboard};
+use core::convert::TryFrom;
 use crate::{iter_unhandled_mut, Event, EventStatus, KeyboardState, USBKeyOut};
 use alloc::sync::Arc;
 use no_std_compat::prelude::v1::*;
@@ -12,7 +12,11 @@ pub struct KeyOutCatcher {
     keys_registered: Vec<u8>,
     pub reports: Vec<Vec<u8>>,
     state: KeyboardState,
-    later: Vec<(u32, Vec<KeyCode>)>,
+    pub later: Vec<(u32, Vec<KeyCode>)>, // ms, keys - flushed into reports by do_send_later
+    pub mouse_reports: Vec<(u8, i8, i8, i8)>,
+    pub consumer_reports: Vec<u16>,
+    pub debug_messages: Vec<String>,
+    pub bootloader_called: bool, // not reset by clear()
 }
 impl KeyOutCatcher {
     pub fn new() -> KeyOutCatcher {
@@ -21,12 +25,20 @@ impl KeyOutCatcher {
             reports: Vec::new(),
             state: KeyboardState::new(),
             later: Vec::new(),
+            mouse_reports: Vec::new(),
+            consumer_reports: Vec::new(),
+            debug_messages: Vec::new(),
+            bootloader_called: false,
         }
     }
     // for testing, clear the catcher of everything
     pub fn clear(&mut self) {
         self.keys_registered.clear();
         self.reports.clear();
+        self.later.clear();
+        self.mouse_reports.clear();
+        self.consumer_reports.clear();
+        self.debug_messages.clear();
     }
 }
 impl USBKeyOut for KeyOutCatcher {
@@ -38,13 +50,15 @@ impl USBKeyOut for KeyOutCatcher {
         return &self.state;
     }
 
-    #[allow(unused_variables)]
     fn debug(&mut self, s: &str) {
         #[cfg(test)]
         println!("{}", s);
+        self.debug_messages.push(s.to_string());
     }
 
-    fn bootloader(&mut self) {}
+    fn bootloader(&mut self) {
+        self.bootloader_called = true;
+    }
 
     fn send_keys(&mut self, keys: &[KeyCode]) {
         self.reports.push(keys.iter().map(|&x| x.to_u8()).collect());
@@ -59,12 +73,70 @@ impl USBKeyOut for KeyOutCatcher {
         self.keys_registered.clear();
     }
 
-    fn send_keys_later(&mut self, _keys: &[KeyCode], _ms: u16) {}
-    fn do_send_later(&mut self) {}
+    fn send_keys_later(&mut self, keys: &[KeyCode], ms: u16) {
+        self.later.push((u32::from(ms), keys.to_vec()));
+    }
+    /// send everything queued (in order), without waiting
+    fn do_send_later(&mut self) {
+        for (_ms, keys) in self.later.drain(..) {
+            self.reports.push(keys.iter().map(|&x| x.to_u8()).collect());
+        }
+    }
 
     fn send_empty(&mut self) {
         self.reports.push(Vec::new());
     }
+    fn send_mouse(&mut self, buttons: u8, dx: i8, dy: i8, wheel: i8) {
+        self.mouse_reports.push((buttons, dx, dy, wheel));
+    }
+    fn send_consumer(&mut self, usage: u16) {
+        self.consumer_reports.push(usage);
+    }
+}
+impl Keyboard<'_, KeyOutCatcher> {
+    /// feed a batch of events and return the reports that were send -
+    /// either in one Keyboard::feed, or one per event if per_event is set.
+    ///
+    /// Unhandled events are dropped, as a firmware would.
+    /// If no key is down after a single feed, a trailing empty report
+    /// is send (unless the last one was empty already),
+    /// so a press and release within one batch does not leave the key stuck.
+    pub fn process_batch(&mut self, events: &[InputEvent], per_event: bool) -> Vec<Vec<KeyCode>> {
+        if per_event {
+            for event in events {
+                if self.feed(&[*event]).is_err() {
+                    self.clear_unhandled();
+                }
+            }
+        } else {
+            if self.feed(events).is_err() {
+                self.clear_unhandled();
+            }
+            let mut any_down = false;
+            for (e, _) in self.events.iter() {
+                if let Event::KeyPress(_) = e {
+                    any_down = true;
+                }
+            }
+            let last_empty = match self.output.reports.last() {
+                Some(report) => report.is_empty(),
+                None => true,
+            };
+            if !any_down && !last_empty {
+                self.output.send_empty();
+            }
+        }
+        self.output
+            .reports
+            .drain(..)
+            .map(|report| {
+                report
+                    .into_iter()
+                    .filter_map(|k| KeyCode::try_from(k).ok())
+                    .collect()
+            })
+            .collect()
+    }
 }
 #[cfg(test)]
 pub fn check_output(keyboard: &Keyboard<KeyOutCatcher>, should: &[&[KeyCode]]) {
@@ -213,3 +285,28 @@ impl Checks for Keyboard<'_, KeyOutCatcher> {
         self.output.clear();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::USBKeyboard;
+    use crate::key_codes::KeyCode;
+    use crate::test_helpers::KeyOutCatcher;
+    use crate::{InputEvent, Keyboard};
+    use no_std_compat::prelude::v1::*;
+
+    #[test]
+    fn test_process_batch() {
+        let a = KeyCode::A.to_u32();
+        let mut keyboard = Keyboard::new(KeyOutCatcher::new());
+        keyboard.add_handler(Box::new(USBKeyboard::new()));
+        let batch = [InputEvent::Press(a, 0), InputEvent::Release(a, 10)];
+        let reports = keyboard.process_batch(&batch, true);
+        assert!(reports == vec![vec![KeyCode::A], vec![]]);
+        assert!(keyboard.output.reports.is_empty());
+        //all in one handle_keys - the release still ends in an empty report
+        let reports = keyboard.process_batch(&batch, false);
+        assert!(reports == vec![vec![KeyCode::A], vec![]]);
+        assert!(keyboard.output.reports.is_empty());
+        assert!(keyboard.events.is_empty());
+    }
+}